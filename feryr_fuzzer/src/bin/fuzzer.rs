@@ -1,7 +1,8 @@
 use csv;
 use defs::*;
 use feryr::*;
-use feryr_prog::corpus_handle::prog::Prog;
+use feryr_prog::corpus_handle::{executor::ExecutorError, sequence::Sequence, RngType};
+use rand::SeedableRng;
 use std::{
     cmp::min,
     env, fs,
@@ -24,6 +25,13 @@ fn main() -> Result<(), failure::Error> {
     })
     .expect("Error setting Ctrl-C handler");
 
+    // `fuzzer seed ...` and `fuzzer export ...` work on saved data and exit
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("seed") => return seed::run(&args[1..]),
+        Some("export") => return export::run(&args[1..]),
+        _ => {}
+    }
     parse_args();
 
     println!(
@@ -42,7 +50,6 @@ fn main() -> Result<(), failure::Error> {
     let input_type: String;
     let input_args: String;
     let output_path: String;
-    let args: Vec<String> = env::args().collect();
     match args.len() {
         11 => {
             ros_dir_path = args[2].to_string();
@@ -112,10 +119,8 @@ fn main() -> Result<(), failure::Error> {
 
 // fuzzing loop start here
 pub fn fuzz_loop(fuzz_manager: Arc<RwLock<FuzzManager>>) {
-    // set generation rules
-    const GENERATE_PERIOD: u32 = 50;
-
     let mut idx = 0;
+    let mut rng = RngType::from_entropy();
     // start fuzzing loop
     while RUNNING.load(Ordering::SeqCst) {
         if idx == 30 {
@@ -127,11 +132,11 @@ pub fn fuzz_loop(fuzz_manager: Arc<RwLock<FuzzManager>>) {
         match fuzz_manager.write() {
             Ok(mut handle) => {
                 // generate input for node
-                fuzzer_info!("generating prog ");
-                let current_prog = match Prog::get_call(&handle.ros_launch, GENERATE_PERIOD) {
-                    Ok(prog) => prog,
+                fuzzer_info!("generating sequence ");
+                let mut current_seq = match Sequence::get_seq(&handle.ros_launch, &mut rng) {
+                    Ok(seq) => seq,
                     Err(e) => {
-                        fuzzer_info!("failed to generate prog: {}", e);
+                        fuzzer_info!("failed to generate sequence: {}", e);
                         handle.reboot().unwrap();
                         continue;
                     }
@@ -143,9 +148,13 @@ pub fn fuzz_loop(fuzz_manager: Arc<RwLock<FuzzManager>>) {
                 // execute seeds
                 let work_dir = &mut handle.workdir.clone();
                 // let pid = &mut handle.fuzzing_inst.id();
-                handle.ros_launch.add_prog(current_prog.clone());
                 handle.ros_launch.pid = handle.fuzzing_inst.id().clone();
-                if let Err(e) = current_prog.exec_input_prog(work_dir, &mut handle.ros_launch) {
+                if let Err(e) = current_seq.exec(&mut handle.ros_launch, work_dir) {
+                    // the backend failed, not the target, it restarts on the next call
+                    if e.downcast_ref::<ExecutorError>().is_some() {
+                        fuzzer_info!("{}", e);
+                        continue;
+                    }
                     fuzzer_info!("getting crash: {}", e);
                     if !format!("{}", e).contains("ros2 log error")
                         && !format!("{}", e).contains("ros2 waiting for")
@@ -163,6 +172,16 @@ pub fn fuzz_loop(fuzz_manager: Arc<RwLock<FuzzManager>>) {
                 } else {
                     // normal exec, handle coverage
                     let _file_name = format!("{}/{}", work_dir, "corpus.db");
+                    let boot_fuzz = handle.ros_launch.boot_fuzz;
+                    if boot_fuzz > 0 && handle.total_exec as u64 % boot_fuzz == 0 {
+                        // next boot configuration
+                        handle.reboot().unwrap();
+                        handle.ros_launch.clean_prog();
+                    } else if let Err(e) = handle.restore_state() {
+                        fuzzer_info!("failed to restore node state: {}", e);
+                        handle.reboot().unwrap();
+                        handle.ros_launch.clean_prog();
+                    }
                 }
             }
             Err(_e) => {
@@ -172,6 +191,10 @@ pub fn fuzz_loop(fuzz_manager: Arc<RwLock<FuzzManager>>) {
         }
     }
 
+    if fuzz_manager.read().unwrap().config.export_corpus {
+        fuzz_manager.read().unwrap().export_corpus();
+    }
+
     // create a directory name csv in work_dir
     let work_dir = fuzz_manager.read().unwrap().workdir.clone() + &"/csv".to_owned();
     fs::create_dir_all(work_dir.clone()).unwrap();
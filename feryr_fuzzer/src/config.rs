@@ -0,0 +1,160 @@
+use std::env;
+
+// fuzzing options that are not part of the positional command line
+pub static EXECUTOR_VAR: &str = "FERYR_EXECUTOR";
+pub static RAW_CDR_VAR: &str = "FERYR_RAW_CDR";
+pub static CONCURRENT_VAR: &str = "FERYR_CONCURRENT";
+pub static IMPERSONATE_VAR: &str = "FERYR_IMPERSONATE";
+pub static READONLY_PARAM_VAR: &str = "FERYR_READONLY_PARAM";
+pub static PARAM_CONFUSION_VAR: &str = "FERYR_PARAM_CONFUSION";
+pub static RESTORE_VAR: &str = "FERYR_RESTORE";
+pub static RESTORE_LIFECYCLE_VAR: &str = "FERYR_RESTORE_LIFECYCLE";
+pub static BOOT_FUZZ_VAR: &str = "FERYR_BOOT_FUZZ";
+pub static SIM_CLOCK_VAR: &str = "FERYR_SIM_CLOCK";
+pub static SEMANTIC_VAR: &str = "FERYR_SEMANTIC";
+pub static TF_FUZZ_VAR: &str = "FERYR_TF_FUZZ";
+pub static EXPORT_BAG_VAR: &str = "FERYR_EXPORT_BAG";
+pub static EXPORT_CORPUS_VAR: &str = "FERYR_EXPORT_CORPUS";
+
+// every variable with its values and default, for the usage help
+pub static ENV_HELP: &[(&str, &str)] = &[
+    (
+        EXECUTOR_VAR,
+        "injection backend: cli, helper or mock (default cli)",
+    ),
+    (
+        RAW_CDR_VAR,
+        "set: mix malformed raw cdr into topic inputs, needs helper (default off)",
+    ),
+    (
+        CONCURRENT_VAR,
+        "set: race calls of a sequence against each other (default off)",
+    ),
+    (
+        IMPERSONATE_VAR,
+        "set: publish on topics that already have a publisher (default off)",
+    ),
+    (
+        READONLY_PARAM_VAR,
+        "set: also fuzz read-only parameters (default off)",
+    ),
+    (
+        PARAM_CONFUSION_VAR,
+        "set: send parameters with the wrong type (default off)",
+    ),
+    (
+        RESTORE_VAR,
+        "restore node state: never, always or every N inputs (default never)",
+    ),
+    (
+        RESTORE_LIFECYCLE_VAR,
+        "set: cycle active managed nodes around a restore (default off)",
+    ),
+    (
+        BOOT_FUZZ_VAR,
+        "reboot with a generated launch config every N inputs, set: 1 (default off)",
+    ),
+    (
+        SIM_CLOCK_VAR,
+        "set: launch with use_sim_time and drive /clock, needs helper (default off)",
+    ),
+    (
+        SEMANTIC_VAR,
+        "set: learn the tf frames after boot for the generators (default off)",
+    ),
+    (
+        TF_FUZZ_VAR,
+        "set: favour /tf inputs and learn the frame tree (default off)",
+    ),
+    (
+        EXPORT_BAG_VAR,
+        "also save crash reproducers as bags: mcap or sqlite3 (default off)",
+    ),
+    (
+        EXPORT_CORPUS_VAR,
+        "set: export the sequence corpus as bags at exit (default off)",
+    ),
+];
+
+#[derive(Debug, Clone)]
+pub struct FuzzConfig {
+    // injection backend: "cli", "helper" or "mock"
+    pub executor: String,
+    // mix malformed raw cdr into topic inputs, needs the helper executor
+    pub raw_cdr: bool,
+    // race calls against each other to provoke deadlocks and data races
+    pub concurrent: bool,
+    // publish conflicting messages on topics that have a publisher in the launch
+    pub impersonate: bool,
+    // also fuzz parameters that are read-only
+    pub readonly_param: bool,
+    // set parameters with the wrong type, as arrays instead of scalars or as empty arrays
+    pub param_confusion: bool,
+    // restore node state after "always", every N inputs or "never"
+    pub restore: String,
+    // deactivate and activate managed nodes around a restore
+    pub restore_lifecycle: bool,
+    // boot with generated launch arguments and params files, rebooting every N inputs, 0 is off
+    pub boot_fuzz: u64,
+    // launch with use_sim_time and publish /clock from the fuzzer, needs the helper executor
+    pub sim_clock: bool,
+    // learn the frames of the target after boot for the semantic generators
+    pub semantic: bool,
+    // favour /tf and /tf_static inputs and learn the frame tree over a longer window
+    pub tf_fuzz: bool,
+    // rosbag2 storage ("mcap" or "sqlite3") crash reproducers are also saved in, empty is off
+    pub export_bag: String,
+    // export the whole sequence corpus as bags when the run ends
+    pub export_corpus: bool,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        FuzzConfig {
+            executor: "cli".to_string(),
+            raw_cdr: false,
+            concurrent: false,
+            impersonate: false,
+            readonly_param: false,
+            param_confusion: false,
+            restore: "never".to_string(),
+            restore_lifecycle: false,
+            boot_fuzz: 0,
+            sim_clock: false,
+            semantic: false,
+            tf_fuzz: false,
+            export_bag: String::new(),
+            export_corpus: false,
+        }
+    }
+}
+
+impl FuzzConfig {
+    pub fn from_env() -> Self {
+        let mut config = FuzzConfig::default();
+        if let Ok(executor) = env::var(EXECUTOR_VAR) {
+            config.executor = executor;
+        }
+        config.raw_cdr = env::var(RAW_CDR_VAR).is_ok();
+        config.concurrent = env::var(CONCURRENT_VAR).is_ok();
+        config.impersonate = env::var(IMPERSONATE_VAR).is_ok();
+        config.readonly_param = env::var(READONLY_PARAM_VAR).is_ok();
+        config.param_confusion = env::var(PARAM_CONFUSION_VAR).is_ok();
+        if let Ok(restore) = env::var(RESTORE_VAR) {
+            config.restore = restore;
+        }
+        config.restore_lifecycle = env::var(RESTORE_LIFECYCLE_VAR).is_ok();
+        // a new configuration for every input when set without a number
+        if let Ok(boot_fuzz) = env::var(BOOT_FUZZ_VAR) {
+            config.boot_fuzz = boot_fuzz.parse().unwrap_or(1);
+        }
+        config.sim_clock = env::var(SIM_CLOCK_VAR).is_ok();
+        config.semantic = env::var(SEMANTIC_VAR).is_ok();
+        config.tf_fuzz = env::var(TF_FUZZ_VAR).is_ok();
+        if let Ok(export_bag) = env::var(EXPORT_BAG_VAR) {
+            config.export_bag = export_bag;
+        }
+        config.export_corpus = env::var(EXPORT_CORPUS_VAR).is_ok();
+        config
+    }
+}
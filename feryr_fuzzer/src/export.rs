@@ -0,0 +1,96 @@
+//! Sequences as rosbag2 bags, to replay a bug with standard tooling. A bag directory holds the
+//! topic inputs as cdr messages at the time they were sent and `replay.sh`, which plays the bag
+//! and runs the service, action and parameter calls next to it. Crashes are exported as they
+//! are saved when FERYR_EXPORT_BAG names a storage, `fuzzer export` does it for saved sequences.
+use super::load_target;
+use clap::{App, Arg};
+use feryr_prog::corpus_handle::{
+    bag::{write_bag, BagStorage},
+    sequence::Sequence,
+    target::Target,
+    text::read_seq,
+};
+use std::{fs, os::unix::fs::PermissionsExt, path::Path, time::SystemTime};
+use util::fuzzer_info;
+
+pub const REPLAY_SCRIPT: &str = "replay.sh";
+
+pub fn run(args: &[String]) -> Result<(), failure::Error> {
+    let matches = App::new("export")
+        .about("Export saved sequences, e.g. crash/*/sequence-0, as rosbag2 bags")
+        .arg(
+            Arg::with_name("sequence")
+                .required(true)
+                .multiple(true)
+                .value_name("SEQUENCE"),
+        )
+        .arg(
+            Arg::with_name("input type")
+                .short("i")
+                .long("in")
+                .required(true)
+                .takes_value(true)
+                .value_name("INPUT"),
+        )
+        .arg(
+            Arg::with_name("output data directory")
+                .short("o")
+                .long("out")
+                .required(true)
+                .takes_value(true)
+                .value_name("OUT"),
+        )
+        .arg(
+            Arg::with_name("storage")
+                .short("s")
+                .long("storage")
+                .takes_value(true)
+                .possible_values(&["mcap", "sqlite3"])
+                .default_value("mcap"),
+        )
+        .get_matches_from(args);
+
+    let target = load_target(
+        matches.value_of("input type").unwrap(),
+        matches.value_of("output data directory").unwrap(),
+    )?;
+    let storage = BagStorage::from_name(matches.value_of("storage").unwrap()).unwrap();
+    for path in matches.values_of("sequence").unwrap() {
+        let path = Path::new(path);
+        let seq = read_seq(path)?;
+        // crash/<error>/sequence-0 goes to crash/<error>/bag-0
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("");
+        let name = match stem.strip_prefix("sequence") {
+            Some(idx) => format!("bag{}", idx),
+            None => format!("{}-bag", stem),
+        };
+        let dir = path.with_file_name(name);
+        export_seq(&seq, &target, &dir, storage)?;
+        fuzzer_info!("exported {} to {}", path.display(), dir.display());
+    }
+    Ok(())
+}
+
+// the bag and its replay script in dir, stamped with the wall clock of the last execution
+pub fn export_seq(
+    seq: &Sequence,
+    target: &Target,
+    dir: &Path,
+    storage: BagStorage,
+) -> Result<(), failure::Error> {
+    let start_ns = match seq.outcomes.iter().map(|outcome| outcome.start_time).min() {
+        Some(start_time) => start_time as u64,
+        None => SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos() as u64,
+    };
+    let export = seq.to_bag(target, start_ns);
+    write_bag(dir, storage, &export.topics, &export.messages)?;
+    let script = dir.join(REPLAY_SCRIPT);
+    fs::write(&script, export.script)?;
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
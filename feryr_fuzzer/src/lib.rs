@@ -1,11 +1,24 @@
+pub mod config;
 pub mod defs;
+pub mod export;
+pub mod seed;
 use chrono::{DateTime, Utc};
 use clap::{App, Arg};
+use config::FuzzConfig;
+use defs::NODE_INFO_PATH;
 use feryr_prog::{
     corpus_handle::{
+        bag::BagStorage,
+        boot::{BootConfig, BootSchema},
+        executor::executor_from_str,
         interface::Node,
+        restore::{RestorePolicy, Snapshot},
+        semantic,
         sys::{dump_to_file, get_random_string},
         target::Target,
+        text::PROG_EXT,
+        tf::TF_TOPICS,
+        xml,
     },
     cover_handle::cover::*,
 };
@@ -28,6 +41,9 @@ use std::{
 };
 use util::fuzzer_info;
 
+// boots of the default configuration before a reboot gives up
+const MAX_BOOT_ATTEMPTS: usize = 5;
+
 #[derive(Debug)]
 pub struct FuzzManager {
     pub uptime: DateTime<Utc>,
@@ -41,6 +57,7 @@ pub struct FuzzManager {
     pub workdir: String,
     pub ros_launch: Target,
     pub fuzzing_inst: Child,
+    pub config: FuzzConfig,
 }
 
 impl FuzzManager {
@@ -51,6 +68,25 @@ impl FuzzManager {
         input_type: String,
         input_args: String,
     ) -> Self {
+        let config = FuzzConfig::from_env();
+        let mut ros_launch = Target::new(ros_dir_path, output_path.clone());
+        ros_launch.executor = executor_from_str(&config.executor);
+        ros_launch.raw_cdr = config.raw_cdr;
+        ros_launch.concurrent = config.concurrent;
+        ros_launch.impersonate = config.impersonate;
+        ros_launch.readonly_param = config.readonly_param;
+        ros_launch.param_confusion = config.param_confusion;
+        ros_launch.restore = RestorePolicy::parse(&config.restore);
+        ros_launch.restore_lifecycle = config.restore_lifecycle;
+        ros_launch.boot_fuzz = config.boot_fuzz;
+        ros_launch.tf_fuzz = config.tf_fuzz;
+        // sequences imported with `fuzzer seed import`
+        ros_launch.seq_corpus = seed::load_seeds(&output_path);
+        // nodes on simulated time stand still unless the backend publishes /clock
+        ros_launch.sim_clock = config.sim_clock && ros_launch.executor.support_clock();
+        if config.sim_clock && !ros_launch.sim_clock {
+            fuzzer_info!("{} cannot drive /clock, keeping wall time", config.executor);
+        }
         FuzzManager {
             // presist data
             uptime: chrono::offset::Utc::now(),
@@ -63,12 +99,13 @@ impl FuzzManager {
             input_type: input_type,
             input_args: input_args,
             workdir: output_path.clone(),
-            ros_launch: Target::new(ros_dir_path, output_path),
+            ros_launch,
             fuzzing_inst: Command::new("ls")
                 .stdout(Stdio::null())
                 .stderr(Stdio::null())
                 .spawn()
                 .unwrap(),
+            config,
             // cpu_num: 1,
         }
     }
@@ -116,6 +153,41 @@ impl FuzzManager {
         }
         self.ros_launch.current_corpus.clear();
 
+        // the whole sequence with per call outcomes, and a script to replay it by hand
+        let seq = &self.ros_launch.current_seq;
+        let seq_file = File::create(format!(
+            "{}/{}/{}/{}-{}",
+            self.workdir, "crash", err_des, "sequence", crash_idx
+        ))
+        .unwrap();
+        serde_json::to_writer_pretty(seq_file, seq).unwrap();
+        fs::write(
+            format!(
+                "{}/{}/{}/{}-{}.{}",
+                self.workdir, "crash", err_des, "sequence", crash_idx, PROG_EXT
+            ),
+            seq.to_text(),
+        )
+        .unwrap();
+        fs::write(
+            format!(
+                "{}/{}/{}/{}-{}.sh",
+                self.workdir, "crash", err_des, "sequence", crash_idx
+            ),
+            seq.serialization(),
+        )
+        .unwrap();
+        if let Some(storage) = BagStorage::from_name(&self.config.export_bag) {
+            let bag_dir = format!(
+                "{}/{}/{}/{}-{}",
+                self.workdir, "crash", err_des, "bag", crash_idx
+            );
+            if let Err(e) = export::export_seq(seq, &self.ros_launch, Path::new(&bag_dir), storage)
+            {
+                fuzzer_info!("failed to export {}: {}", bag_dir, e);
+            }
+        }
+
         // copy work_dir/shm, workd_dir/instance_err and workd_dir/instance_out to crash/random_string
         let mut options = CopyOptions::new();
         options.overwrite = true;
@@ -171,6 +243,12 @@ impl FuzzManager {
             &(self.workdir.to_owned() + &"/shm".to_string()),
             &self.input_type,
         );
+        if self.ros_launch.boot_fuzz > 0 {
+            self.ros_launch.boot_schema = BootSchema::load(&Target::get_profile_dir(
+                &(self.workdir.to_owned() + &"/shm".to_string()),
+                &self.input_type,
+            ))?;
+        }
 
         //     let get_interface_info = Command::new("ros2")
         //         .env("SHM_PATH", self.workdir.to_owned() + &"/shm".to_owned())
@@ -197,17 +275,7 @@ impl FuzzManager {
         self.ros_launch.node_name = node_list.into_iter().map(|s| s.to_string()).collect();
         fuzzer_info!("get all node list {:?}", &self.ros_launch.node_name,);
 
-        // read from dir: sys/node.json
-        let file = File::open("./sys/node.json").unwrap();
-        let reader = BufReader::new(file);
-
-        let mut all_node: Vec<Node> = Vec::new();
-
-        for line in reader.lines() {
-            let node: Node = serde_json::from_str(&line.unwrap()).unwrap();
-            all_node.push(node);
-        }
-
+        let all_node = read_nodes(defs::NODE_INFO_PATH);
         let node_map: HashMap<String, Node> = all_node
             .into_iter()
             .map(|node| (node.node_name.clone(), node))
@@ -291,6 +359,18 @@ impl FuzzManager {
     pub fn boot(&mut self) -> Result<(), failure::Error> {
         let std_out = File::create(self.workdir.to_owned() + &"/instance_out").unwrap();
         let std_err = File::create(self.workdir.to_owned() + &"/instance_err").unwrap();
+        // generated launch arguments, and the params file they point to
+        let params_path = self.workdir.to_owned() + "/boot_params.yaml";
+        let mut boot_args = match self.ros_launch.boot_config.as_ref() {
+            Some(boot_config) => {
+                fs::write(&params_path, boot_config.get_params_file())?;
+                boot_config.get_launch_args(&self.ros_launch.boot_schema, &params_path)
+            }
+            None => Vec::new(),
+        };
+        if self.ros_launch.sim_clock {
+            boot_args.push("use_sim_time:=true".to_string());
+        }
 
         match self.input_args.is_empty() {
             true => {
@@ -305,6 +385,7 @@ impl FuzzManager {
                             &self.input_type,
                             &self.ros_launch.launch_file,
                         ])
+                        .args(&boot_args)
                         .stdout(Stdio::from(std_out))
                         .stderr(Stdio::from(std_err))
                         .pre_exec(|| {
@@ -331,6 +412,7 @@ impl FuzzManager {
                     .arg(&self.input_type)
                     .arg(&self.ros_launch.launch_file)
                     .args(&cmd_args)
+                    .args(&boot_args)
                     .stdout(Stdio::from(std_out))
                     .stderr(Stdio::from(std_err))
                     .spawn()
@@ -341,16 +423,42 @@ impl FuzzManager {
             }
         }
 
+        // the helper publishes /clock from its start on, before that timers of nodes on
+        // simulated time never fire
+        if self.ros_launch.sim_clock {
+            self.ros_launch
+                .executor
+                .start(&(self.workdir.to_owned() + "/shm"))?;
+        }
+
         // check boot
         fuzzer_info!("waiting ros app to boot: {}", self.fuzzing_inst.id());
 
+        // only a generated configuration is blamed for a node that exits, the default launch
+        // may stop helper processes on purpose
+        let boot_fuzzing = self.ros_launch.boot_config.is_some();
         let start_time = Instant::now();
         while !self.check_boot() {
+            if boot_fuzzing && self.check_boot_crash() {
+                fuzzer_info!("ros app crashed at boot");
+                return Err(failure::err_msg("ros app boot crash"));
+            }
             if start_time.elapsed().as_secs() > 10 {
                 fuzzer_info!("ros app boot timeout");
                 return Err(failure::err_msg("ros app boot timeout"));
             }
         }
+        // a node may die while the others already answer the probe
+        if boot_fuzzing && self.check_boot_crash() {
+            fuzzer_info!("ros app crashed at boot");
+            return Err(failure::err_msg("ros app boot crash"));
+        }
+        // injection backend lives as long as this boot
+        if !self.ros_launch.sim_clock {
+            self.ros_launch
+                .executor
+                .start(&(self.workdir.to_owned() + "/shm"))?;
+        }
         Ok(())
     }
 
@@ -378,6 +486,46 @@ impl FuzzManager {
         false
     }
 
+    // launch reports every node that exits, whatever the reason
+    pub fn check_boot_crash(&mut self) -> bool {
+        ["instance_out", "instance_err"].iter().any(|log| {
+            fs::read_to_string(format!("{}/{}", self.workdir, log))
+                .is_ok_and(|log| log.contains("process has died"))
+        })
+    }
+
+    // keep the boot configuration that crashed or never passed the probe, with the launch logs
+    pub fn save_boot_finding(&mut self, reason: &str) -> Result<(), failure::Error> {
+        let boot_config = match self.ros_launch.boot_config.as_ref() {
+            Some(boot_config) => boot_config,
+            None => return Ok(()),
+        };
+        let boot_dir = format!("{}/{}", self.workdir, "boot");
+        create_dir_all(&boot_dir)?;
+        let boot_idx = fs::read_dir(&boot_dir)?.count();
+        let finding_path = format!("{}/{}-{}", boot_dir, reason.replace(' ', "_"), boot_idx);
+        create_dir_all(&finding_path)?;
+        fuzzer_info!("saving boot finding to {}", finding_path);
+
+        serde_json::to_writer_pretty(
+            File::create(format!("{}/{}", finding_path, "config.json"))?,
+            boot_config,
+        )?;
+        fs::write(
+            format!("{}/{}", finding_path, "params.yaml"),
+            boot_config.get_params_file(),
+        )?;
+        let args = boot_config.get_launch_args(&self.ros_launch.boot_schema, "params.yaml");
+        fs::write(format!("{}/{}", finding_path, "args"), args.join(" "))?;
+        for log in ["instance_out", "instance_err"] {
+            fs::copy(
+                format!("{}/{}", self.workdir, log),
+                format!("{}/{}", finding_path, log),
+            )?;
+        }
+        Ok(())
+    }
+
     pub fn get_child_process(&mut self, pid: &Pid) -> Vec<String> {
         let pid = pid.to_string();
         let output = Command::new("pstree")
@@ -419,6 +567,7 @@ impl FuzzManager {
 
     // kill a process with given node name
     pub fn kill_ros_app(&mut self) -> Result<(), failure::Error> {
+        self.ros_launch.executor.stop();
         // get pid
         let pids = self.get_child_process(&Pid::from_raw(self.fuzzing_inst.id() as i32));
 
@@ -448,6 +597,23 @@ impl FuzzManager {
         Ok(())
     }
 
+    // the sequence corpus as bag directories under workdir/corpus, at the end of a run
+    pub fn export_corpus(&self) {
+        let storage = BagStorage::from_name(&self.config.export_bag).unwrap_or(BagStorage::Mcap);
+        for (idx, seq) in self.ros_launch.seq_corpus.iter().enumerate() {
+            let bag_dir = format!("{}/{}/{}-{}", self.workdir, "corpus", "bag", idx);
+            if let Err(e) = export::export_seq(seq, &self.ros_launch, Path::new(&bag_dir), storage)
+            {
+                fuzzer_info!("failed to export {}: {}", bag_dir, e);
+            }
+        }
+        fuzzer_info!(
+            "exported {} sequences to {}/corpus",
+            self.ros_launch.seq_corpus.len(),
+            self.workdir
+        );
+    }
+
     pub fn repro(&mut self) {
         // TODO!
         // match handle.check_timeout() {
@@ -465,17 +631,120 @@ impl FuzzManager {
     }
 
     pub fn reboot(&mut self) -> Result<(), failure::Error> {
-        self.kill_ros_app().unwrap();
+        self.kill_ros_app()?;
         // clean all file
-        self.clean_shm_file().unwrap();
-        match self.boot() {
-            Ok(_) => {}
-            Err(_e) => {
-                dbg!("boot failed");
-                self.reboot().unwrap();
+        self.clean_shm_file()?;
+        if self.ros_launch.boot_fuzz > 0 {
+            let target = &mut self.ros_launch;
+            match target.boot_config.as_mut() {
+                Some(boot_config) if target.rng.gen_ratio(1, 2) => boot_config.mutate(
+                    &target.boot_schema,
+                    &target.nodes,
+                    target.param_confusion,
+                    &mut target.rng,
+                ),
+                _ => {
+                    target.boot_config = Some(BootConfig::generate(
+                        &target.boot_schema,
+                        &target.nodes,
+                        target.param_confusion,
+                        &mut target.rng,
+                    ))
+                }
+            }
+            // each boot starts from its own parameters
+            target.snapshot = None;
+        }
+        if let Err(e) = self.boot() {
+            fuzzer_info!("boot failed: {}", e);
+            // the default configuration is known to boot, a failing generated one is a finding
+            if self.ros_launch.boot_config.is_some() {
+                let reason = match format!("{}", e).contains("crash") {
+                    true => "crash",
+                    false => "probe",
+                };
+                self.save_boot_finding(reason)?;
+                self.ros_launch.boot_config = None;
+            }
+            return self.boot_default();
+        }
+        self.after_boot()
+    }
+
+    // boot fuzzing goes on with a new configuration at the next reboot
+    fn boot_default(&mut self) -> Result<(), failure::Error> {
+        for attempt in 1..=MAX_BOOT_ATTEMPTS {
+            self.kill_ros_app()?;
+            self.clean_shm_file()?;
+            match self.boot() {
+                Ok(_) => return self.after_boot(),
+                Err(e) => fuzzer_info!("boot attempt {} failed: {}", attempt, e),
             }
         }
+        Err(failure::format_err!(
+            "ros app failed to boot {} times",
+            MAX_BOOT_ATTEMPTS
+        ))
+    }
+
+    fn after_boot(&mut self) -> Result<(), failure::Error> {
         self.ros_launch.pid = self.fuzzing_inst.id();
+        // frames and clock of the target for the semantic generators, each probe takes the
+        // whole echo window so only the enabled modes pay for it
+        if self.config.semantic || self.config.tf_fuzz {
+            for topic in TF_TOPICS {
+                semantic::observe_tf(&self.echo_window(topic));
+            }
+        }
+        if self.ros_launch.sim_clock {
+            semantic::observe_clock(&self.echo_window("/clock"));
+        }
+        // robot descriptions the XML mutator starts from, read once like the snapshot
+        if self.ros_launch.xml_seeds.is_empty() {
+            self.ros_launch.xml_seeds =
+                xml::read_seeds(&self.ros_launch.nodes, &(self.workdir.to_owned() + "/shm"));
+        }
+        // every boot starts from the same parameters, one snapshot serves them all
+        if self.ros_launch.restore != RestorePolicy::Never && self.ros_launch.snapshot.is_none() {
+            let node_names: Vec<String> = self
+                .ros_launch
+                .nodes
+                .iter()
+                .map(|node| node.node_name.clone())
+                .collect();
+            self.ros_launch.snapshot = Some(Snapshot::take(
+                &node_names,
+                &(self.workdir.to_owned() + "/shm"),
+            )?);
+        }
+        Ok(())
+    }
+
+    // every message of `topic` published in two seconds, empty when nothing is published on it
+    pub fn echo_window(&self, topic: &str) -> String {
+        let mut args = vec!["2", "ros2", "topic", "echo", topic];
+        if topic == "/tf_static" {
+            args.extend(["--qos-durability", "transient_local"]);
+        }
+        Command::new("timeout")
+            .env("SHM_PATH", self.workdir.to_owned() + "/shm")
+            .args(&args)
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .unwrap_or_default()
+    }
+
+    // bring the nodes back to their state after boot when the restore policy says so
+    pub fn restore_state(&mut self) -> Result<(), failure::Error> {
+        if !self.ros_launch.restore.is_due(self.total_exec as u64) {
+            return Ok(());
+        }
+        if let Some(snapshot) = self.ros_launch.snapshot.as_ref() {
+            snapshot.restore(
+                &(self.workdir.to_owned() + "/shm"),
+                self.ros_launch.restore_lifecycle,
+            )?;
+        }
         Ok(())
     }
  
@@ -513,10 +782,43 @@ impl FuzzManager {
     }
 }
 
+// nodes recorded for the target, one json object per line
+pub fn read_nodes(path: &str) -> Vec<Node> {
+    let file = File::open(path).unwrap();
+    let reader = BufReader::new(file);
+
+    let mut all_node: Vec<Node> = Vec::new();
+    for line in reader.lines() {
+        let node: Node = serde_json::from_str(&line.unwrap()).unwrap();
+        all_node.push(node);
+    }
+    all_node
+}
+
+// interfaces and nodes of a target as gen_targets finds them, without booting it. The profile
+// is looked up next to a scratch shm directory under output_path.
+pub fn load_target(input_type: &str, output_path: &str) -> Result<Target, failure::Error> {
+    let work_dir = Path::new(output_path).join("offline");
+    let shm_dir = work_dir.join("shm");
+    create_dir_all(&shm_dir)?;
+    let mut target = Target::new(String::new(), output_path.to_string());
+    target.get_interfaces(&shm_dir.to_string_lossy(), &input_type.to_string());
+    target.nodes = read_nodes(NODE_INFO_PATH);
+    fs::remove_dir_all(&work_dir)?;
+    Ok(target)
+}
+
 pub fn usage_help() {
     println!(
         "Usage: ./fuzzer -c config_file_path -r ros_dir -i input_type -a input_args -o output_dir"
     );
+    println!("       ./fuzzer seed import bag_path -i input_type -o output_dir");
+    println!("       ./fuzzer export sequence_path... -i input_type -o output_dir [-s storage]");
+    println!();
+    println!("Environment:");
+    for (var, help) in config::ENV_HELP {
+        println!("  {:<24}{}", var, help);
+    }
 }
 
 pub fn quit_fuzzer() {
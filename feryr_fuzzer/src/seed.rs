@@ -0,0 +1,109 @@
+//! `fuzzer seed import <bag> -i <input> -o <out>` turns the messages of a rosbag2 bag into seed
+//! sequences under `<out>seeds/`. Every fuzzing run with the same output directory starts its
+//! corpus from them. Importing needs the profile of the target under sys/ but no running ros.
+//! Seeds are written in the text format, hand-written `.prog` files can be dropped next to them.
+use super::load_target;
+use clap::{App, Arg, SubCommand};
+use feryr_prog::corpus_handle::{
+    bag::read_bag,
+    sequence::Sequence,
+    text::{read_seq, PROG_EXT},
+};
+use std::{
+    fs::{self, create_dir_all},
+    path::Path,
+};
+use util::fuzzer_info;
+
+pub const SEED_DIR: &str = "seeds";
+// spread evenly over a long recording
+const MAX_SEEDS: usize = 4096;
+
+pub fn run(args: &[String]) -> Result<(), failure::Error> {
+    let matches = App::new("seed")
+        .about("Manage the seed corpus")
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Import the messages of a rosbag2 bag (.db3 or .mcap)")
+                .arg(Arg::with_name("bag").required(true).value_name("BAG"))
+                .arg(
+                    Arg::with_name("input type")
+                        .short("i")
+                        .long("in")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("INPUT"),
+                )
+                .arg(
+                    Arg::with_name("output data directory")
+                        .short("o")
+                        .long("out")
+                        .required(true)
+                        .takes_value(true)
+                        .value_name("OUT"),
+                ),
+        )
+        .get_matches_from(args);
+
+    match matches.subcommand() {
+        ("import", Some(import)) => import_bag(
+            import.value_of("bag").unwrap(),
+            import.value_of("input type").unwrap(),
+            import.value_of("output data directory").unwrap(),
+        ),
+        _ => {
+            println!("{}", matches.usage());
+            Ok(())
+        }
+    }
+}
+
+pub fn import_bag(bag: &str, input_type: &str, output_path: &str) -> Result<(), failure::Error> {
+    let target = load_target(input_type, output_path)?;
+    let messages = read_bag(bag)?;
+    let (mut seqs, stats) = Sequence::from_bag(&target, &messages);
+    fuzzer_info!(
+        "{} messages in {}, {} on subscribed topics, {} not decodable",
+        stats.messages,
+        bag,
+        stats.subscribed,
+        stats.undecodable
+    );
+    if seqs.len() > MAX_SEEDS {
+        let step = seqs.len() as f64 / MAX_SEEDS as f64;
+        seqs = (0..MAX_SEEDS)
+            .map(|idx| seqs[(idx as f64 * step) as usize].clone())
+            .collect();
+    }
+
+    let seed_dir = output_path.to_owned() + SEED_DIR;
+    create_dir_all(&seed_dir)?;
+    let name = Path::new(bag.trim_end_matches('/'))
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or("bag");
+    for (idx, seq) in seqs.iter().enumerate() {
+        fs::write(
+            format!("{}/{}-{}.{}", seed_dir, name, idx, PROG_EXT),
+            seq.to_text(),
+        )?;
+    }
+    fuzzer_info!("saved {} seed sequences to {}", seqs.len(), seed_dir);
+    Ok(())
+}
+
+// seed sequences of earlier imports, text or json, an unreadable one is skipped
+pub fn load_seeds(output_path: &str) -> Vec<Sequence> {
+    let paths = match fs::read_dir(output_path.to_owned() + SEED_DIR) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+    let mut seqs = Vec::new();
+    for path in paths.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        match read_seq(&path) {
+            Ok(seq) => seqs.push(seq),
+            Err(e) => fuzzer_info!("skipping seed {}: {}", path.display(), e),
+        }
+    }
+    seqs
+}
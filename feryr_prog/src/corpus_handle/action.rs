@@ -0,0 +1,249 @@
+//! Action protocol inputs. An action server is driven through its goal, cancel and result
+//! services, and a sequence interleaves these requests to reach lifecycle states that a single
+//! `send_goal` never reaches (cancel while executing, preempt, result of an unknown goal).
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const GOAL_ID_LEN: usize = 16;
+pub type GoalId = [u8; GOAL_ID_LEN];
+
+pub const CANCEL_GOAL_TYPE: &str = "action_msgs/srv/CancelGoal";
+// goal status names of action_msgs/msg/GoalStatus, indexed by value
+pub const GOAL_STATUS: [&str; 7] = [
+    "UNKNOWN",
+    "ACCEPTED",
+    "EXECUTING",
+    "CANCELING",
+    "SUCCEEDED",
+    "CANCELED",
+    "ABORTED",
+];
+const ACCEPTED_LINE: &str = "Goal accepted with ID: ";
+const STATUS_LINE: &str = "Goal finished with status: ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ActionOp {
+    SendGoal,
+    // cancel target_id
+    Cancel,
+    // zero goal id and stamp, cancels every goal of the server
+    CancelAll,
+    // send a new goal while target_id is still running
+    Preempt,
+    GetResult,
+}
+const ACTION_OPS: [ActionOp; 5] = [
+    ActionOp::SendGoal,
+    ActionOp::Cancel,
+    ActionOp::CancelAll,
+    ActionOp::Preempt,
+    ActionOp::GetResult,
+];
+
+/// One request of the action protocol, the goal message itself is the payload of the call.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ActionCall {
+    pub op: ActionOp,
+    // id of a goal sent by SendGoal and Preempt, the cli always picks a random one
+    pub goal_id: GoalId,
+    // goal a Cancel, Preempt or GetResult refers to
+    pub target_id: GoalId,
+    // replace target_id by the last goal accepted earlier in the sequence
+    pub follow_last: bool,
+    // SendGoal waits for the result, otherwise returns once the goal is accepted
+    pub wait_result: bool,
+}
+
+impl Default for ActionCall {
+    fn default() -> Self {
+        ActionCall {
+            op: ActionOp::SendGoal,
+            goal_id: [0; GOAL_ID_LEN],
+            target_id: [0; GOAL_ID_LEN],
+            follow_last: true,
+            wait_result: true,
+        }
+    }
+}
+
+impl ActionCall {
+    pub fn generate(rng: &mut RngType) -> ActionCall {
+        ActionCall {
+            // keep most inputs sending goals, the other requests need one to act on
+            op: match rng.gen_range(0..2) {
+                0 => ActionOp::SendGoal,
+                _ => ACTION_OPS[rng.gen_range(0..ACTION_OPS.len())],
+            },
+            goal_id: rng.gen(),
+            target_id: gen_goal_id(rng),
+            follow_last: !rng.gen_ratio(1, 4),
+            wait_result: rng.gen(),
+        }
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..5) {
+            0 => self.op = ACTION_OPS[rng.gen_range(0..ACTION_OPS.len())],
+            1 => self.goal_id = gen_goal_id(rng),
+            2 => {
+                // a fixed target one bit away from the current one
+                self.follow_last = false;
+                let byte = rng.gen_range(0..GOAL_ID_LEN);
+                self.target_id[byte] ^= 1 << rng.gen_range(0..8);
+            }
+            3 => self.follow_last = !self.follow_last,
+            _ => self.wait_result = !self.wait_result,
+        }
+    }
+
+    // services behind an action are named <action>/_action/<service>
+    pub fn get_service_name(&self, action_name: &str) -> String {
+        let service = match self.op {
+            ActionOp::Cancel | ActionOp::CancelAll => "cancel_goal",
+            ActionOp::GetResult => "get_result",
+            ActionOp::SendGoal | ActionOp::Preempt => "send_goal",
+        };
+        format!("{}/_action/{}", action_name.trim_end_matches('/'), service)
+    }
+
+    // type of the service for the cli, None when the call goes through `ros2 action send_goal`
+    pub fn get_service_type(&self, action_type: &str) -> Option<String> {
+        match self.op {
+            ActionOp::Cancel | ActionOp::CancelAll => Some(CANCEL_GOAL_TYPE.to_string()),
+            ActionOp::GetResult => Some(format!("{}_GetResult", action_type)),
+            ActionOp::SendGoal | ActionOp::Preempt => None,
+        }
+    }
+
+    // request of the cancel and result services, the goal payload is not used
+    pub fn get_service_payload(&self) -> String {
+        let uuid = self
+            .target_id
+            .iter()
+            .map(|byte| byte.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        match self.op {
+            ActionOp::Cancel => format!("{{goal_info: {{goal_id: {{uuid: [{}]}}}}}}", uuid),
+            ActionOp::GetResult => format!("{{goal_id: {{uuid: [{}]}}}}", uuid),
+            _ => "{}".to_string(),
+        }
+    }
+}
+
+// zero and all-ones ids are reserved or never issued, servers must reject them cleanly
+fn gen_goal_id(rng: &mut RngType) -> GoalId {
+    match rng.gen_range(0..8) {
+        0 => [0; GOAL_ID_LEN],
+        1 => [0xff; GOAL_ID_LEN],
+        _ => rng.gen(),
+    }
+}
+
+/// What one action request observed, parsed from the output of the cli or of the helper.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ActionOutcome {
+    pub goal_id: Option<GoalId>,
+    pub accepted: bool,
+    pub rejected: bool,
+    pub feedback: usize,
+    pub status: String,
+    pub result: String,
+    // callbacks of the action entities that ran during the call
+    #[serde(default)]
+    pub callbacks: Vec<u64>,
+}
+
+impl ActionOutcome {
+    pub fn parse(output: &str) -> ActionOutcome {
+        let mut outcome = ActionOutcome::default();
+        let mut lines = output.lines().peekable();
+        while let Some(line) = lines.next() {
+            let line = line.trim();
+            if let Some(id) = line.strip_prefix(ACCEPTED_LINE) {
+                outcome.accepted = true;
+                outcome.goal_id = parse_goal_id(id.trim());
+            } else if line.starts_with("Goal was rejected") {
+                outcome.rejected = true;
+            } else if line == "Feedback:" {
+                outcome.feedback += 1;
+            } else if let Some(status) = line.strip_prefix(STATUS_LINE) {
+                outcome.status = status.trim().to_string();
+            } else if line == "Result:" {
+                // the result message runs until the next blank line
+                let mut result = Vec::new();
+                while let Some(next) = lines.peek() {
+                    if next.trim().is_empty() {
+                        break;
+                    }
+                    result.push(next.trim());
+                    lines.next();
+                }
+                outcome.result = result.join("\n");
+            }
+        }
+        outcome
+    }
+}
+
+pub fn parse_goal_id(hex: &str) -> Option<GoalId> {
+    if hex.len() != GOAL_ID_LEN * 2 || !hex.is_ascii() {
+        return None;
+    }
+    let mut id = [0; GOAL_ID_LEN];
+    for (idx, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).ok()?;
+    }
+    Some(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_send_goal_output() {
+        let output = "Waiting for an action server to become available...\n\
+            Sending goal:\n     order: 5\n\n\
+            Goal accepted with ID: 0a1b2c3d4e5f60718293a4b5c6d7e8f9\n\n\
+            Feedback:\n    partial_sequence: [0, 1, 1]\n\n\
+            Feedback:\n    partial_sequence: [0, 1, 1, 2]\n\n\
+            Result:\n    sequence: [0, 1, 1, 2, 3]\n\n\
+            Goal finished with status: SUCCEEDED\n";
+        let outcome = ActionOutcome::parse(output);
+        assert!(outcome.accepted && !outcome.rejected);
+        assert_eq!(outcome.goal_id.unwrap()[0], 0x0a);
+        assert_eq!(outcome.goal_id.unwrap()[15], 0xf9);
+        assert_eq!(outcome.feedback, 2);
+        assert_eq!(outcome.result, "sequence: [0, 1, 1, 2, 3]");
+        assert_eq!(outcome.status, "SUCCEEDED");
+
+        let outcome = ActionOutcome::parse("Sending goal:\n     order: -1\n\nGoal was rejected.\n");
+        assert!(outcome.rejected && outcome.goal_id.is_none());
+    }
+
+    #[test]
+    fn service_requests() {
+        let mut call = ActionCall {
+            op: ActionOp::Cancel,
+            ..Default::default()
+        };
+        call.target_id[0] = 7;
+        assert_eq!(
+            call.get_service_name("/fibonacci/"),
+            "/fibonacci/_action/cancel_goal"
+        );
+        assert!(call
+            .get_service_payload()
+            .starts_with("{goal_info: {goal_id: {uuid: [7, 0, 0,"));
+        call.op = ActionOp::GetResult;
+        assert_eq!(
+            call.get_service_type("example_interfaces/action/Fibonacci")
+                .unwrap(),
+            "example_interfaces/action/Fibonacci_GetResult"
+        );
+        call.op = ActionOp::CancelAll;
+        assert_eq!(call.get_service_payload(), "{}");
+    }
+}
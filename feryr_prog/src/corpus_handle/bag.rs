@@ -0,0 +1,576 @@
+//! rosbag2 storage. Bags are read from the sqlite3 (`.db3`) and the MCAP storage plugins,
+//! either one file or a bag directory with its `metadata.yaml`. Only CDR serialized messages
+//! are kept, in the order they were recorded. Bags are written the same way, one storage file
+//! per bag directory, for `ros2 bag play` to replay them.
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    collections::HashMap,
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+pub const MCAP_MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_DATA_END: u8 = 0x0f;
+
+/// One recorded message, `data` is the CDR payload with its encapsulation header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BagMessage {
+    pub topic: String,
+    pub itf_type: String,
+    // receive time in ns
+    pub time_ns: u64,
+    pub data: Vec<u8>,
+}
+
+// storage files of a bag, in split order
+fn get_storage_files(path: &Path) -> Result<Vec<PathBuf>, failure::Error> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files: Vec<PathBuf> = fs::read_dir(path)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|file| matches!(get_ext(file), "db3" | "mcap"))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(failure::format_err!(
+            "no .db3 or .mcap file in {}",
+            path.display()
+        ));
+    }
+    Ok(files)
+}
+
+// rosbag2 records the long form, e.g. std_msgs/msg/String
+pub fn get_bag_type(itf_type: &str) -> String {
+    let parts: Vec<&str> = itf_type.split('/').collect();
+    match parts.len() {
+        2 => format!("{}/msg/{}", parts[0], parts[1]),
+        _ => itf_type.to_string(),
+    }
+}
+
+fn get_ext(path: &Path) -> &str {
+    path.extension().and_then(|ext| ext.to_str()).unwrap_or("")
+}
+
+pub fn read_bag(path: &str) -> Result<Vec<BagMessage>, failure::Error> {
+    let mut messages = Vec::new();
+    for file in get_storage_files(Path::new(path))? {
+        match get_ext(&file) {
+            "db3" => messages.extend(read_sqlite(&file)?),
+            "mcap" => messages.extend(read_mcap(&fs::read(&file)?)?),
+            _ => {
+                return Err(failure::format_err!(
+                    "unknown bag storage: {}",
+                    file.display()
+                ))
+            }
+        }
+    }
+    messages.sort_by_key(|message| message.time_ns);
+    Ok(messages)
+}
+
+pub fn read_sqlite(path: &Path) -> Result<Vec<BagMessage>, failure::Error> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT topics.name, topics.type, messages.timestamp, messages.data \
+         FROM messages JOIN topics ON messages.topic_id = topics.id \
+         WHERE topics.serialization_format = 'cdr' ORDER BY messages.timestamp",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        Ok(BagMessage {
+            topic: row.get(0)?,
+            itf_type: row.get(1)?,
+            time_ns: row.get::<_, i64>(2)? as u64,
+            data: row.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+// little endian fields of an MCAP record
+struct McapReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> McapReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], failure::Error> {
+        let end = self.pos.saturating_add(len);
+        if end > self.data.len() {
+            return Err(failure::format_err!(
+                "mcap: truncated record at {}",
+                self.pos
+            ));
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn take_int<const N: usize>(&mut self) -> Result<[u8; N], failure::Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn take_u16(&mut self) -> Result<u16, failure::Error> {
+        Ok(u16::from_le_bytes(self.take_int()?))
+    }
+
+    fn take_u32(&mut self) -> Result<u32, failure::Error> {
+        Ok(u32::from_le_bytes(self.take_int()?))
+    }
+
+    fn take_u64(&mut self) -> Result<u64, failure::Error> {
+        Ok(u64::from_le_bytes(self.take_int()?))
+    }
+
+    fn take_string(&mut self) -> Result<String, failure::Error> {
+        let len = self.take_u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).to_string())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+#[derive(Default)]
+struct McapState {
+    // schema id to ros type
+    schemas: HashMap<u16, String>,
+    // channel id to topic, schema id and message encoding
+    channels: HashMap<u16, (String, u16, String)>,
+    messages: Vec<BagMessage>,
+}
+
+impl McapState {
+    // records of the data section or of a chunk, up to the end of the data section
+    fn read_records(&mut self, data: &[u8]) -> Result<(), failure::Error> {
+        let mut reader = McapReader { data, pos: 0 };
+        while !reader.is_empty() {
+            let op = reader.take(1)?[0];
+            let len = reader.take_u64()? as usize;
+            let mut record = McapReader {
+                data: reader.take(len)?,
+                pos: 0,
+            };
+            match op {
+                OP_SCHEMA => {
+                    let id = record.take_u16()?;
+                    self.schemas.insert(id, record.take_string()?);
+                }
+                OP_CHANNEL => {
+                    let id = record.take_u16()?;
+                    let schema_id = record.take_u16()?;
+                    let topic = record.take_string()?;
+                    let encoding = record.take_string()?;
+                    self.channels.insert(id, (topic, schema_id, encoding));
+                }
+                OP_MESSAGE => {
+                    let channel_id = record.take_u16()?;
+                    let _sequence = record.take_u32()?;
+                    let log_time = record.take_u64()?;
+                    let _publish_time = record.take_u64()?;
+                    let (topic, schema_id, encoding) = match self.channels.get(&channel_id) {
+                        Some(channel) => channel,
+                        None => continue,
+                    };
+                    if encoding != "cdr" {
+                        continue;
+                    }
+                    self.messages.push(BagMessage {
+                        topic: topic.clone(),
+                        itf_type: self.schemas.get(schema_id).cloned().unwrap_or_default(),
+                        time_ns: log_time,
+                        data: record.data[record.pos..].to_vec(),
+                    });
+                }
+                OP_CHUNK => {
+                    let _start_time = record.take_u64()?;
+                    let _end_time = record.take_u64()?;
+                    let size = record.take_u64()? as usize;
+                    let _crc = record.take_u32()?;
+                    let compression = record.take_string()?;
+                    let len = record.take_u64()? as usize;
+                    let records = decompress(&compression, record.take(len)?, size)?;
+                    self.read_records(&records)?;
+                }
+                OP_DATA_END | OP_FOOTER => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decompress(compression: &str, data: &[u8], size: usize) -> Result<Vec<u8>, failure::Error> {
+    match compression {
+        "" => Ok(data.to_vec()),
+        "zstd" => Ok(zstd::stream::decode_all(data)?),
+        "lz4" => {
+            let mut records = Vec::with_capacity(size);
+            lz4_flex::frame::FrameDecoder::new(data).read_to_end(&mut records)?;
+            Ok(records)
+        }
+        _ => Err(failure::format_err!(
+            "mcap: unknown chunk compression {}",
+            compression
+        )),
+    }
+}
+
+pub fn read_mcap(data: &[u8]) -> Result<Vec<BagMessage>, failure::Error> {
+    if !data.starts_with(MCAP_MAGIC) {
+        return Err(failure::err_msg("mcap: bad magic"));
+    }
+    let mut state = McapState::default();
+    state.read_records(&data[MCAP_MAGIC.len()..])?;
+    Ok(state.messages)
+}
+
+/// Storage plugin of a written bag.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BagStorage {
+    Sqlite,
+    Mcap,
+}
+
+impl BagStorage {
+    pub fn from_name(name: &str) -> Option<BagStorage> {
+        match name {
+            "sqlite3" | "db3" => Some(BagStorage::Sqlite),
+            "mcap" => Some(BagStorage::Mcap),
+            _ => None,
+        }
+    }
+
+    pub fn get_identifier(&self) -> &'static str {
+        match self {
+            BagStorage::Sqlite => "sqlite3",
+            BagStorage::Mcap => "mcap",
+        }
+    }
+
+    pub fn get_extension(&self) -> &'static str {
+        match self {
+            BagStorage::Sqlite => "db3",
+            BagStorage::Mcap => "mcap",
+        }
+    }
+}
+
+/// A topic of a written bag, `qos` is its `offered_qos_profiles` yaml.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BagTopic {
+    pub name: String,
+    pub itf_type: String,
+    pub qos: String,
+}
+
+// the bag directory with one storage file and its metadata.yaml, every message has to be on
+// one of the topics
+pub fn write_bag(
+    dir: &Path,
+    storage: BagStorage,
+    topics: &[BagTopic],
+    messages: &[BagMessage],
+) -> Result<(), failure::Error> {
+    let mut messages = messages.to_vec();
+    messages.sort_by_key(|message| message.time_ns);
+    let mut counts = vec![0; topics.len()];
+    for message in messages.iter() {
+        match topics.iter().position(|topic| topic.name == message.topic) {
+            Some(idx) => counts[idx] += 1,
+            None => {
+                return Err(failure::format_err!(
+                    "bag: no topic {} for a message",
+                    message.topic
+                ))
+            }
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    let name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("bag");
+    let file = format!("{}_0.{}", name, storage.get_extension());
+    let _ = fs::remove_file(dir.join(&file));
+    match storage {
+        BagStorage::Sqlite => write_sqlite(&dir.join(&file), topics, &messages)?,
+        BagStorage::Mcap => fs::write(dir.join(&file), write_mcap(topics, &messages))?,
+    }
+
+    let start = messages.first().map(|message| message.time_ns).unwrap_or(0);
+    let duration = messages.last().map(|message| message.time_ns).unwrap_or(0) - start;
+    let mut metadata = format!(
+        "rosbag2_bagfile_information:\n  version: 5\n  storage_identifier: {}\n  duration:\n    \
+         nanoseconds: {}\n  starting_time:\n    nanoseconds_since_epoch: {}\n  message_count: {}\n  \
+         topics_with_message_count:\n",
+        storage.get_identifier(),
+        duration,
+        start,
+        messages.len()
+    );
+    for (topic, count) in topics.iter().zip(counts) {
+        metadata += &format!(
+            "    - topic_metadata:\n        name: {}\n        type: {}\n        \
+             serialization_format: cdr\n        offered_qos_profiles: {:?}\n      message_count: {}\n",
+            topic.name, topic.itf_type, topic.qos, count
+        );
+    }
+    metadata += &format!(
+        "  compression_format: \"\"\n  compression_mode: \"\"\n  relative_file_paths:\n    - {}\n  \
+         files:\n    - path: {}\n      starting_time:\n        nanoseconds_since_epoch: {}\n      \
+         duration:\n        nanoseconds: {}\n      message_count: {}\n",
+        file,
+        file,
+        start,
+        duration,
+        messages.len()
+    );
+    fs::write(dir.join("metadata.yaml"), metadata)?;
+    Ok(())
+}
+
+pub fn write_sqlite(
+    path: &Path,
+    topics: &[BagTopic],
+    messages: &[BagMessage],
+) -> Result<(), failure::Error> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(
+        "CREATE TABLE topics(id INTEGER PRIMARY KEY, name TEXT NOT NULL, type TEXT NOT NULL, \
+         serialization_format TEXT NOT NULL, offered_qos_profiles TEXT NOT NULL);
+         CREATE TABLE messages(id INTEGER PRIMARY KEY, topic_id INTEGER NOT NULL, \
+         timestamp INTEGER NOT NULL, data BLOB NOT NULL);
+         CREATE INDEX timestamp_idx ON messages (timestamp ASC);",
+    )?;
+    let tx = conn.transaction()?;
+    for (idx, topic) in topics.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO topics VALUES (?1, ?2, ?3, 'cdr', ?4)",
+            rusqlite::params![idx as i64 + 1, topic.name, topic.itf_type, topic.qos],
+        )?;
+    }
+    for message in messages {
+        let id = topics
+            .iter()
+            .position(|topic| topic.name == message.topic)
+            .unwrap_or(0);
+        tx.execute(
+            "INSERT INTO messages(topic_id, timestamp, data) VALUES (?1, ?2, ?3)",
+            rusqlite::params![id as i64 + 1, message.time_ns as i64, message.data],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+fn record(op: u8, fields: &[&[u8]]) -> Vec<u8> {
+    let body: Vec<u8> = fields.concat();
+    let mut res = vec![op];
+    res.extend_from_slice(&(body.len() as u64).to_le_bytes());
+    res.extend(body);
+    res
+}
+
+fn string(val: &str) -> Vec<u8> {
+    let mut res = (val.len() as u32).to_le_bytes().to_vec();
+    res.extend_from_slice(val.as_bytes());
+    res
+}
+
+// an unchunked file without summary, the mcap readers fall back to scanning the data section
+pub fn write_mcap(topics: &[BagTopic], messages: &[BagMessage]) -> Vec<u8> {
+    let mut res = MCAP_MAGIC.to_vec();
+    res.extend(record(OP_HEADER, &[&string("ros2"), &string("feryr")]));
+    // one schema and one channel per topic, ids start at 1
+    for (idx, topic) in topics.iter().enumerate() {
+        let id = (idx as u16 + 1).to_le_bytes();
+        res.extend(record(
+            OP_SCHEMA,
+            &[
+                &id,
+                &string(&topic.itf_type),
+                &string("ros2msg"),
+                &string(""),
+            ],
+        ));
+        let metadata = [string("offered_qos_profiles"), string(&topic.qos)].concat();
+        res.extend(record(
+            OP_CHANNEL,
+            &[
+                &id,
+                &id,
+                &string(&topic.name),
+                &string("cdr"),
+                &(metadata.len() as u32).to_le_bytes(),
+                &metadata,
+            ],
+        ));
+    }
+    for (sequence, message) in messages.iter().enumerate() {
+        let id = topics
+            .iter()
+            .position(|topic| topic.name == message.topic)
+            .unwrap_or(0) as u16
+            + 1;
+        res.extend(record(
+            OP_MESSAGE,
+            &[
+                &id.to_le_bytes(),
+                &(sequence as u32).to_le_bytes(),
+                &message.time_ns.to_le_bytes(),
+                &message.time_ns.to_le_bytes(),
+                &message.data,
+            ],
+        ));
+    }
+    res.extend(record(OP_DATA_END, &[&0u32.to_le_bytes()]));
+    res.extend(record(
+        OP_FOOTER,
+        &[
+            &0u64.to_le_bytes(),
+            &0u64.to_le_bytes(),
+            &0u32.to_le_bytes(),
+        ],
+    ));
+    res.extend_from_slice(MCAP_MAGIC);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sqlite_and_mcap_messages() {
+        let payload = b"\x00\x01\x00\x00\x03\x00\x00\x00hi\x00".to_vec();
+        let dir = std::env::temp_dir().join(format!("feryr-bag-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let conn = Connection::open(dir.join("bag_0.db3")).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE topics(id INTEGER PRIMARY KEY, name TEXT NOT NULL, type TEXT NOT NULL, \
+             serialization_format TEXT NOT NULL, offered_qos_profiles TEXT NOT NULL);
+             CREATE TABLE messages(id INTEGER PRIMARY KEY, topic_id INTEGER NOT NULL, \
+             timestamp INTEGER NOT NULL, data BLOB NOT NULL);
+             INSERT INTO topics VALUES (1, '/chatter', 'std_msgs/msg/String', 'cdr', '');",
+        )
+        .unwrap();
+        for time in [300i64, 100] {
+            conn.execute(
+                "INSERT INTO messages(topic_id, timestamp, data) VALUES (1, ?1, ?2)",
+                rusqlite::params![time, payload],
+            )
+            .unwrap();
+        }
+        drop(conn);
+
+        // one schema and channel up front, a message in a chunk and one after it
+        let message = |time: u64| {
+            record(
+                OP_MESSAGE,
+                &[
+                    &1u16.to_le_bytes(),
+                    &0u32.to_le_bytes(),
+                    &time.to_le_bytes(),
+                    &time.to_le_bytes(),
+                    &payload,
+                ],
+            )
+        };
+        let inner = message(200);
+        let mut mcap = MCAP_MAGIC.to_vec();
+        mcap.extend(record(
+            OP_SCHEMA,
+            &[
+                &1u16.to_le_bytes(),
+                &string("std_msgs/msg/String"),
+                &string("ros2msg"),
+                &string(""),
+            ],
+        ));
+        mcap.extend(record(
+            OP_CHANNEL,
+            &[
+                &1u16.to_le_bytes(),
+                &1u16.to_le_bytes(),
+                &string("/chatter"),
+                &string("cdr"),
+                &0u32.to_le_bytes(),
+            ],
+        ));
+        mcap.extend(record(
+            OP_CHUNK,
+            &[
+                &200u64.to_le_bytes(),
+                &200u64.to_le_bytes(),
+                &(inner.len() as u64).to_le_bytes(),
+                &0u32.to_le_bytes(),
+                &string(""),
+                &(inner.len() as u64).to_le_bytes(),
+                &inner,
+            ],
+        ));
+        mcap.extend(message(400));
+        mcap.extend(record(OP_DATA_END, &[&0u32.to_le_bytes()]));
+        fs::write(dir.join("bag_1.mcap"), &mcap).unwrap();
+
+        let messages = read_bag(dir.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let times: Vec<u64> = messages.iter().map(|message| message.time_ns).collect();
+        assert_eq!(times, vec![100, 200, 300, 400]);
+        for message in messages {
+            assert_eq!(message.topic, "/chatter");
+            assert_eq!(message.itf_type, "std_msgs/msg/String");
+            assert_eq!(message.data, payload);
+        }
+        assert!(read_mcap(b"not a bag").is_err());
+    }
+
+    #[test]
+    fn written_bags_read_back() {
+        let topics = vec![BagTopic {
+            name: "/chatter".to_string(),
+            itf_type: "std_msgs/msg/String".to_string(),
+            qos: "- history: 1".to_string(),
+        }];
+        let messages: Vec<BagMessage> = [200u64, 100]
+            .iter()
+            .map(|&time_ns| BagMessage {
+                topic: "/chatter".to_string(),
+                itf_type: "std_msgs/msg/String".to_string(),
+                time_ns,
+                data: b"\x00\x01\x00\x00\x01\x00\x00\x00\x00".to_vec(),
+            })
+            .collect();
+        for storage in [BagStorage::Sqlite, BagStorage::Mcap] {
+            let dir = std::env::temp_dir().join(format!(
+                "feryr-bag-{}-{}",
+                storage.get_identifier(),
+                std::process::id()
+            ));
+            write_bag(&dir, storage, &topics, &messages).unwrap();
+            let metadata = fs::read_to_string(dir.join("metadata.yaml")).unwrap();
+            let read = read_bag(dir.to_str().unwrap()).unwrap();
+            fs::remove_dir_all(&dir).unwrap();
+            assert!(metadata.contains("duration:\n    nanoseconds: 100\n"));
+            assert_eq!(read.len(), 2);
+            assert_eq!(read[0].time_ns, 100);
+            assert_eq!(read[1], messages[0]);
+        }
+        assert!(write_bag(Path::new("/nonexistent"), BagStorage::Mcap, &[], &messages).is_err());
+    }
+}
@@ -0,0 +1,244 @@
+//! Boot-time fuzzing. Launch arguments declared in the profile of the target and a params file
+//! for the declared parameters of the nodes are generated for each boot, so that the node
+//! constructors and on_configure callbacks run on configurations other than the default one.
+use super::{
+    interface::Node,
+    param::{gen_confused, get_params_file, ParamValue},
+    yaml::float_scalar,
+    RngType,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::{fs::File, path::Path};
+use util::fuzzer_info;
+
+pub const BOOT_SCHEMA_FILE: &str = "launch_args.json";
+pub const MAX_BOOT_PARAMS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ArgKind {
+    Bool,
+    Int,
+    Float,
+    String,
+    // one of `values`
+    Choice,
+}
+
+/// A launch argument of the target, declared in `sys/<target>/launch_args.json`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LaunchArg {
+    pub name: String,
+    pub kind: ArgKind,
+    // known good values, also tried for strings
+    #[serde(default)]
+    pub values: Vec<String>,
+    #[serde(default)]
+    pub min: f64,
+    #[serde(default)]
+    pub max: f64,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct BootSchema {
+    pub args: Vec<LaunchArg>,
+    // launch argument taking a params file, e.g. params_file for nav2, no params file without
+    #[serde(default)]
+    pub params_file_arg: Option<String>,
+}
+
+impl BootSchema {
+    // an empty schema when the profile declares none
+    pub fn load(profile_dir: &str) -> Result<BootSchema, failure::Error> {
+        let path = Path::new(profile_dir).join(BOOT_SCHEMA_FILE);
+        if !path.exists() {
+            return Ok(BootSchema::default());
+        }
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+}
+
+/// Configuration of one boot, saved with the findings it leads to.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BootConfig {
+    pub args: Vec<(String, String)>,
+    // overrides per node, written to the params file
+    pub params: Vec<(String, Vec<ParamValue>)>,
+}
+
+impl BootConfig {
+    pub fn generate(
+        schema: &BootSchema,
+        nodes: &[Node],
+        confusion: bool,
+        rng: &mut RngType,
+    ) -> BootConfig {
+        let mut config = BootConfig::default();
+        for arg in schema.args.iter() {
+            if rng.gen() {
+                config.args.push((arg.name.clone(), gen_arg(arg, rng)));
+            }
+        }
+        if schema.params_file_arg.is_some() {
+            for node in nodes.iter() {
+                if rng.gen_ratio(1, 2) {
+                    continue;
+                }
+                let params = gen_params(node, confusion, rng);
+                if !params.is_empty() {
+                    config.params.push((node.node_name.clone(), params));
+                }
+            }
+        }
+        config
+    }
+
+    pub fn mutate(
+        &mut self,
+        schema: &BootSchema,
+        nodes: &[Node],
+        confusion: bool,
+        rng: &mut RngType,
+    ) {
+        match rng.gen_range(0..3) {
+            0 if !schema.args.is_empty() => {
+                let arg = &schema.args[rng.gen_range(0..schema.args.len())];
+                let val = gen_arg(arg, rng);
+                match self.args.iter_mut().find(|(name, _)| *name == arg.name) {
+                    Some(old) if rng.gen_ratio(1, 4) => {
+                        let name = old.0.clone();
+                        self.args.retain(|(other, _)| *other != name);
+                    }
+                    Some(old) => old.1 = val,
+                    None => self.args.push((arg.name.clone(), val)),
+                }
+            }
+            1 if schema.params_file_arg.is_some() && !nodes.is_empty() => {
+                let node = &nodes[rng.gen_range(0..nodes.len())];
+                let params = gen_params(node, confusion, rng);
+                self.params.retain(|(name, _)| *name != node.node_name);
+                if !params.is_empty() {
+                    self.params.push((node.node_name.clone(), params));
+                }
+            }
+            _ => *self = BootConfig::generate(schema, nodes, confusion, rng),
+        }
+    }
+
+    pub fn get_params_file(&self) -> String {
+        get_params_file(&self.params)
+    }
+
+    // `name:=value` arguments of `ros2 launch`, the params file is passed when there is one
+    pub fn get_launch_args(&self, schema: &BootSchema, params_path: &str) -> Vec<String> {
+        let mut args: Vec<String> = self
+            .args
+            .iter()
+            .map(|(name, val)| format!("{}:={}", name, val))
+            .collect();
+        if let (Some(arg), false) = (&schema.params_file_arg, self.params.is_empty()) {
+            args.push(format!("{}:={}", arg, params_path));
+        }
+        args
+    }
+}
+
+// a declared good value most of the time, otherwise a value of the kind out of its range or
+// of no kind at all
+fn gen_arg(arg: &LaunchArg, rng: &mut RngType) -> String {
+    if !arg.values.is_empty() && rng.gen_ratio(1, 2) {
+        return arg.values.choose(rng).unwrap().clone();
+    }
+    if rng.gen_ratio(1, 8) {
+        return ["", "''", "none", "-1", "nan", "/"]
+            .choose(rng)
+            .unwrap()
+            .to_string();
+    }
+    match arg.kind {
+        ArgKind::Bool => ["true", "false", "True", "False", "1", "0"]
+            .choose(rng)
+            .unwrap()
+            .to_string(),
+        ArgKind::Int => match rng.gen_ratio(1, 4) {
+            true => [i64::MIN, -1, 0, i64::MAX].choose(rng).unwrap().to_string(),
+            false => (rng.gen_range(arg.min..=arg.max.max(arg.min)) as i64).to_string(),
+        },
+        ArgKind::Float => match rng.gen_ratio(1, 4) {
+            true => ["-1.0", "0.0", "1e308", ".inf"]
+                .choose(rng)
+                .unwrap()
+                .to_string(),
+            false => float_scalar(rng.gen_range(arg.min..=arg.max.max(arg.min)), 64),
+        },
+        // launch substitutes the raw text, no quoting needed
+        ArgKind::String | ArgKind::Choice => (0..rng.gen_range(0..16))
+            .map(|_| rng.gen_range(b'a'..=b'z') as char)
+            .collect(),
+    }
+}
+
+// read-only parameters are only writable here, at construction
+fn gen_params(node: &Node, confusion: bool, rng: &mut RngType) -> Vec<ParamValue> {
+    let mut names: Vec<&String> = node.get_param().keys().collect();
+    names.shuffle(rng);
+    names.truncate(rng.gen_range(1..=MAX_BOOT_PARAMS));
+    let mut params = Vec::new();
+    for name in names {
+        let mut itf_info = node.get_param().get(name).unwrap().clone();
+        let (itf_type, value) = match confusion && rng.gen_ratio(1, 4) {
+            true => gen_confused(&itf_info.itf_type, rng),
+            false => {
+                if let Err(e) = itf_info.gen_value(rng) {
+                    fuzzer_info!("gen boot param error: {}", e);
+                    continue;
+                }
+                (itf_info.itf_type.clone(), itf_info.to_yaml())
+            }
+        };
+        params.push(ParamValue {
+            name: name.to_string(),
+            itf_type,
+            value,
+        });
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn launch_args_follow_schema() {
+        let schema: BootSchema = serde_json::from_str(
+            r#"{"params_file_arg": "params_file", "args": [
+                {"name": "autostart", "kind": "Bool"},
+                {"name": "log_level", "kind": "Choice", "values": ["info", "debug"]}]}"#,
+        )
+        .unwrap();
+        let mut rng = RngType::seed_from_u64(0);
+        let mut config = BootConfig::generate(&schema, &[], false, &mut rng);
+        for _ in 0..32 {
+            config.mutate(&schema, &[], false, &mut rng);
+            let args = config.get_launch_args(&schema, "/tmp/params.yaml");
+            assert_eq!(args.len(), config.args.len());
+            assert!(args
+                .iter()
+                .all(|arg| arg.starts_with("autostart:=") || arg.starts_with("log_level:=")));
+        }
+
+        config.params.push((
+            "planner_server".to_string(),
+            vec![ParamValue {
+                name: "expected_planner_frequency".to_string(),
+                itf_type: "float64".to_string(),
+                value: "-1.0".to_string(),
+            }],
+        ));
+        let args = config.get_launch_args(&schema, "/tmp/params.yaml");
+        assert_eq!(args.last().unwrap(), "params_file:=/tmp/params.yaml");
+        assert!(config.get_params_file().contains("/planner_server"));
+    }
+}
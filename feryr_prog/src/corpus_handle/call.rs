@@ -0,0 +1,327 @@
+use super::{
+    action::ActionCall,
+    clock::ClockStream,
+    impersonate::Impersonation,
+    impostor::Impostor,
+    interface::ITF,
+    lifecycle::LifecycleCall,
+    param::{Confusion, ParamUpdate, SET_ATOMICALLY_TYPE},
+    qos::Qos,
+    timing::Timing,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub const ROS2_BIN: &str = "ros2";
+// the directory of the running replay script
+const SCRIPT_DIR: &str = "\"$(dirname \"$0\")\"";
+
+/// Structured command line of one input, spawned as discrete arguments so
+/// generated values never go through a shell.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CallArgs {
+    pub itf: ITF,
+    // node the call targets, only on the command line for ITF::Param
+    pub node_name: String,
+    pub itf_name: String,
+    pub itf_type: String,
+    pub payload: String,
+    pub options: Vec<String>,
+    // pre-serialized cdr message, published instead of payload when set
+    #[serde(default)]
+    pub raw: Vec<u8>,
+    // publishing pattern of a topic call, a single message when unset
+    #[serde(default)]
+    pub timing: Option<Timing>,
+    // protocol request of an action call, a plain send_goal when unset
+    #[serde(default)]
+    pub action: Option<ActionCall>,
+    // server stood up by an ITF::Impostor call
+    #[serde(default)]
+    pub impostor: Option<Impostor>,
+    // topic call injected next to a legitimate publisher
+    #[serde(default)]
+    pub impersonate: Option<Impersonation>,
+    // profile of the injected publisher, the cli default when unset
+    #[serde(default)]
+    pub qos: Option<Qos>,
+    // several parameters of the node at once, replaces the single `param set`
+    #[serde(default)]
+    pub params: Option<ParamUpdate>,
+    // parameters sent with another type than declared, in the payload or in params
+    #[serde(default)]
+    pub confusion: Vec<Confusion>,
+    // transition requested by an ITF::Lifecycle call, its request is the payload
+    #[serde(default)]
+    pub lifecycle: Option<LifecycleCall>,
+    // time published by an ITF::Clock call
+    #[serde(default)]
+    pub clock: Option<ClockStream>,
+}
+
+impl CallArgs {
+    pub fn new(itf: ITF, node_name: &str, itf_name: &str, itf_type: &str, payload: String) -> Self {
+        let options = match itf {
+            ITF::Topic => vec!["--once".to_string()],
+            _ => Vec::new(),
+        };
+        CallArgs {
+            itf,
+            node_name: node_name.to_string(),
+            itf_name: itf_name.to_string(),
+            itf_type: itf_type.to_string(),
+            payload,
+            options,
+            raw: Vec::new(),
+            timing: None,
+            action: None,
+            impostor: None,
+            impersonate: None,
+            qos: None,
+            params: None,
+            confusion: Vec::new(),
+            lifecycle: None,
+            clock: None,
+        }
+    }
+
+    // the cli has no burst or jitter, it publishes the same count spread evenly
+    pub fn set_timing(&mut self, timing: Option<Timing>) {
+        if self.itf != ITF::Topic {
+            return;
+        }
+        self.options = match &timing {
+            Some(timing) => vec![
+                "-r".to_string(),
+                (timing.rate * timing.burst as f64).to_string(),
+                "-t".to_string(),
+                timing.get_count().to_string(),
+            ],
+            None => vec!["--once".to_string()],
+        };
+        self.timing = timing;
+    }
+
+    pub fn set_action(&mut self, action: Option<ActionCall>) {
+        if self.itf != ITF::Action {
+            return;
+        }
+        self.action = action;
+        // feedback is printed by the cli only on request
+        self.options = match self.get_action_service() {
+            Some(_) => Vec::new(),
+            None => vec!["--feedback".to_string()],
+        };
+    }
+
+    pub fn set_lifecycle(&mut self, lifecycle: Option<LifecycleCall>) {
+        if self.itf != ITF::Lifecycle {
+            return;
+        }
+        if let Some(lifecycle) = &lifecycle {
+            self.payload = lifecycle.get_payload();
+        }
+        self.lifecycle = lifecycle;
+    }
+
+    pub fn set_clock(&mut self, clock: Option<ClockStream>) {
+        if self.itf != ITF::Clock {
+            return;
+        }
+        self.options = Vec::new();
+        self.clock = clock;
+    }
+
+    // name and type of the action service called directly instead of `ros2 action send_goal`
+    pub fn get_action_service(&self) -> Option<(String, String)> {
+        let action = self.action.as_ref()?;
+        let srv_type = action.get_service_type(&self.itf_type)?;
+        Some((action.get_service_name(&self.itf_name), srv_type))
+    }
+
+    pub fn get_verb(&self) -> [&'static str; 2] {
+        match self.itf {
+            ITF::Topic => ["topic", "pub"],
+            ITF::Service => ["service", "call"],
+            ITF::Action if self.get_action_service().is_some() => ["service", "call"],
+            ITF::Action => ["action", "send_goal"],
+            ITF::Param => match &self.params {
+                Some(params) if params.atomic => ["service", "call"],
+                Some(_) => ["param", "load"],
+                None => ["param", "set"],
+            },
+            // not a ros2 verb, only the helper executor serves impostors
+            ITF::Impostor => ["impostor", "serve"],
+            // ros2 service call <node>/change_state lifecycle_msgs/srv/ChangeState <request>
+            ITF::Lifecycle => ["service", "call"],
+            // a single message of the stream, only the helper executor plays it
+            ITF::Clock => ["topic", "pub"],
+        }
+    }
+
+    // file a non-atomic param update is loaded from, written by the executor before the call
+    pub fn get_param_path(&self, param_dir: &str) -> Option<PathBuf> {
+        match (&self.itf, &self.params) {
+            (ITF::Param, Some(params)) if !params.atomic => {
+                Some(params.get_param_path(param_dir, &self.node_name))
+            }
+            _ => None,
+        }
+    }
+
+    /// Arguments passed to `ros2`, without the program name. A param file is read from
+    /// param_dir.
+    pub fn get_argv(&self, param_dir: &str) -> Vec<String> {
+        let mut argv: Vec<String> = self.get_verb().iter().map(|s| s.to_string()).collect();
+        argv.extend(self.options.iter().cloned());
+        if let (ITF::Topic, Some(qos)) = (&self.itf, &self.qos) {
+            argv.extend(qos.get_cli_options());
+        }
+        match self.itf {
+            // ros2 service call <node_name>/set_parameters_atomically <srv_type> <request>
+            ITF::Param if self.params.as_ref().is_some_and(|params| params.atomic) => {
+                let params = self.params.as_ref().unwrap();
+                argv.push(ParamUpdate::get_service_name(&self.node_name));
+                argv.push(SET_ATOMICALLY_TYPE.to_string());
+                argv.push(params.get_service_payload());
+                return argv;
+            }
+            // ros2 param load <node_name> <param_file>
+            ITF::Param if self.params.is_some() => {
+                let path = self.get_param_path(param_dir).unwrap();
+                argv.push(self.node_name.clone());
+                argv.push(path.to_string_lossy().to_string());
+                return argv;
+            }
+            // ros2 param set <node_name> <param_name> <value>
+            ITF::Param => {
+                argv.push(self.node_name.clone());
+                argv.push(self.itf_name.clone());
+            }
+            // ros2 service call <action>/_action/<service> <service_type> <request>
+            ITF::Action if self.get_action_service().is_some() => {
+                let (srv_name, srv_type) = self.get_action_service().unwrap();
+                argv.push(srv_name);
+                argv.push(srv_type);
+                argv.push(self.action.as_ref().unwrap().get_service_payload());
+                return argv;
+            }
+            // ros2 <verb> <itf_name> <itf_type> <values>
+            _ => {
+                argv.push(self.itf_name.clone());
+                argv.push(self.itf_type.clone());
+            }
+        }
+        argv.push(self.payload.clone());
+        argv
+    }
+
+    /// Copy-pasteable shell rendering, only used for logs and reports.
+    pub fn to_call_stream(&self) -> String {
+        if let Some(impostor) = &self.impostor {
+            return format!("# {}", impostor.describe(&self.itf_name, &self.itf_type));
+        }
+        if let Some(clock) = &self.clock {
+            return format!("# {}", clock.describe());
+        }
+        let mut call_stream = ROS2_BIN.to_string();
+        for arg in self.get_argv("") {
+            call_stream.push(' ');
+            call_stream.push_str(&shell_quote(&arg));
+        }
+        if let (ITF::Param, Some(params)) = (&self.itf, &self.params) {
+            if !params.atomic {
+                call_stream.push_str(&format!(" # {}", params.get_param_file(&self.node_name)));
+            }
+        }
+        for note in self.get_notes() {
+            call_stream.push_str(&format!(" # {}", note));
+        }
+        call_stream
+    }
+
+    // the command line of a replay script, without any comment so that whatever the
+    // script appends after it still runs. None for calls only the helper can make
+    pub fn to_shell_command(&self) -> Option<String> {
+        if self.impostor.is_some() || self.clock.is_some() {
+            return None;
+        }
+        let mut argv: Vec<String> = self
+            .get_argv("")
+            .iter()
+            .map(|arg| shell_quote(arg))
+            .collect();
+        if self.get_param_path("").is_some() {
+            // the param file is written next to the script by get_shell_notes
+            let file = argv.pop().unwrap();
+            argv.push(format!("{}/{}", SCRIPT_DIR, file));
+        }
+        Some(format!("{} {}", ROS2_BIN, argv.join(" ")))
+    }
+
+    // the lines a replay script puts above the call: its annotations as comments and the
+    // heredoc that recreates its param file
+    pub fn get_shell_notes(&self) -> String {
+        let mut lines = String::new();
+        if let Some(impostor) = &self.impostor {
+            lines.push_str(&format!(
+                "# {}\n",
+                impostor.describe(&self.itf_name, &self.itf_type)
+            ));
+        }
+        if let Some(clock) = &self.clock {
+            lines.push_str(&format!("# {}\n", clock.describe()));
+        }
+        for note in self.get_notes() {
+            lines.push_str(&format!("# {}\n", note));
+        }
+        if let (Some(path), Some(params)) = (self.get_param_path(""), &self.params) {
+            lines.push_str(&format!(
+                "cat > {}/{} <<'FERYR_PARAMS'\n{}\nFERYR_PARAMS\n",
+                SCRIPT_DIR,
+                shell_quote(&path.to_string_lossy()),
+                params.get_param_file(&self.node_name)
+            ));
+        }
+        lines
+    }
+
+    // what the command line cannot express
+    fn get_notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        for confusion in self.confusion.iter() {
+            notes.push(format!(
+                "{} declared {} sent {}",
+                confusion.name, confusion.declared, confusion.sent
+            ));
+        }
+        if let Some(impersonate) = &self.impersonate {
+            // the cli does not follow the real publisher, it only publishes next to it
+            notes.push(format!(
+                "impersonating {}, divergence {:.2}",
+                impersonate.publisher, impersonate.divergence
+            ));
+        }
+        if !self.raw.is_empty() {
+            // the cli cannot publish raw bytes, keep them as a comment
+            let mut note = "raw cdr: ".to_string();
+            for byte in self.raw.iter() {
+                note.push_str(&format!("{:02x}", byte));
+            }
+            notes.push(note);
+        }
+        notes
+    }
+}
+
+// posix single quoting, a quote inside becomes '\''
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=+,@%".contains(c));
+    if plain {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
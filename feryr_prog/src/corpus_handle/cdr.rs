@@ -0,0 +1,892 @@
+//! Raw CDR (XCDR1, little endian) encoding of interface values
+//!
+//! The ros2 cli validates every payload against the message type, so malformed wire data can
+//! only be sent by publishing pre-serialized bytes. The encoder walks the IDL schema of the
+//! target together with the value tree, fields missing from the tree (like empty message
+//! sequences) are encoded as zero values. Every length prefix and string body is recorded in
+//! the layout, which lets the mutator break the wire format where deserializers trust it.
+//! The decoder reads recorded payloads back into value trees, e.g. to seed from a bag.
+
+use super::{
+    interface::{new_leaf, InterfaceParam, InterfaceVal, ValueType},
+    ty::{array::ArrayType, TYPE},
+    RngType,
+};
+use rand::Rng;
+use std::collections::HashMap;
+
+// encapsulation kind CDR_LE followed by two option bytes
+pub const CDR_LE_HEADER: [u8; 4] = [0x00, 0x01, 0x00, 0x00];
+pub const CDR_HEADER_LEN: usize = 4;
+
+const INTERESTING_LEN: [u32; 6] = [0, 1, 0x7f, 0xffff, 0x7fff_ffff, 0xffff_ffff];
+const BAD_UTF8: [&[u8]; 4] = [b"\xff", b"\xc3\x28", b"\xed\xa0\x80", b"\xf4\x90\x80\x80"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CdrKind {
+    Header,
+    Scalar,
+    // element count of a sequence
+    SeqLen,
+    // byte length of a string, terminator included
+    StrLen,
+    StrBody,
+}
+
+#[derive(Debug, Clone)]
+pub struct CdrField {
+    pub offset: usize,
+    pub len: usize,
+    pub kind: CdrKind,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CdrBuf {
+    pub data: Vec<u8>,
+    pub fields: Vec<CdrField>,
+}
+
+// primitive value taken from a leaf of the value tree
+enum Prim {
+    Int(u64),
+    Float(f64),
+    Str(String),
+}
+
+impl CdrBuf {
+    pub fn new() -> Self {
+        let mut buf = CdrBuf::default();
+        buf.push_field(&CDR_LE_HEADER, CdrKind::Header);
+        buf
+    }
+
+    // alignment is relative to the end of the encapsulation header
+    fn align(&mut self, size: usize) {
+        while (self.data.len() - CDR_HEADER_LEN) % size != 0 {
+            self.data.push(0);
+        }
+    }
+
+    fn push_field(&mut self, bytes: &[u8], kind: CdrKind) {
+        self.fields.push(CdrField {
+            offset: self.data.len(),
+            len: bytes.len(),
+            kind,
+        });
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn put_scalar(&mut self, bytes: &[u8]) {
+        self.align(bytes.len());
+        self.push_field(bytes, CdrKind::Scalar);
+    }
+
+    fn put_len(&mut self, len: usize, kind: CdrKind) {
+        self.align(4);
+        self.push_field(&(len as u32).to_le_bytes(), kind);
+    }
+
+    fn put_string(&mut self, val: &str) {
+        let mut body = val.as_bytes().to_vec();
+        body.push(0);
+        self.put_len(body.len(), CdrKind::StrLen);
+        self.push_field(&body, CdrKind::StrBody);
+    }
+
+    // wide chars are 4 bytes on the wire and carry no terminator
+    fn put_wstring(&mut self, val: &str) {
+        let chars: Vec<char> = val.chars().collect();
+        self.put_len(chars.len(), CdrKind::StrLen);
+        let body: Vec<u8> = chars
+            .iter()
+            .flat_map(|c| (*c as u32).to_le_bytes())
+            .collect();
+        self.push_field(&body, CdrKind::StrBody);
+    }
+
+    fn put_prim(&mut self, ty: TYPE, val: Option<&Prim>) {
+        let int = match val {
+            Some(Prim::Int(v)) => *v,
+            Some(Prim::Float(v)) => *v as i64 as u64,
+            _ => 0,
+        };
+        let float = match val {
+            Some(Prim::Float(v)) => *v,
+            Some(Prim::Int(v)) => *v as f64,
+            _ => 0.0,
+        };
+        match ty {
+            TYPE::Bool | TYPE::Byte | TYPE::Char | TYPE::Int8 | TYPE::UInt8 => {
+                self.put_scalar(&[int as u8])
+            }
+            TYPE::Int16 | TYPE::UInt16 => self.put_scalar(&(int as u16).to_le_bytes()),
+            TYPE::Int32 | TYPE::UInt32 => self.put_scalar(&(int as u32).to_le_bytes()),
+            TYPE::Int64 | TYPE::UInt64 => self.put_scalar(&int.to_le_bytes()),
+            TYPE::Float32 => self.put_scalar(&(float as f32).to_le_bytes()),
+            TYPE::Float64 => self.put_scalar(&float.to_le_bytes()),
+            TYPE::String | TYPE::WString => {
+                let val = match val {
+                    Some(Prim::Str(s)) => s.as_str(),
+                    _ => "",
+                };
+                if ty == TYPE::String {
+                    self.put_string(val);
+                } else {
+                    self.put_wstring(val);
+                }
+            }
+            TYPE::COMPLEX | TYPE::ARRAY => unreachable!(),
+        }
+    }
+
+    fn shift_fields(&mut self, pos: usize, count: usize) {
+        for field in self.fields.iter_mut() {
+            if field.offset >= pos {
+                field.offset += count;
+            }
+        }
+    }
+
+    /// Apply a few structure aware mutations: broken length prefixes, invalid utf-8, missing
+    /// terminators, bit flips in scalars, and at most one layout change (truncation or
+    /// misalignment) that shifts everything behind it.
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        for _ in 0..rng.gen_range(1..4) {
+            self.mutate_field(rng);
+        }
+        if rng.gen_ratio(1, 3) {
+            self.mutate_layout(rng);
+        }
+    }
+
+    fn mutate_field(&mut self, rng: &mut RngType) {
+        if self.fields.is_empty() {
+            return;
+        }
+        let field = self.fields[rng.gen_range(0..self.fields.len())].clone();
+        let bytes = &mut self.data[field.offset..field.offset + field.len];
+        match field.kind {
+            CdrKind::Header => {
+                // CDR_BE, the rest of the buffer stays little endian
+                bytes[1] = 0x00;
+            }
+            CdrKind::SeqLen | CdrKind::StrLen => {
+                let cur = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                let len = match rng.gen_range(0..4) {
+                    0 => cur.wrapping_add(1),
+                    1 => cur.wrapping_sub(1),
+                    2 => rng.gen(),
+                    _ => INTERESTING_LEN[rng.gen_range(0..INTERESTING_LEN.len())],
+                };
+                bytes.copy_from_slice(&len.to_le_bytes());
+            }
+            CdrKind::StrBody => {
+                if bytes.is_empty() {
+                    return;
+                }
+                if rng.gen() {
+                    // drop the terminator
+                    let last = bytes.len() - 1;
+                    bytes[last] = b'A';
+                } else {
+                    let bad = BAD_UTF8[rng.gen_range(0..BAD_UTF8.len())];
+                    let pos = rng.gen_range(0..bytes.len());
+                    let end = (pos + bad.len()).min(bytes.len());
+                    bytes[pos..end].copy_from_slice(&bad[..end - pos]);
+                }
+            }
+            CdrKind::Scalar => {
+                let idx = rng.gen_range(0..bytes.len());
+                bytes[idx] ^= 1 << rng.gen_range(0..8);
+            }
+        }
+    }
+
+    fn mutate_layout(&mut self, rng: &mut RngType) {
+        if self.data.len() <= CDR_HEADER_LEN {
+            return;
+        }
+        if rng.gen() {
+            let len = rng.gen_range(CDR_HEADER_LEN..self.data.len());
+            self.data.truncate(len);
+            self.fields.retain(|f| f.offset + f.len <= len);
+        } else {
+            // insert a few bytes at a field boundary to misalign all following fields
+            let field = &self.fields[rng.gen_range(0..self.fields.len())];
+            let pos = field.offset + field.len;
+            let count = rng.gen_range(1..4);
+            let pad: Vec<u8> = (0..count).map(|_| rng.gen()).collect();
+            self.data.splice(pos..pos, pad);
+            self.shift_fields(pos, count);
+        }
+    }
+}
+
+pub struct CdrEncoder<'a> {
+    type_maps: &'a HashMap<String, String>,
+    itf_info: &'a HashMap<String, Vec<InterfaceParam>>,
+    buf: CdrBuf,
+}
+
+impl<'a> CdrEncoder<'a> {
+    pub fn new(
+        type_maps: &'a HashMap<String, String>,
+        itf_info: &'a HashMap<String, Vec<InterfaceParam>>,
+    ) -> Self {
+        CdrEncoder {
+            type_maps,
+            itf_info,
+            buf: CdrBuf::new(),
+        }
+    }
+
+    pub fn encode(mut self, val: &InterfaceVal) -> Result<CdrBuf, failure::Error> {
+        self.encode_msg(&val.itf_type, Some(val))?;
+        Ok(self.buf)
+    }
+
+    fn encode_msg(
+        &mut self,
+        itf_type: &str,
+        val: Option<&InterfaceVal>,
+    ) -> Result<(), failure::Error> {
+        let itf_type = match itf_type.find('[') {
+            Some(idx) => &itf_type[..idx],
+            None => itf_type,
+        };
+        let params = get_params(self.type_maps, self.itf_info, itf_type)?;
+        // rosidl gives a message without fields a dummy structure_needs_at_least_one_member
+        if params.iter().all(|param| param.is_const) {
+            self.buf.put_prim(TYPE::UInt8, None);
+            return Ok(());
+        }
+        for param in params {
+            if param.is_const {
+                continue;
+            }
+            // message arrays are stored as repeated siblings with the same name
+            let children: Vec<&InterfaceVal> = match val {
+                Some(val) => val
+                    .val
+                    .iter()
+                    .filter_map(|v| match v {
+                        ValueType::Op(itf) if itf.itf_name == param.arg_name => Some(itf),
+                        _ => None,
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            if param.is_meta_type() {
+                self.encode_meta(param, children.first().copied());
+            } else if param.is_array {
+                match get_fixed_len(&param.arg_type) {
+                    Some(len) => {
+                        for idx in 0..len {
+                            self.encode_msg(&param.arg_type, children.get(idx).copied())?;
+                        }
+                    }
+                    None => {
+                        self.buf.put_len(children.len(), CdrKind::SeqLen);
+                        for child in children {
+                            self.encode_msg(&param.arg_type, Some(child))?;
+                        }
+                    }
+                }
+            } else {
+                self.encode_msg(&param.arg_type, children.first().copied())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_meta(&mut self, param: &InterfaceParam, val: Option<&InterfaceVal>) {
+        let leaf = val.and_then(|v| v.val.first());
+        if !param.is_array {
+            let ty = TYPE::from_str(param.arg_type.as_str());
+            let prim = leaf.and_then(get_prim);
+            self.buf.put_prim(ty, prim.as_ref());
+            return;
+        }
+
+        let inner_type = param.get_array_inner_type();
+        let items = match leaf {
+            Some(ValueType::Op5(array)) => get_array_prims(array),
+            _ => Vec::new(),
+        };
+        let len = match get_fixed_len(&param.arg_type) {
+            Some(len) => len,
+            None => {
+                self.buf.put_len(items.len(), CdrKind::SeqLen);
+                items.len()
+            }
+        };
+        for idx in 0..len {
+            self.buf.put_prim(inner_type, items.get(idx));
+        }
+    }
+}
+
+// same lookup as InterfaceVal::construct_itf_layers
+fn get_params<'a>(
+    type_maps: &HashMap<String, String>,
+    itf_info: &'a HashMap<String, Vec<InterfaceParam>>,
+    itf_type: &str,
+) -> Result<&'a Vec<InterfaceParam>, failure::Error> {
+    let begin_idx = itf_type.rfind('/').map(|idx| idx + 1).unwrap_or(0);
+    let end_idx = itf_type.find('[').unwrap_or(itf_type.len());
+    let type_short = &itf_type[begin_idx..end_idx];
+    let type_long = type_maps
+        .get(type_short)
+        .ok_or_else(|| failure::format_err!("unknown interface type: {}", itf_type))?;
+    itf_info
+        .get(type_long)
+        .ok_or_else(|| failure::format_err!("no schema for interface type: {}", type_long))
+}
+
+/// Reads a CDR_LE payload, e.g. one recorded in a bag, into a value tree shaped like the
+/// generated ones: message arrays as repeated siblings and leaves as `new_leaf` builds them.
+pub struct CdrDecoder<'a> {
+    type_maps: &'a HashMap<String, String>,
+    itf_info: &'a HashMap<String, Vec<InterfaceParam>>,
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> CdrDecoder<'a> {
+    pub fn new(
+        type_maps: &'a HashMap<String, String>,
+        itf_info: &'a HashMap<String, Vec<InterfaceParam>>,
+        data: &'a [u8],
+    ) -> Self {
+        CdrDecoder {
+            type_maps,
+            itf_info,
+            data,
+            pos: CDR_HEADER_LEN,
+        }
+    }
+
+    pub fn decode(
+        mut self,
+        itf_name: &str,
+        itf_type: &str,
+    ) -> Result<InterfaceVal, failure::Error> {
+        if self.data.len() < CDR_HEADER_LEN || self.data[..2] != CDR_LE_HEADER[..2] {
+            return Err(failure::err_msg("cdr: not a little endian payload"));
+        }
+        let mut itf = InterfaceVal::new(&itf_name.to_string(), &itf_type.to_string());
+        self.decode_msg(&mut itf)?;
+        Ok(itf)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], failure::Error> {
+        let end = self.pos.saturating_add(len);
+        if end > self.data.len() {
+            return Err(failure::format_err!(
+                "cdr: {} bytes past the end at {}",
+                len,
+                self.pos
+            ));
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    // alignment is relative to the end of the encapsulation header
+    fn take_scalar<const N: usize>(&mut self) -> Result<[u8; N], failure::Error> {
+        while (self.pos - CDR_HEADER_LEN) % N != 0 {
+            self.pos += 1;
+        }
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    // element count of a sequence, every element takes at least a byte
+    fn take_count(&mut self) -> Result<usize, failure::Error> {
+        let count = u32::from_le_bytes(self.take_scalar()?) as usize;
+        if count > self.data.len().saturating_sub(self.pos) {
+            return Err(failure::format_err!(
+                "cdr: sequence of {} at {}",
+                count,
+                self.pos
+            ));
+        }
+        Ok(count)
+    }
+
+    fn take_string(&mut self, wide: bool) -> Result<String, failure::Error> {
+        let len = u32::from_le_bytes(self.take_scalar()?) as usize;
+        if wide {
+            let body = self.take(len.saturating_mul(4))?;
+            return Ok(body
+                .chunks(4)
+                .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+                .map(|c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect());
+        }
+        let body = self.take(len)?;
+        let body = body.strip_suffix(&[0]).unwrap_or(body);
+        Ok(String::from_utf8_lossy(body).to_string())
+    }
+
+    fn decode_msg(&mut self, itf: &mut InterfaceVal) -> Result<(), failure::Error> {
+        let itf_type = itf.itf_type.clone();
+        let params = get_params(self.type_maps, self.itf_info, &itf_type)?;
+        if params.iter().all(|param| param.is_const) {
+            self.take(1)?;
+            return Ok(());
+        }
+        for param in params {
+            if param.is_const {
+                continue;
+            }
+            if param.is_meta_type() {
+                let mut field = InterfaceVal::new(&param.arg_name, &param.arg_type);
+                field.val.push(self.decode_meta(param)?);
+                itf.val.push(ValueType::Op(field));
+                continue;
+            }
+            let count = match (param.is_array, get_fixed_len(&param.arg_type)) {
+                (false, _) => 1,
+                (true, Some(len)) => len,
+                (true, None) => self.take_count()?,
+            };
+            for _ in 0..count {
+                let mut field = InterfaceVal::new(&param.arg_name, &param.arg_type);
+                self.decode_msg(&mut field)?;
+                itf.val.push(ValueType::Op(field));
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_meta(&mut self, param: &InterfaceParam) -> Result<ValueType, failure::Error> {
+        let leaf = |ty: TYPE| {
+            new_leaf(ty, 0)
+                .ok_or_else(|| failure::format_err!("cdr: unsupported type {}", param.arg_type))
+        };
+        if !param.is_array {
+            let mut val = leaf(TYPE::from_str(param.arg_type.as_str()))?;
+            self.decode_prim(&mut val)?;
+            return Ok(val);
+        }
+
+        let inner_type = param.get_array_inner_type();
+        let len = match get_fixed_len(&param.arg_type) {
+            Some(len) => len,
+            None => self.take_count()?,
+        };
+        let mut array = ArrayType::new(TYPE::ARRAY as usize, inner_type, len as u64);
+        if let TYPE::UInt8 | TYPE::Byte | TYPE::Char = inner_type {
+            array.set_bytes(self.take(len)?.to_vec());
+            return Ok(ValueType::Op5(array));
+        }
+        let mut items = Vec::new();
+        for _ in 0..len {
+            let mut val = leaf(inner_type)?;
+            self.decode_prim(&mut val)?;
+            items.push(val);
+        }
+        set_array_prims(&mut array, items);
+        Ok(ValueType::Op5(array))
+    }
+
+    fn decode_prim(&mut self, val: &mut ValueType) -> Result<(), failure::Error> {
+        match val {
+            ValueType::Op1(int) => {
+                let num = match int.get_pad() {
+                    -8 => i8::from_le_bytes(self.take_scalar()?) as u64,
+                    8 => u8::from_le_bytes(self.take_scalar()?) as u64,
+                    -16 => i16::from_le_bytes(self.take_scalar()?) as u64,
+                    16 => u16::from_le_bytes(self.take_scalar()?) as u64,
+                    -32 => i32::from_le_bytes(self.take_scalar()?) as u64,
+                    32 => u32::from_le_bytes(self.take_scalar()?) as u64,
+                    -64 => i64::from_le_bytes(self.take_scalar()?) as u64,
+                    _ => u64::from_le_bytes(self.take_scalar()?),
+                };
+                int.set_val(num);
+            }
+            ValueType::Op2(bool) => bool.set_val(self.take_scalar::<1>()?[0] != 0),
+            ValueType::Op3(double) => {
+                let num = match double.get_pad() {
+                    32 => f32::from_le_bytes(self.take_scalar()?) as f64,
+                    _ => f64::from_le_bytes(self.take_scalar()?),
+                };
+                double.set_val(num);
+            }
+            ValueType::Op4(chara) => chara.set_val(self.take_scalar::<1>()?[0] as char),
+            ValueType::Op6(string) => {
+                let wide = string.get_tyid() == TYPE::WString as usize;
+                string.set_val(self.take_string(wide)?);
+            }
+            ValueType::Op(_) | ValueType::Op5(_) => unreachable!("leaves only"),
+        }
+        Ok(())
+    }
+}
+
+// `float64[36]` has a fixed length, `float64[]` and `float64[<=36]` are sequences
+fn get_fixed_len(arg_type: &str) -> Option<usize> {
+    let begin = arg_type.find('[')?;
+    let end = arg_type.rfind(']')?;
+    arg_type[begin + 1..end].parse::<usize>().ok()
+}
+
+fn get_prim(val: &ValueType) -> Option<Prim> {
+    match val {
+        ValueType::Op1(int) => Some(Prim::Int(int.get_num())),
+        ValueType::Op2(bool) => Some(Prim::Int(bool.get_num())),
+        ValueType::Op3(double) => Some(Prim::Float(double.get_num())),
+        ValueType::Op4(chara) => Some(Prim::Int(chara.get_char() as u64)),
+        ValueType::Op6(string) => Some(Prim::Str(string.get_val())),
+        ValueType::Op(_) | ValueType::Op5(_) => None,
+    }
+}
+
+pub(crate) fn set_array_prims(array: &mut ArrayType, items: Vec<ValueType>) {
+    let (mut ints, mut bools, mut floats, mut strings) = (vec![], vec![], vec![], vec![]);
+    for item in items {
+        match item {
+            ValueType::Op1(int) => ints.push(int),
+            ValueType::Op2(bool) => bools.push(bool),
+            ValueType::Op3(double) => floats.push(double),
+            ValueType::Op6(string) => strings.push(string),
+            _ => {}
+        }
+    }
+    match array.get_inner_type() {
+        TYPE::String | TYPE::WString => array.set_string_array(strings),
+        TYPE::Float32 | TYPE::Float64 => array.set_float_array(floats),
+        TYPE::Bool => array.set_bool_array(bools),
+        _ => array.set_int_array(ints),
+    }
+}
+
+fn get_array_prims(array: &ArrayType) -> Vec<Prim> {
+    if !array.get_bytes().is_empty() {
+        return array
+            .get_bytes()
+            .iter()
+            .map(|byte| Prim::Int(*byte as u64))
+            .collect();
+    }
+    match array.get_inner_type() {
+        TYPE::String | TYPE::WString => array
+            .get_string_array()
+            .iter()
+            .map(|s| Prim::Str(s.get_val()))
+            .collect(),
+        TYPE::Byte | TYPE::Char => array
+            .get_char_array()
+            .iter()
+            .map(|c| Prim::Int(c.get_char() as u64))
+            .collect(),
+        TYPE::Float32 | TYPE::Float64 => array
+            .get_float_array()
+            .iter()
+            .map(|f| Prim::Float(f.get_num()))
+            .collect(),
+        TYPE::Bool => array
+            .get_bool_array()
+            .iter()
+            .map(|b| Prim::Int(b.get_num()))
+            .collect(),
+        _ => array
+            .get_int_array()
+            .iter()
+            .map(|i| Prim::Int(i.get_num()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::ty::{character, double, integer};
+    use rand::SeedableRng;
+
+    fn param(arg_type: &str, arg_name: &str) -> InterfaceParam {
+        let is_array = arg_type.contains('[');
+        let max_array_size = get_fixed_len(arg_type).unwrap_or(0) as i32;
+        InterfaceParam::new(
+            arg_type.to_string(),
+            arg_name.to_string(),
+            is_array,
+            max_array_size,
+            false,
+            String::new(),
+        )
+    }
+
+    fn schema() -> (
+        HashMap<String, String>,
+        HashMap<String, Vec<InterfaceParam>>,
+    ) {
+        let mut type_maps = HashMap::new();
+        let mut itf_info = HashMap::new();
+        let mut add = |long: &str, params: Vec<InterfaceParam>| {
+            type_maps.insert(
+                long.rsplit('/').next().unwrap().to_string(),
+                long.to_string(),
+            );
+            itf_info.insert(long.to_string(), params);
+        };
+        add("std_msgs/msg/String", vec![param("string", "data")]);
+        add(
+            "builtin_interfaces/msg/Time",
+            vec![param("int32", "sec"), param("uint32", "nanosec")],
+        );
+        add(
+            "std_msgs/msg/Header",
+            vec![
+                param("builtin_interfaces/Time", "stamp"),
+                param("string", "frame_id"),
+            ],
+        );
+        add(
+            "geometry_msgs/msg/Point",
+            vec![
+                param("float64", "x"),
+                param("float64", "y"),
+                param("float64", "z"),
+            ],
+        );
+        add(
+            "test_msgs/msg/Mixed",
+            vec![
+                param("uint8", "a"),
+                param("float64", "b"),
+                param("uint16[]", "c"),
+                param("string[2]", "d"),
+                param("Point[]", "points"),
+                param("Point[]", "empty"),
+                param("wstring", "w"),
+            ],
+        );
+        add("std_msgs/msg/Empty", vec![]);
+        add(
+            "test_msgs/msg/Wrapped",
+            vec![param("std_msgs/Empty", "empty"), param("uint32", "n")],
+        );
+        (type_maps, itf_info)
+    }
+
+    fn field(name: &str, ty: &str, val: ValueType) -> ValueType {
+        let mut itf = InterfaceVal::new(&name.to_string(), &ty.to_string());
+        itf.val.push(val);
+        ValueType::Op(itf)
+    }
+
+    fn int(ty: TYPE, val: u64, pad: i32) -> ValueType {
+        ValueType::Op1(integer::IntType::new(ty as usize, val, u64::MAX, 0, pad))
+    }
+
+    fn float(val: f64) -> ValueType {
+        ValueType::Op3(double::DoubleType::new(
+            TYPE::Float64 as usize,
+            val,
+            f64::MAX,
+            f64::MIN,
+            64,
+        ))
+    }
+
+    fn string(ty: TYPE, val: &str) -> ValueType {
+        ValueType::Op6(character::StringType::new(ty as usize, val.to_string(), 0))
+    }
+
+    fn point(name: &str, x: f64, y: f64, z: f64) -> ValueType {
+        let mut itf = InterfaceVal::new(&name.to_string(), &"Point[]".to_string());
+        itf.val.push(field("x", "float64", float(x)));
+        itf.val.push(field("y", "float64", float(y)));
+        itf.val.push(field("z", "float64", float(z)));
+        ValueType::Op(itf)
+    }
+
+    fn encode(itf_type: &str, val: Vec<ValueType>) -> CdrBuf {
+        let (type_maps, itf_info) = schema();
+        let mut itf = InterfaceVal::new(&"/test".to_string(), &itf_type.to_string());
+        itf.val = val;
+        CdrEncoder::new(&type_maps, &itf_info).encode(&itf).unwrap()
+    }
+
+    #[test]
+    fn string_reference() {
+        let buf = encode(
+            "std_msgs/msg/String",
+            vec![field("data", "string", string(TYPE::String, "hello"))],
+        );
+        assert_eq!(
+            buf.data,
+            b"\x00\x01\x00\x00\x06\x00\x00\x00hello\x00".to_vec()
+        );
+    }
+
+    #[test]
+    fn header_reference() {
+        let mut stamp =
+            InterfaceVal::new(&"stamp".to_string(), &"builtin_interfaces/Time".to_string());
+        stamp
+            .val
+            .push(field("sec", "int32", int(TYPE::Int32, 1, -32)));
+        stamp
+            .val
+            .push(field("nanosec", "uint32", int(TYPE::UInt32, 2, 32)));
+        let buf = encode(
+            "std_msgs/msg/Header",
+            vec![
+                ValueType::Op(stamp),
+                field("frame_id", "string", string(TYPE::String, "map")),
+            ],
+        );
+        let mut expect = CDR_LE_HEADER.to_vec();
+        expect.extend_from_slice(&[1, 0, 0, 0, 2, 0, 0, 0, 4, 0, 0, 0]);
+        expect.extend_from_slice(b"map\x00");
+        assert_eq!(buf.data, expect);
+    }
+
+    #[test]
+    fn point_reference() {
+        let (type_maps, itf_info) = schema();
+        let mut itf = InterfaceVal::new(
+            &"/point".to_string(),
+            &"geometry_msgs/msg/Point".to_string(),
+        );
+        if let ValueType::Op(p) = point("p", 1.0, 2.0, 3.0) {
+            itf.val = p.val;
+        }
+        let buf = CdrEncoder::new(&type_maps, &itf_info).encode(&itf).unwrap();
+        let mut expect = CDR_LE_HEADER.to_vec();
+        for v in [1.0f64, 2.0, 3.0] {
+            expect.extend_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(buf.data, expect);
+    }
+
+    #[test]
+    fn alignment_and_sequences() {
+        let mut c = ArrayType::new(TYPE::ARRAY as usize, TYPE::UInt16, 2);
+        c.set_int_array(vec![
+            integer::IntType::new(0, 0x0102, u16::MAX as u64, 0, 16),
+            integer::IntType::new(0, 0x0304, u16::MAX as u64, 0, 16),
+        ]);
+        let mut d = ArrayType::new(TYPE::ARRAY as usize, TYPE::String, 1);
+        d.set_string_array(vec![character::StringType::new(0, "a".to_string(), 1)]);
+        let buf = encode(
+            "test_msgs/msg/Mixed",
+            vec![
+                field("a", "uint8", int(TYPE::UInt8, 7, 8)),
+                field("b", "float64", float(0.5)),
+                field("c", "uint16[]", ValueType::Op5(c)),
+                field("d", "string[2]", ValueType::Op5(d)),
+                point("points", 1.0, 2.0, 3.0),
+                field("w", "wstring", string(TYPE::WString, "\u{e9}")),
+            ],
+        );
+
+        let mut expect = CDR_LE_HEADER.to_vec();
+        expect.extend_from_slice(&[7, 0, 0, 0, 0, 0, 0, 0]);
+        expect.extend_from_slice(&0.5f64.to_le_bytes());
+        expect.extend_from_slice(&[2, 0, 0, 0, 0x02, 0x01, 0x04, 0x03]);
+        // fixed array of strings, the missing element is an empty string
+        expect.extend_from_slice(&[2, 0, 0, 0, b'a', 0, 0, 0, 1, 0, 0, 0, 0]);
+        expect.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]);
+        for v in [1.0f64, 2.0, 3.0] {
+            expect.extend_from_slice(&v.to_le_bytes());
+        }
+        // the empty message sequence is not in the value tree but still on the wire
+        expect.extend_from_slice(&[0, 0, 0, 0]);
+        expect.extend_from_slice(&[1, 0, 0, 0, 0xe9, 0, 0, 0]);
+        assert_eq!(buf.data, expect);
+
+        let lens: Vec<usize> = buf
+            .fields
+            .iter()
+            .filter(|f| f.kind == CdrKind::SeqLen)
+            .map(|f| f.offset)
+            .collect();
+        assert_eq!(lens, vec![20, 44, 76]);
+    }
+
+    #[test]
+    fn decode_round_trip() {
+        let (type_maps, itf_info) = schema();
+        let mut c = ArrayType::new(TYPE::ARRAY as usize, TYPE::UInt16, 1);
+        c.set_int_array(vec![integer::IntType::new(0, 9, u16::MAX as u64, 0, 16)]);
+        let origin = encode(
+            "test_msgs/msg/Mixed",
+            vec![
+                field("a", "uint8", int(TYPE::UInt8, 7, 8)),
+                field("c", "uint16[]", ValueType::Op5(c)),
+                point("points", 1.0, 2.0, 3.0),
+                point("points", 4.0, 5.0, 6.0),
+                field("w", "wstring", string(TYPE::WString, "w\u{e9}")),
+            ],
+        );
+        let itf = CdrDecoder::new(&type_maps, &itf_info, &origin.data)
+            .decode("/test", "test_msgs/msg/Mixed")
+            .unwrap();
+        let points = itf
+            .val
+            .iter()
+            .filter(|val| matches!(val, ValueType::Op(p) if p.itf_name == "points"))
+            .count();
+        assert_eq!(points, 2);
+        let buf = CdrEncoder::new(&type_maps, &itf_info).encode(&itf).unwrap();
+        assert_eq!(buf.data, origin.data);
+
+        let truncated = &origin.data[..origin.data.len() - 2];
+        assert!(CdrDecoder::new(&type_maps, &itf_info, truncated)
+            .decode("/test", "test_msgs/msg/Mixed")
+            .is_err());
+    }
+
+    #[test]
+    fn empty_message_has_a_dummy_member() {
+        let (type_maps, itf_info) = schema();
+        let buf = encode("std_msgs/msg/Empty", Vec::new());
+        let mut expect = CDR_LE_HEADER.to_vec();
+        expect.push(0);
+        assert_eq!(buf.data, expect);
+
+        // the dummy of a nested empty message is aligned like any uint8
+        let buf = encode(
+            "test_msgs/msg/Wrapped",
+            vec![field("n", "uint32", int(TYPE::UInt32, 5, 32))],
+        );
+        let mut expect = CDR_LE_HEADER.to_vec();
+        expect.extend_from_slice(&[0, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(buf.data, expect);
+        let itf = CdrDecoder::new(&type_maps, &itf_info, &buf.data)
+            .decode("/test", "test_msgs/msg/Wrapped")
+            .unwrap();
+        let buf = CdrEncoder::new(&type_maps, &itf_info).encode(&itf).unwrap();
+        assert_eq!(buf.data, expect);
+    }
+
+    #[test]
+    fn mutate_keeps_layout_consistent() {
+        let mut rng = RngType::seed_from_u64(0);
+        let origin = encode(
+            "std_msgs/msg/Header",
+            vec![field(
+                "frame_id",
+                "string",
+                string(TYPE::String, "base_link"),
+            )],
+        );
+        let mut changed = 0;
+        for _ in 0..1000 {
+            let mut buf = origin.clone();
+            buf.mutate(&mut rng);
+            assert!(buf.data.len() >= CDR_HEADER_LEN);
+            for field in buf.fields.iter() {
+                assert!(field.offset + field.len <= buf.data.len());
+            }
+            if buf.data != origin.data {
+                changed += 1;
+            }
+        }
+        assert!(changed > 900);
+    }
+}
@@ -0,0 +1,325 @@
+//! Simulated time. With `use_sim_time:=true` the nodes follow `/clock`, which only the fuzzer
+//! publishes: a baseline running at the wall clock rate for the whole boot, and the streams of
+//! ITF::Clock calls that jump, freeze and rescale it in between.
+use super::{cdr::CDR_LE_HEADER, timing::MAX_DURATION_MS, RngType};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const CLOCK_TOPIC: &str = "/clock";
+pub const CLOCK_TYPE: &str = "rosgraph_msgs/msg/Clock";
+pub const MAX_EVENTS: usize = 16;
+// a day either way, the helper keeps the time within builtin_interfaces/Time
+pub const MAX_JUMP_MS: i64 = 86_400_000;
+pub const MAX_CLOCK_RATE: f64 = 100.0;
+const INTERESTING_RATE: [f64; 6] = [0.01, 0.5, 1.0, 2.0, 10.0, 100.0];
+const INTERESTING_JUMP_MS: [i64; 8] = [1, 10, 100, 1000, 5000, 60_000, 3_600_000, MAX_JUMP_MS];
+const INTERESTING_PERIOD_MS: [u64; 4] = [1, 10, 50, 100];
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum ClockEvent {
+    // advance `rate` simulated seconds per wall second
+    Run { rate: f64, duration_ms: u64 },
+    // one tick `delta_ms` away from the last one, backwards when negative
+    Jump { delta_ms: i64 },
+    // keep publishing the same time
+    Freeze { duration_ms: u64 },
+}
+
+impl ClockEvent {
+    fn generate(rng: &mut RngType) -> ClockEvent {
+        let duration_ms = rng.gen_range(1..=MAX_DURATION_MS / 4);
+        match rng.gen_range(0..3) {
+            0 => ClockEvent::Run {
+                rate: INTERESTING_RATE[rng.gen_range(0..INTERESTING_RATE.len())],
+                duration_ms,
+            },
+            1 => {
+                let delta_ms = INTERESTING_JUMP_MS[rng.gen_range(0..INTERESTING_JUMP_MS.len())];
+                ClockEvent::Jump {
+                    delta_ms: match rng.gen() {
+                        true => delta_ms,
+                        false => -delta_ms,
+                    },
+                }
+            }
+            _ => ClockEvent::Freeze { duration_ms },
+        }
+    }
+
+    pub fn get_duration_ms(&self) -> u64 {
+        match self {
+            ClockEvent::Run { duration_ms, .. } | ClockEvent::Freeze { duration_ms } => {
+                *duration_ms
+            }
+            ClockEvent::Jump { .. } => 0,
+        }
+    }
+
+    pub fn get_phase(&self) -> ClockPhase {
+        match self {
+            ClockEvent::Run { rate, .. } if *rate == 1.0 => ClockPhase::Normal,
+            ClockEvent::Run { .. } => ClockPhase::Scaled,
+            ClockEvent::Jump { delta_ms } if *delta_ms < 0 => ClockPhase::Backward,
+            ClockEvent::Jump { .. } => ClockPhase::Forward,
+            ClockEvent::Freeze { .. } => ClockPhase::Frozen,
+        }
+    }
+}
+
+/// Events played in order on `/clock`, a tick published every `period_ms` while time runs or
+/// stands still. The baseline takes over again from the last published time.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClockStream {
+    pub period_ms: u64,
+    pub events: Vec<ClockEvent>,
+}
+
+impl Default for ClockStream {
+    fn default() -> Self {
+        ClockStream {
+            period_ms: 10,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl ClockStream {
+    pub fn generate(rng: &mut RngType) -> ClockStream {
+        let mut stream = ClockStream {
+            period_ms: INTERESTING_PERIOD_MS[rng.gen_range(0..INTERESTING_PERIOD_MS.len())],
+            events: (0..rng.gen_range(1..=4))
+                .map(|_| ClockEvent::generate(rng))
+                .collect(),
+        };
+        stream.clamp();
+        stream
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..4) {
+            0 if !self.events.is_empty() => {
+                let idx = rng.gen_range(0..self.events.len());
+                self.events[idx] = ClockEvent::generate(rng);
+            }
+            1 if self.events.len() > 1 => {
+                self.events.remove(rng.gen_range(0..self.events.len()));
+            }
+            2 => {
+                self.period_ms =
+                    INTERESTING_PERIOD_MS[rng.gen_range(0..INTERESTING_PERIOD_MS.len())]
+            }
+            _ => {
+                let idx = rng.gen_range(0..=self.events.len());
+                self.events.insert(idx, ClockEvent::generate(rng));
+            }
+        }
+        self.clamp();
+    }
+
+    pub fn get_duration_ms(&self) -> u64 {
+        self.events
+            .iter()
+            .map(|event| event.get_duration_ms())
+            .sum()
+    }
+
+    pub fn describe(&self) -> String {
+        let events: Vec<String> = self
+            .events
+            .iter()
+            .map(|event| match event {
+                ClockEvent::Run { rate, duration_ms } => format!("run x{} {}ms", rate, duration_ms),
+                ClockEvent::Jump { delta_ms } => format!("jump {:+}ms", delta_ms),
+                ClockEvent::Freeze { duration_ms } => format!("freeze {}ms", duration_ms),
+            })
+            .collect();
+        format!(
+            "{} every {}ms: {}",
+            CLOCK_TOPIC,
+            self.period_ms,
+            events.join(", ")
+        )
+    }
+
+    // the ticks the helper publishes, wall_ns from the start of the stream and simulated time
+    // from sim_ns
+    pub fn plan_ticks(&self, sim_ns: u64) -> Vec<ClockTick> {
+        let period_ns = self.period_ms * 1_000_000;
+        let mut ticks = Vec::new();
+        let (mut wall_ns, mut sim_ns) = (0, sim_ns);
+        for (event_idx, event) in self.events.iter().enumerate() {
+            let rate = match event {
+                ClockEvent::Run { rate, .. } => *rate,
+                ClockEvent::Freeze { .. } => 0.0,
+                ClockEvent::Jump { delta_ms } => {
+                    sim_ns = (sim_ns as i64 + delta_ms * 1_000_000).max(0) as u64;
+                    ticks.push(ClockTick {
+                        wall_ns,
+                        sim_ns,
+                        event: event_idx,
+                    });
+                    continue;
+                }
+            };
+            for _ in 0..(event.get_duration_ms() / self.period_ms).max(1) {
+                ticks.push(ClockTick {
+                    wall_ns,
+                    sim_ns,
+                    event: event_idx,
+                });
+                wall_ns += period_ns;
+                sim_ns += (period_ns as f64 * rate) as u64;
+            }
+        }
+        ticks
+    }
+
+    // keep the stream well inside the execution timeout, like a topic stream
+    fn clamp(&mut self) {
+        self.events.truncate(MAX_EVENTS);
+        self.period_ms = self.period_ms.clamp(1, 1000);
+        for event in self.events.iter_mut() {
+            match event {
+                ClockEvent::Run { rate, duration_ms } => {
+                    *rate = rate.clamp(1.0 / MAX_CLOCK_RATE, MAX_CLOCK_RATE);
+                    *duration_ms = (*duration_ms).clamp(1, MAX_DURATION_MS);
+                }
+                ClockEvent::Jump { delta_ms } => {
+                    *delta_ms = (*delta_ms).clamp(-MAX_JUMP_MS, MAX_JUMP_MS)
+                }
+                ClockEvent::Freeze { duration_ms } => {
+                    *duration_ms = (*duration_ms).clamp(1, MAX_DURATION_MS)
+                }
+            }
+        }
+        while self.get_duration_ms() > MAX_DURATION_MS {
+            let longest = (0..self.events.len())
+                .max_by_key(|&idx| self.events[idx].get_duration_ms())
+                .unwrap();
+            self.events.remove(longest);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ClockPhase {
+    Normal,
+    // running faster or slower than the wall clock
+    Scaled,
+    Frozen,
+    Forward,
+    Backward,
+}
+
+/// One time published by the helper, reported as `clock <wall_ns> <sim_ns> <event>`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct ClockTick {
+    pub wall_ns: u64,
+    pub sim_ns: u64,
+    pub event: usize,
+}
+
+// rosgraph_msgs/msg/Clock holding sim_ns, as the helper publishes it
+pub fn get_clock_cdr(sim_ns: u64) -> Vec<u8> {
+    let mut data = CDR_LE_HEADER.to_vec();
+    data.extend_from_slice(&((sim_ns / 1_000_000_000) as i32).to_le_bytes());
+    data.extend_from_slice(&((sim_ns % 1_000_000_000) as u32).to_le_bytes());
+    data
+}
+
+// ticks in the output of an ITF::Clock call, in publishing order
+pub fn parse_ticks(output: &str) -> Vec<ClockTick> {
+    let mut ticks = Vec::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 || fields[0] != "clock" {
+            continue;
+        }
+        if let (Ok(wall_ns), Ok(sim_ns), Ok(event)) =
+            (fields[1].parse(), fields[2].parse(), fields[3].parse())
+        {
+            ticks.push(ClockTick {
+                wall_ns,
+                sim_ns,
+                event,
+            });
+        }
+    }
+    ticks
+}
+
+/// A timer callback start matched with the last time published before it.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ClockFiring {
+    pub cb_id: u64,
+    pub wall_ns: u64,
+    pub sim_ns: u64,
+    pub phase: ClockPhase,
+    // fired again without the simulated time moving forward since its last firing
+    pub stale: bool,
+}
+
+// the tick in effect at wall_ns and the phase of the event that published it
+pub fn get_tick_at<'a>(
+    stream: &ClockStream,
+    ticks: &'a [ClockTick],
+    wall_ns: u64,
+) -> Option<(&'a ClockTick, ClockPhase)> {
+    let tick = ticks.iter().rev().find(|tick| tick.wall_ns <= wall_ns)?;
+    let phase = stream
+        .events
+        .get(tick.event)
+        .map(|event| event.get_phase())
+        .unwrap_or(ClockPhase::Normal);
+    Some((tick, phase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn stream_stays_bounded_and_ticks_map_to_events() {
+        let mut rng = RngType::seed_from_u64(0);
+        let mut stream = ClockStream::generate(&mut rng);
+        for _ in 0..64 {
+            stream.mutate(&mut rng);
+            assert!(!stream.events.is_empty() && stream.events.len() <= MAX_EVENTS);
+            assert!(stream.get_duration_ms() <= MAX_DURATION_MS);
+        }
+
+        let stream = ClockStream {
+            period_ms: 10,
+            events: vec![
+                ClockEvent::Run {
+                    rate: 1.0,
+                    duration_ms: 20,
+                },
+                ClockEvent::Jump { delta_ms: -1000 },
+                ClockEvent::Freeze { duration_ms: 20 },
+            ],
+        };
+        let ticks = parse_ticks(
+            "published 4 clock ticks\nclock 100 5000 0\nclock 110 5010 0\n\
+             clock 111 4010 1\nclock 121 4010 2\n",
+        );
+        assert_eq!(ticks.len(), 4);
+        assert!(get_tick_at(&stream, &ticks, 99).is_none());
+        assert_eq!(
+            get_tick_at(&stream, &ticks, 105).unwrap().1,
+            ClockPhase::Normal
+        );
+        assert_eq!(
+            get_tick_at(&stream, &ticks, 115).unwrap().1,
+            ClockPhase::Backward
+        );
+        let (tick, phase) = get_tick_at(&stream, &ticks, 200).unwrap();
+        assert_eq!((tick.sim_ns, phase), (4010, ClockPhase::Frozen));
+
+        let planned = stream.plan_ticks(5_000_000_000);
+        let sim: Vec<u64> = planned.iter().map(|tick| tick.sim_ns / 1_000_000).collect();
+        assert_eq!(sim, vec![5000, 5010, 4020, 4020, 4020]);
+        assert_eq!(planned[4].wall_ns, 30_000_000);
+    }
+}
@@ -0,0 +1,661 @@
+use super::{
+    call::{CallArgs, ROS2_BIN},
+    interface::ITF,
+};
+use failure::Fail;
+use std::{
+    collections::VecDeque,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use util::fuzzer_info;
+use wait_timeout::ChildExt;
+
+pub const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+pub const HELPER_SCRIPT: &str = "./sys/helper/inject.py";
+pub const HELPER_LOG: &str = "instance_err";
+
+/// A failure of the injection backend itself, e.g. a spawn error or a broken pipe to the
+/// helper. The target may be fine, so it is not saved as a crash.
+#[derive(Debug, Fail)]
+#[fail(display = "executor error: {}", _0)]
+pub struct ExecutorError(pub String);
+
+fn backend_error(e: failure::Error) -> failure::Error {
+    match e.downcast_ref::<ExecutorError>() {
+        Some(_) => e,
+        None => ExecutorError(e.to_string()).into(),
+    }
+}
+
+// what the backend reports back for one call, stdout and stderr are merged
+#[derive(Debug, Default, Clone)]
+pub struct ExecOutput {
+    pub output: String,
+    pub timeout: bool,
+}
+
+/// Injection backend used by `Prog::exec_one`. A backend is started once per
+/// boot of the ros application and stopped before it is killed.
+pub trait Executor: std::fmt::Debug + Send + Sync {
+    fn start(&mut self, _shm_dir: &str) -> Result<(), failure::Error> {
+        Ok(())
+    }
+    fn exec(&mut self, call: &CallArgs, shm_dir: &str) -> Result<ExecOutput, failure::Error>;
+    // run the calls concurrently, each one started offset_us after the first, one output per
+    // call in the given order. Backends without concurrency run them one after the other.
+    fn exec_batch(
+        &mut self,
+        calls: &[(CallArgs, u64)],
+        shm_dir: &str,
+    ) -> Result<Vec<ExecOutput>, failure::Error> {
+        let mut outputs = Vec::new();
+        for (call, _) in calls.iter() {
+            outputs.push(self.exec(call, shm_dir)?);
+        }
+        Ok(outputs)
+    }
+    fn stop(&mut self) {}
+    // whether `CallArgs::raw` is published as is
+    fn support_raw(&self) -> bool {
+        false
+    }
+    // whether ITF::Impostor servers can be stood up
+    fn support_server(&self) -> bool {
+        false
+    }
+    // whether /clock is published for nodes on simulated time, see clock.rs
+    fn support_clock(&self) -> bool {
+        false
+    }
+}
+
+pub fn executor_from_str(name: &str) -> Box<dyn Executor> {
+    match name {
+        "helper" => Box::new(HelperExecutor::new(
+            "python3".to_string(),
+            vec![HELPER_SCRIPT.to_string()],
+        )),
+        "mock" => Box::new(MockExecutor::default()),
+        _ => Box::new(CliExecutor::default()),
+    }
+}
+
+// spawn one ros2 cli process per call, slow but needs nothing besides ros2
+#[derive(Debug)]
+pub struct CliExecutor {
+    pub timeout: Duration,
+}
+impl Default for CliExecutor {
+    fn default() -> Self {
+        CliExecutor {
+            timeout: EXEC_TIMEOUT,
+        }
+    }
+}
+
+impl CliExecutor {
+    fn spawn(&self, call: &CallArgs, shm_dir: &str) -> Result<Child, failure::Error> {
+        if call.itf == ITF::Impostor {
+            return Err(ExecutorError("cli cannot serve an impostor".to_string()).into());
+        }
+        if call.itf == ITF::Clock {
+            return Err(ExecutorError("cli cannot drive the clock".to_string()).into());
+        }
+        if let (Some(path), Some(params)) = (call.get_param_path(shm_dir), &call.params) {
+            fs::write(path, params.get_param_file(&call.node_name))?;
+        }
+        Ok(Command::new(ROS2_BIN)
+            .env("SHM_PATH", shm_dir)
+            .args(call.get_argv(shm_dir))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?)
+    }
+
+    fn collect(
+        &self,
+        mut send_input_cmd: Child,
+        timeout: Duration,
+    ) -> Result<ExecOutput, failure::Error> {
+        let mut res = ExecOutput::default();
+        match send_input_cmd.wait_timeout(timeout)? {
+            Some(_) => {
+                send_input_cmd
+                    .stdout
+                    .take()
+                    .expect("filed to open stdout")
+                    .read_to_string(&mut res.output)?;
+                let mut input_err = String::new();
+                send_input_cmd
+                    .stderr
+                    .take()
+                    .expect("filed to open stderr")
+                    .read_to_string(&mut input_err)?;
+                res.output.push_str(&input_err);
+            }
+            None => {
+                // only stdout is kept on timeout, it tells what the cli waits for
+                res.timeout = true;
+                send_input_cmd.kill()?;
+                send_input_cmd
+                    .stdout
+                    .take()
+                    .expect("filed to open stdout")
+                    .read_to_string(&mut res.output)?;
+            }
+        }
+        Ok(res)
+    }
+
+    // spawn every call at its offset, then collect them in the given order
+    fn run_batch(
+        &self,
+        calls: &[(CallArgs, u64)],
+        shm_dir: &str,
+    ) -> Result<Vec<ExecOutput>, failure::Error> {
+        let group_start = Instant::now();
+        let mut order: Vec<usize> = (0..calls.len()).collect();
+        order.sort_by_key(|&idx| calls[idx].1);
+
+        let mut children: Vec<Option<Child>> = calls.iter().map(|_| None).collect();
+        for idx in order {
+            let offset = Duration::from_micros(calls[idx].1);
+            if let Some(wait) = offset.checked_sub(group_start.elapsed()) {
+                thread::sleep(wait);
+            }
+            children[idx] = Some(self.spawn(&calls[idx].0, shm_dir)?);
+        }
+
+        // every call gets the full timeout counted from the start of the last one
+        let last_offset = calls.iter().map(|call| call.1).max().unwrap_or(0);
+        let deadline = Duration::from_micros(last_offset) + self.timeout;
+        let mut outputs = Vec::new();
+        for child in children.into_iter().flatten() {
+            let timeout = deadline.saturating_sub(group_start.elapsed());
+            outputs.push(self.collect(child, timeout)?);
+        }
+        Ok(outputs)
+    }
+}
+
+// the param file of a call is only read while the cli runs
+fn remove_param_file(call: &CallArgs, shm_dir: &str) {
+    if let Some(path) = call.get_param_path(shm_dir) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+impl Executor for CliExecutor {
+    fn exec(&mut self, call: &CallArgs, shm_dir: &str) -> Result<ExecOutput, failure::Error> {
+        let res = self
+            .spawn(call, shm_dir)
+            .and_then(|child| self.collect(child, self.timeout));
+        remove_param_file(call, shm_dir);
+        res.map_err(backend_error)
+    }
+
+    fn exec_batch(
+        &mut self,
+        calls: &[(CallArgs, u64)],
+        shm_dir: &str,
+    ) -> Result<Vec<ExecOutput>, failure::Error> {
+        let res = self.run_batch(calls, shm_dir);
+        for (call, _) in calls.iter() {
+            remove_param_file(call, shm_dir);
+        }
+        res.map_err(backend_error)
+    }
+}
+
+/// Long-lived injection process speaking json lines: one serialized
+/// `CallArgs` per line on stdin, one `{"output": ..., "timeout": ...}` per
+/// line on stdout. Endpoints are created once and reused across calls.
+#[derive(Debug)]
+pub struct HelperExecutor {
+    pub program: String,
+    pub args: Vec<String>,
+    pub timeout: Duration,
+    child: Option<Child>,
+    stdin: Option<ChildStdin>,
+    // behind a mutex so the backend can live in the shared fuzz manager
+    lines: Option<Mutex<Receiver<String>>>,
+    shm_dir: String,
+}
+
+impl HelperExecutor {
+    pub fn new(program: String, args: Vec<String>) -> Self {
+        HelperExecutor {
+            program,
+            args,
+            timeout: EXEC_TIMEOUT,
+            child: None,
+            stdin: None,
+            lines: None,
+            shm_dir: String::new(),
+        }
+    }
+
+    pub fn is_running(&mut self) -> bool {
+        match self.child.as_mut() {
+            Some(child) => matches!(child.try_wait(), Ok(None)),
+            None => false,
+        }
+    }
+
+    // send one request line and wait for its reply, None when the helper is stuck
+    fn request(
+        &mut self,
+        mut request: String,
+        shm_dir: &str,
+        timeout: Duration,
+    ) -> Result<Option<serde_json::Value>, failure::Error> {
+        if !self.is_running() || self.shm_dir != shm_dir {
+            self.start(shm_dir)?;
+        }
+
+        request.push('\n');
+        let sent = match self.stdin.as_mut() {
+            Some(stdin) => stdin
+                .write_all(request.as_bytes())
+                .and_then(|_| stdin.flush())
+                .is_ok(),
+            None => false,
+        };
+        if !sent {
+            self.stop();
+            return Err(ExecutorError("injection helper exited".to_string()).into());
+        }
+
+        let reply = match self.lines.as_ref() {
+            Some(lines) => lines.lock().unwrap().recv_timeout(timeout),
+            None => Err(RecvTimeoutError::Disconnected),
+        };
+        match reply {
+            Ok(line) => Ok(Some(serde_json::from_str(&line)?)),
+            Err(RecvTimeoutError::Timeout) => {
+                // the helper is stuck inside the call, restart it on next exec
+                self.stop();
+                Ok(None)
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.stop();
+                Err(ExecutorError("injection helper exited".to_string()).into())
+            }
+        }
+    }
+}
+
+fn reply_output(reply: &serde_json::Value) -> ExecOutput {
+    ExecOutput {
+        output: reply["output"].as_str().unwrap_or_default().to_string(),
+        timeout: reply["timeout"].as_bool().unwrap_or(false),
+    }
+}
+
+impl Executor for HelperExecutor {
+    fn start(&mut self, shm_dir: &str) -> Result<(), failure::Error> {
+        self.stop();
+        fuzzer_info!("start injection helper: {} {:?}", self.program, self.args);
+        // tracebacks of the helper go next to the launch logs of the instance
+        let stderr = Path::new(shm_dir)
+            .parent()
+            .map(|work_dir| work_dir.join(HELPER_LOG))
+            .and_then(|log| OpenOptions::new().create(true).append(true).open(log).ok());
+        let mut child = Command::new(&self.program)
+            .env("SHM_PATH", shm_dir)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(match stderr {
+                Some(log) => Stdio::from(log),
+                None => Stdio::null(),
+            })
+            .spawn()?;
+
+        // the reader thread lets exec wait on a reply with a timeout
+        let stdout = child.stdout.take().expect("filed to open stdout");
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        self.stdin = child.stdin.take();
+        self.lines = Some(Mutex::new(receiver));
+        self.child = Some(child);
+        self.shm_dir = shm_dir.to_string();
+        Ok(())
+    }
+
+    fn exec(&mut self, call: &CallArgs, shm_dir: &str) -> Result<ExecOutput, failure::Error> {
+        let request = serde_json::to_string(call)?;
+        match self
+            .request(request, shm_dir, self.timeout)
+            .map_err(backend_error)?
+        {
+            Some(reply) => Ok(reply_output(&reply)),
+            None => Ok(ExecOutput {
+                output: String::new(),
+                timeout: true,
+            }),
+        }
+    }
+
+    // the helper runs a batch in threads and answers {"outputs": [...]} once all are done
+    fn exec_batch(
+        &mut self,
+        calls: &[(CallArgs, u64)],
+        shm_dir: &str,
+    ) -> Result<Vec<ExecOutput>, failure::Error> {
+        let batch: Vec<serde_json::Value> = calls
+            .iter()
+            .map(|(call, offset_us)| serde_json::json!({"offset_us": offset_us, "call": call}))
+            .collect();
+        let request = serde_json::json!({ "batch": batch }).to_string();
+        let last_offset = calls.iter().map(|call| call.1).max().unwrap_or(0);
+        let timeout = Duration::from_micros(last_offset) + self.timeout;
+
+        let mut outputs: Vec<ExecOutput> = match self
+            .request(request, shm_dir, timeout)
+            .map_err(backend_error)?
+        {
+            Some(reply) => match reply["outputs"].as_array() {
+                Some(replies) => replies.iter().map(reply_output).collect(),
+                None => Vec::new(),
+            },
+            None => Vec::new(),
+        };
+        // calls without an answer are still stuck in the helper
+        outputs.resize(
+            calls.len(),
+            ExecOutput {
+                output: String::new(),
+                timeout: true,
+            },
+        );
+        Ok(outputs)
+    }
+
+    fn support_raw(&self) -> bool {
+        true
+    }
+
+    fn support_server(&self) -> bool {
+        true
+    }
+
+    fn support_clock(&self) -> bool {
+        true
+    }
+
+    fn stop(&mut self) {
+        self.stdin = None;
+        self.lines = None;
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Drop for HelperExecutor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// what the mock saw and what it answers next
+#[derive(Debug, Default)]
+pub struct MockState {
+    pub history: Vec<CallArgs>,
+    // size of every batch, a single call is not a batch
+    pub batches: Vec<usize>,
+    // Err fails the call in the backend instead of the target
+    pub outputs: VecDeque<Result<ExecOutput, String>>,
+    pub started: usize,
+}
+
+// records every call and replays scripted outputs, no ros needed. Clones share the state, so
+// a test keeps a handle on the backend it hands to a target.
+#[derive(Debug, Default, Clone)]
+pub struct MockExecutor {
+    pub state: Arc<Mutex<MockState>>,
+}
+
+impl MockExecutor {
+    pub fn push_output(&mut self, output: &str, timeout: bool) {
+        self.state.lock().unwrap().outputs.push_back(Ok(ExecOutput {
+            output: output.to_string(),
+            timeout,
+        }));
+    }
+
+    pub fn push_failure(&mut self, reason: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .outputs
+            .push_back(Err(reason.to_string()));
+    }
+
+    pub fn get_history(&self) -> Vec<CallArgs> {
+        self.state.lock().unwrap().history.clone()
+    }
+}
+
+impl Executor for MockExecutor {
+    fn start(&mut self, _shm_dir: &str) -> Result<(), failure::Error> {
+        self.state.lock().unwrap().started += 1;
+        Ok(())
+    }
+
+    fn exec(&mut self, call: &CallArgs, _shm_dir: &str) -> Result<ExecOutput, failure::Error> {
+        let mut state = self.state.lock().unwrap();
+        state.history.push(call.clone());
+        match state.outputs.pop_front() {
+            Some(Err(reason)) => Err(ExecutorError(reason).into()),
+            Some(Ok(output)) => Ok(output),
+            None => Ok(ExecOutput::default()),
+        }
+    }
+
+    fn exec_batch(
+        &mut self,
+        calls: &[(CallArgs, u64)],
+        shm_dir: &str,
+    ) -> Result<Vec<ExecOutput>, failure::Error> {
+        self.state.lock().unwrap().batches.push(calls.len());
+        let mut outputs = Vec::new();
+        for (call, _) in calls.iter() {
+            outputs.push(self.exec(call, shm_dir)?);
+        }
+        Ok(outputs)
+    }
+
+    fn support_raw(&self) -> bool {
+        true
+    }
+
+    fn support_server(&self) -> bool {
+        true
+    }
+
+    fn support_clock(&self) -> bool {
+        true
+    }
+}
+
+// request line a stand-in helper receives for the calls, a single call or a batch
+#[cfg(test)]
+pub(crate) fn get_helper_request(calls: &[(CallArgs, u64)], name: &str) -> serde_json::Value {
+    let work_dir =
+        std::env::temp_dir().join(format!("feryr-request-{}-{}", name, std::process::id()));
+    let shm_dir = work_dir.join("shm");
+    fs::create_dir_all(&shm_dir).unwrap();
+    let script = "while IFS= read -r l; do printf '%s\\n' \"$l\" > \"$SHM_PATH/request\"; \
+                  echo '{}'; done";
+    let mut executor =
+        HelperExecutor::new("sh".to_string(), vec!["-c".to_string(), script.to_string()]);
+    let shm_path = shm_dir.to_string_lossy();
+    match calls {
+        [(call, _)] => executor.exec(call, &shm_path).map(|_| ()),
+        _ => executor.exec_batch(calls, &shm_path).map(|_| ()),
+    }
+    .unwrap();
+    executor.stop();
+    let request = fs::read_to_string(shm_dir.join("request")).unwrap();
+    fs::remove_dir_all(&work_dir).unwrap();
+    serde_json::from_str(&request).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::param::{ParamUpdate, ParamValue};
+
+    fn topic_call(payload: &str) -> CallArgs {
+        CallArgs::new(
+            ITF::Topic,
+            "/talker",
+            "/chatter",
+            "std_msgs/msg/String",
+            payload.to_string(),
+        )
+    }
+
+    // shm directory of a scratch instance, the helper logs next to it
+    fn shm_dir(name: &str) -> String {
+        let dir = std::env::temp_dir()
+            .join(format!("feryr-executor-{}-{}", name, std::process::id()))
+            .join("shm");
+        fs::create_dir_all(&dir).unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn mock_records_calls() {
+        let mut executor = MockExecutor::default();
+        executor.push_output("publishing #1", false);
+        let res = executor.exec(&topic_call("{data: \"a\"}"), "/tmp").unwrap();
+        assert_eq!(res.output, "publishing #1");
+        let res = executor.exec(&topic_call("{data: \"b\"}"), "/tmp").unwrap();
+        assert!(res.output.is_empty() && !res.timeout);
+        let history = executor.get_history();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].payload, "{data: \"b\"}");
+
+        // a clone handed to a target still reports to the test
+        let mut backend = executor.clone();
+        executor.push_failure("broken pipe");
+        let e = backend.exec(&topic_call("{}"), "/tmp").unwrap_err();
+        assert!(e.downcast_ref::<ExecutorError>().is_some());
+        assert_eq!(executor.get_history().len(), 3);
+    }
+
+    #[test]
+    fn helper_round_trip() {
+        // stand-in helper that answers every request line with its length
+        let script = "while IFS= read -r l; do printf '{\"output\": \"%s\"}\\n' ${#l}; done";
+        let mut executor =
+            HelperExecutor::new("sh".to_string(), vec!["-c".to_string(), script.to_string()]);
+        let call = topic_call("{data: \"$(reboot)\"}");
+        let expect = serde_json::to_string(&call).unwrap().len().to_string();
+        let shm_dir = shm_dir("round-trip");
+        for _ in 0..3 {
+            let res = executor.exec(&call, &shm_dir).unwrap();
+            assert_eq!(res.output, expect);
+            assert!(!res.timeout);
+        }
+        assert!(executor.is_running());
+        executor.stop();
+        assert!(!executor.is_running());
+    }
+
+    #[test]
+    fn helper_batch_pads_missing_outputs() {
+        // answers a whole batch line with a single output
+        let script = "while IFS= read -r l; do echo '{\"outputs\": [{\"output\": \"a\"}]}'; done";
+        let mut executor =
+            HelperExecutor::new("sh".to_string(), vec!["-c".to_string(), script.to_string()]);
+        let calls = vec![(topic_call("{}"), 0), (topic_call("{}"), 500)];
+        let res = executor.exec_batch(&calls, &shm_dir("batch")).unwrap();
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].output, "a");
+        assert!(!res[0].timeout && res[1].timeout);
+    }
+
+    #[test]
+    fn helper_timeout_restarts() {
+        let mut executor = HelperExecutor::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), "sleep 5".to_string()],
+        );
+        executor.timeout = Duration::from_millis(100);
+        let res = executor
+            .exec(&topic_call("{}"), &shm_dir("timeout"))
+            .unwrap();
+        assert!(res.timeout);
+        assert!(!executor.is_running());
+    }
+
+    #[test]
+    fn helper_failures_are_executor_errors() {
+        // a traceback, then the helper dies before answering
+        let mut executor = HelperExecutor::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), "echo Traceback >&2".to_string()],
+        );
+        let shm_dir = shm_dir("failure");
+        let e = executor.exec(&topic_call("{}"), &shm_dir).unwrap_err();
+        assert!(e.downcast_ref::<ExecutorError>().is_some());
+        let log = Path::new(&shm_dir).parent().unwrap().join(HELPER_LOG);
+        assert!(fs::read_to_string(log).unwrap().contains("Traceback"));
+
+        let mut executor = CliExecutor::default();
+        let mut call = topic_call("{}");
+        call.itf = ITF::Impostor;
+        let e = executor.exec(&call, &shm_dir).unwrap_err();
+        assert!(e.downcast_ref::<ExecutorError>().is_some());
+    }
+
+    #[test]
+    fn param_files_stay_in_the_instance() {
+        let mut call = CallArgs::new(ITF::Param, "/planner", "", "", String::new());
+        call.params = Some(ParamUpdate {
+            params: vec![ParamValue {
+                name: "max_speed".to_string(),
+                itf_type: "float64".to_string(),
+                value: "1.5".to_string(),
+            }],
+            atomic: false,
+        });
+        let shm_dir = shm_dir("params");
+        let path = call.get_param_path(&shm_dir).unwrap();
+        assert!(path.starts_with(&shm_dir));
+        let argv = call.get_argv(&shm_dir);
+        assert_eq!(argv.last().unwrap(), &path.to_string_lossy());
+
+        // written for the cli and gone once the call is over, whether it ran or not
+        let mut executor = CliExecutor::default();
+        let _ = executor.exec(&call, &shm_dir);
+        let _ = executor.exec_batch(&[(call.clone(), 0), (call.clone(), 100)], &shm_dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,142 @@
+//! Publisher impersonation. A topic that already has a legitimate publisher in the launch gets
+//! conflicting messages injected next to it, so its consumers see two diverging sources.
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_LAG_US: u64 = 200_000;
+pub const MAX_FAKES: u32 = 64;
+pub const MAX_WINDOW_MS: u64 = 5000;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Impersonation {
+    // node whose publisher is imitated
+    pub publisher: String,
+    // share of the fields taken from the generated message, the rest is copied from the last
+    // real one: 0 replays the real publisher, 1 sends the generated message
+    pub divergence: f64,
+    // answer each real message with a fake one lag_us later, otherwise publish on the timing
+    // of the call (or once) without looking at the real publisher
+    pub follow: bool,
+    pub lag_us: u64,
+    // fakes sent in follow mode, within window_ms
+    pub count: u32,
+    pub window_ms: u64,
+}
+
+impl Impersonation {
+    pub fn generate(publisher: &str, rng: &mut RngType) -> Impersonation {
+        Impersonation {
+            publisher: publisher.to_string(),
+            divergence: gen_divergence(rng),
+            follow: !rng.gen_ratio(1, 4),
+            lag_us: gen_lag(rng),
+            count: rng.gen_range(1..=MAX_FAKES),
+            window_ms: rng.gen_range(100..=MAX_WINDOW_MS),
+        }
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..5) {
+            0 => self.divergence = gen_divergence(rng),
+            1 => self.follow = !self.follow,
+            2 => self.lag_us = gen_lag(rng),
+            3 => self.count = rng.gen_range(1..=MAX_FAKES),
+            _ => self.window_ms = rng.gen_range(100..=MAX_WINDOW_MS),
+        }
+    }
+}
+
+// slightly off messages are the hardest to tell apart from the real ones
+fn gen_divergence(rng: &mut RngType) -> f64 {
+    match rng.gen_range(0..4) {
+        0 => 1.0,
+        1 => 0.0,
+        _ => rng.gen_range(0.0..0.5),
+    }
+}
+
+// right behind the real message, or anywhere up to the next one
+fn gen_lag(rng: &mut RngType) -> u64 {
+    match rng.gen_range(0..3) {
+        0 => 0,
+        1 => rng.gen_range(0..1000),
+        _ => rng.gen_range(0..MAX_LAG_US),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::{call::CallArgs, executor::get_helper_request, interface::ITF};
+
+    #[test]
+    fn impersonated_call_rendering() {
+        let mut call = CallArgs::new(
+            ITF::Topic,
+            "listener",
+            "/chatter",
+            "std_msgs/msg/String",
+            "{data: hi}".to_string(),
+        );
+        let argv = call.get_argv("");
+        call.impersonate = Some(Impersonation {
+            publisher: "/talker".to_string(),
+            divergence: 0.25,
+            follow: true,
+            lag_us: 0,
+            count: 1,
+            window_ms: 100,
+        });
+        // the message goes out as before, the report names the imitated publisher
+        assert_eq!(call.get_argv(""), argv);
+        assert_eq!(
+            call.to_call_stream(),
+            "ros2 topic pub --once /chatter std_msgs/msg/String '{data: hi}' \
+             # impersonating /talker, divergence 0.25"
+        );
+    }
+
+    #[test]
+    fn follow_divergence_and_lag_reach_the_helper() {
+        let mut call = CallArgs::new(
+            ITF::Topic,
+            "listener",
+            "/chatter",
+            "std_msgs/msg/String",
+            "{data: hi}".to_string(),
+        );
+        call.impersonate = Some(Impersonation {
+            publisher: "/talker".to_string(),
+            divergence: 0.25,
+            follow: true,
+            lag_us: 0,
+            count: 1,
+            window_ms: 100,
+        });
+        let request = get_helper_request(&[(call.clone(), 0)], "follow");
+        let impersonate = &request["impersonate"];
+        assert_eq!(impersonate["publisher"], "/talker");
+        assert_eq!(impersonate["follow"], true);
+        assert_eq!(impersonate["divergence"], 0.25);
+        assert_eq!(impersonate["lag_us"], 0);
+
+        // a lagging fake of a batch keeps its own settings next to a plain publish
+        let impersonate = call.impersonate.as_mut().unwrap();
+        impersonate.follow = false;
+        impersonate.lag_us = 1500;
+        impersonate.count = 3;
+        impersonate.window_ms = 200;
+        let mut plain = call.clone();
+        plain.impersonate = None;
+        let request = get_helper_request(&[(plain, 0), (call, 500)], "lag");
+        let batch = request["batch"].as_array().unwrap();
+        assert!(batch[0]["call"]["impersonate"].is_null());
+        assert_eq!(batch[1]["offset_us"], 500);
+        let impersonate = &batch[1]["call"]["impersonate"];
+        assert_eq!(impersonate["follow"], false);
+        assert_eq!(impersonate["lag_us"], 1500);
+        assert_eq!(impersonate["count"], 3);
+        assert_eq!(impersonate["window_ms"], 200);
+    }
+}
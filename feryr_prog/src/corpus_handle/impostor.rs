@@ -0,0 +1,212 @@
+//! Impostor servers. The helper stands up a service or action server on a name a node is a
+//! client of, next to the real server or in its absence, and answers with generated
+//! responses so that the response handling of the client node gets fuzzed.
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_RESPONSES: usize = 4;
+pub const MAX_ANSWERS: u32 = 16;
+pub const MAX_LIFETIME_MS: u64 = 5000;
+pub const MAX_ANSWER_DELAY_US: u64 = 2_000_000;
+pub const MAX_FEEDBACK: u32 = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ImpostorKind {
+    Service,
+    Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Impostor {
+    pub kind: ImpostorKind,
+    // yaml payloads answered in turn, generated over every field of the interface, the helper
+    // keeps the ones of the response (result and feedback for actions)
+    pub responses: Vec<String>,
+    // wait before each answer, a late answer races the real server
+    pub delay_us: u64,
+    // stop serving after this many requests
+    pub answers: u32,
+    pub lifetime_ms: u64,
+    // a real server with the same name is running, the client gets both answers
+    pub racing: bool,
+    // keep serving in the background while the next calls of a sequence run
+    pub detach: bool,
+    // action only: reject goals, number of feedback messages, abort instead of succeed
+    pub reject: bool,
+    pub feedback: u32,
+    pub abort: bool,
+}
+
+impl Impostor {
+    pub fn generate(kind: ImpostorKind, racing: bool, rng: &mut RngType) -> Impostor {
+        Impostor {
+            kind,
+            responses: Vec::new(),
+            delay_us: gen_answer_delay(rng),
+            answers: rng.gen_range(1..=MAX_ANSWERS),
+            lifetime_ms: rng.gen_range(100..=MAX_LIFETIME_MS),
+            racing,
+            // nothing calls the impostor unless the following calls make the client act
+            detach: !rng.gen_ratio(1, 4),
+            reject: rng.gen_ratio(1, 8),
+            feedback: rng.gen_range(0..=MAX_FEEDBACK),
+            abort: rng.gen_ratio(1, 4),
+        }
+    }
+
+    // responses are regenerated by the caller, everything else changes here
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..6) {
+            0 => self.delay_us = gen_answer_delay(rng),
+            1 => self.answers = rng.gen_range(1..=MAX_ANSWERS),
+            2 => self.lifetime_ms = rng.gen_range(100..=MAX_LIFETIME_MS),
+            3 => self.detach = !self.detach,
+            4 => self.feedback = rng.gen_range(0..=MAX_FEEDBACK),
+            _ => match rng.gen() {
+                true => self.reject = !self.reject,
+                false => self.abort = !self.abort,
+            },
+        }
+    }
+
+    pub fn describe(&self, itf_name: &str, itf_type: &str) -> String {
+        let kind = match self.kind {
+            ImpostorKind::Service => "service",
+            ImpostorKind::Action => "action",
+        };
+        format!(
+            "impostor {} server {} {}: {} answers, {} us delay, {} ms{}{}",
+            kind,
+            itf_name,
+            itf_type,
+            self.answers,
+            self.delay_us,
+            self.lifetime_ms,
+            if self.racing { ", racing" } else { "" },
+            if self.detach { ", detached" } else { "" },
+        )
+    }
+}
+
+// answer at once to win the race against the real server, or late to arrive after it
+fn gen_answer_delay(rng: &mut RngType) -> u64 {
+    match rng.gen_range(0..3) {
+        0 => 0,
+        1 => rng.gen_range(0..1000),
+        _ => rng.gen_range(0..MAX_ANSWER_DELAY_US),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::{call::CallArgs, executor::get_helper_request, interface::ITF};
+
+    fn impostor(kind: ImpostorKind) -> Impostor {
+        Impostor {
+            kind,
+            responses: vec!["{sum: 3}".to_string()],
+            delay_us: 500,
+            answers: 2,
+            lifetime_ms: 1000,
+            racing: true,
+            detach: false,
+            reject: false,
+            feedback: 0,
+            abort: false,
+        }
+    }
+
+    #[test]
+    fn describe_kind_and_flags() {
+        let service = impostor(ImpostorKind::Service);
+        assert_eq!(
+            service.describe("/add", "example_interfaces/srv/AddTwoInts"),
+            "impostor service server /add example_interfaces/srv/AddTwoInts: 2 answers, \
+             500 us delay, 1000 ms, racing"
+        );
+        let mut action = impostor(ImpostorKind::Action);
+        action.racing = false;
+        action.detach = true;
+        assert_eq!(
+            action.describe("/fibonacci", "example_interfaces/action/Fibonacci"),
+            "impostor action server /fibonacci example_interfaces/action/Fibonacci: 2 answers, \
+             500 us delay, 1000 ms, detached"
+        );
+    }
+
+    #[test]
+    fn impostor_call_rendering() {
+        let mut call = CallArgs::new(
+            ITF::Impostor,
+            "client",
+            "/add",
+            "example_interfaces/srv/AddTwoInts",
+            "{a: 1, b: 2, sum: 3}".to_string(),
+        );
+        call.impostor = Some(impostor(ImpostorKind::Service));
+        assert_eq!(
+            call.get_argv(""),
+            vec![
+                "impostor",
+                "serve",
+                "/add",
+                "example_interfaces/srv/AddTwoInts",
+                "{a: 1, b: 2, sum: 3}"
+            ]
+        );
+        // the cli cannot serve, the report only says what the helper did
+        assert_eq!(
+            call.to_call_stream(),
+            "# impostor service server /add example_interfaces/srv/AddTwoInts: 2 answers, \
+             500 us delay, 1000 ms, racing"
+        );
+    }
+
+    #[test]
+    fn answers_delay_and_detach_reach_the_helper() {
+        let mut call = CallArgs::new(
+            ITF::Impostor,
+            "client",
+            "/add",
+            "example_interfaces/srv/AddTwoInts",
+            "{a: 1, b: 2, sum: 3}".to_string(),
+        );
+        call.impostor = Some(impostor(ImpostorKind::Service));
+        let request = get_helper_request(&[(call.clone(), 0)], "impostor");
+        let served = &request["impostor"];
+        assert_eq!(served["kind"], "Service");
+        assert_eq!(served["responses"][0], "{sum: 3}");
+        assert_eq!(served["answers"], 2);
+        assert_eq!(served["delay_us"], 500);
+        assert_eq!(served["lifetime_ms"], 1000);
+        assert_eq!(served["detach"], false);
+
+        // a detached action server goes out first in its batch and keeps serving the rest
+        call.itf_name = "/fibonacci".to_string();
+        call.itf_type = "example_interfaces/action/Fibonacci".to_string();
+        let mut action = impostor(ImpostorKind::Action);
+        action.detach = true;
+        action.feedback = 3;
+        action.reject = true;
+        call.impostor = Some(action);
+        let goal = CallArgs::new(
+            ITF::Action,
+            "client",
+            "/fibonacci",
+            "example_interfaces/action/Fibonacci",
+            "{order: 5}".to_string(),
+        );
+        let request = get_helper_request(&[(call, 0), (goal, 200)], "detach");
+        let batch = request["batch"].as_array().unwrap();
+        let served = &batch[0]["call"]["impostor"];
+        assert_eq!(served["kind"], "Action");
+        assert_eq!(served["detach"], true);
+        assert_eq!(served["feedback"], 3);
+        assert_eq!(served["reject"], true);
+        assert_eq!(served["abort"], false);
+        assert!(batch[1]["call"]["impostor"].is_null());
+        assert_eq!(batch[1]["offset_us"], 200);
+    }
+}
@@ -1,4 +1,9 @@
-use super::{ty::TYPE, RngType};
+use super::{
+    lifecycle::CHANGE_STATE_TYPE,
+    semantic::{get_generator, SEMANTIC_CTX, SEMANTIC_RATIO},
+    ty::TYPE,
+    RngType,
+};
 use crate::corpus_handle::ty::{array, character, double, integer};
 use multimap::MultiMap;
 use rand::Rng;
@@ -11,6 +16,12 @@ pub enum ITF {
     Service,
     Action,
     Param,
+    // a fake server on a name the node is a client of
+    Impostor,
+    // a state transition of a managed node
+    Lifecycle,
+    // a stream of simulated time on /clock, not bound to a node
+    Clock,
 }
 impl Default for ITF {
     fn default() -> Self {
@@ -85,6 +96,129 @@ impl InterfaceParam {
     pub fn update_array_len(&mut self, len: i32) {
         self.max_array_size = len;
     }
+
+    // parse the value of a constant like `uint8 STATUS_OK = 0`, only integers are supported
+    pub fn get_const_val(&self) -> Option<i128> {
+        if !self.is_const {
+            return None;
+        }
+        let val = self.const_val.trim();
+        match val.strip_prefix("0x").or_else(|| val.strip_prefix("0X")) {
+            Some(hex) => i128::from_str_radix(hex, 16).ok(),
+            None => val.parse::<i128>().ok(),
+        }
+    }
+}
+
+// Constants declared in the same message that share a type and a name prefix,
+// e.g. STATUS_UNKNOWN, STATUS_ACCEPTED, ... in action_msgs/msg/GoalStatus
+#[derive(Default, Debug, Clone)]
+pub struct ConstGroup {
+    pub arg_type: String,
+    pub prefix: String,
+    pub values: Vec<i128>,
+}
+impl ConstGroup {
+    pub fn collect(args: &[InterfaceParam]) -> Vec<ConstGroup> {
+        let mut groups: Vec<ConstGroup> = Vec::new();
+        let mut names: Vec<Vec<String>> = Vec::new();
+        for arg in args {
+            let val = match arg.get_const_val() {
+                Some(val) => val,
+                None => continue,
+            };
+            // group by type and the first token of the constant name
+            let head = arg.arg_name.split('_').next().unwrap_or("").to_string();
+            match groups
+                .iter()
+                .position(|g| g.arg_type == arg.arg_type && g.prefix == head)
+            {
+                Some(idx) => {
+                    groups[idx].values.push(val);
+                    names[idx].push(arg.arg_name.clone());
+                }
+                None => {
+                    groups.push(ConstGroup {
+                        arg_type: arg.arg_type.clone(),
+                        prefix: head,
+                        values: vec![val],
+                    });
+                    names.push(vec![arg.arg_name.clone()]);
+                }
+            }
+        }
+        // extend the prefix to all tokens the members share, e.g. COVARIANCE_TYPE
+        for (group, names) in groups.iter_mut().zip(names.iter()) {
+            if names.len() < 2 {
+                continue;
+            }
+            let tokens: Vec<Vec<&str>> = names.iter().map(|n| n.split('_').collect()).collect();
+            let mut common = 0;
+            while tokens
+                .iter()
+                .all(|t| t.len() > common + 1 && t[common] == tokens[0][common])
+            {
+                common += 1;
+            }
+            if common > 1 {
+                group.prefix = tokens[0][..common].join("_");
+            }
+        }
+        groups
+    }
+
+    // candidate values for a field: constants whose prefix matches the field name first,
+    // otherwise every constant of the same type, plus the neighbors just outside the set
+    pub fn get_candidates(groups: &[ConstGroup], field: &InterfaceParam) -> Vec<i128> {
+        let same_type: Vec<&ConstGroup> = groups
+            .iter()
+            .filter(|g| g.arg_type == field.arg_type)
+            .collect();
+        if same_type.is_empty() {
+            return Vec::new();
+        }
+        let field_name = field.arg_name.to_lowercase();
+        let named: Vec<&ConstGroup> = same_type
+            .iter()
+            .filter(|g| {
+                let prefix = g.prefix.to_lowercase();
+                !prefix.is_empty() && (field_name.contains(&prefix) || prefix.contains(&field_name))
+            })
+            .copied()
+            .collect();
+        let chosen = if named.is_empty() { same_type } else { named };
+
+        let mut values: Vec<i128> = chosen.iter().flat_map(|g| g.values.clone()).collect();
+        values.sort();
+        values.dedup();
+        let (type_min, type_max) = match int_range(TYPE::from_str(field.arg_type.as_str())) {
+            Some(range) => range,
+            None => return Vec::new(),
+        };
+        let lowest = values[0] - 1;
+        let highest = values[values.len() - 1] + 1;
+        if lowest >= type_min {
+            values.push(lowest);
+        }
+        if highest <= type_max {
+            values.push(highest);
+        }
+        values
+    }
+}
+
+fn int_range(ty: TYPE) -> Option<(i128, i128)> {
+    match ty {
+        TYPE::Int8 => Some((i8::MIN as i128, i8::MAX as i128)),
+        TYPE::Int16 => Some((i16::MIN as i128, i16::MAX as i128)),
+        TYPE::Int32 => Some((i32::MIN as i128, i32::MAX as i128)),
+        TYPE::Int64 => Some((i64::MIN as i128, i64::MAX as i128)),
+        TYPE::UInt8 => Some((0, u8::MAX as i128)),
+        TYPE::UInt16 => Some((0, u16::MAX as i128)),
+        TYPE::UInt32 => Some((0, u32::MAX as i128)),
+        TYPE::UInt64 => Some((0, u64::MAX as i128)),
+        _ => None,
+    }
 }
 
 // This structure manage one particular ros node information: subscribers/publisher/service/action, etc
@@ -174,6 +308,14 @@ impl Node {
         self.param = param;
     }
 
+    // change_state service of a managed node
+    pub fn get_lifecycle(&self) -> Option<&String> {
+        self.service_server
+            .iter()
+            .find(|(_, val)| val.itf_type == CHANGE_STATE_TYPE)
+            .map(|(name, _)| name)
+    }
+
     pub fn get_node_name(&self) -> &String {
         &self.node_name
     }
@@ -192,6 +334,12 @@ impl Node {
         if self.get_param().len() != 0 {
             vec.push(ITF::Param);
         }
+        if self.get_service_client().len() != 0 || self.get_action_client().len() != 0 {
+            vec.push(ITF::Impostor);
+        }
+        if self.get_lifecycle().is_some() {
+            vec.push(ITF::Lifecycle);
+        }
 
         vec
     }
@@ -208,11 +356,110 @@ pub enum ValueType {
     Op6(character::StringType),
 }
 
+// zero valued leaf of a primitive field, None for arrays and messages
+pub(crate) fn new_leaf(ty: TYPE, str_len: i32) -> Option<ValueType> {
+    let val = match ty {
+        string_type @ (TYPE::String | TYPE::WString) => ValueType::Op6(character::StringType::new(
+            string_type as usize,
+            "".to_string(),
+            str_len,
+        )),
+        TYPE::Float32 => ValueType::Op3(double::DoubleType::new(
+            TYPE::Float32 as usize,
+            0.0,
+            f32::MAX as f64,
+            f32::MIN as f64,
+            32,
+        )),
+        TYPE::Float64 => ValueType::Op3(double::DoubleType::new(
+            TYPE::Float64 as usize,
+            0.0,
+            f64::MAX as f64,
+            f64::MIN as f64,
+            64,
+        )),
+        TYPE::Byte => ValueType::Op4(character::CharType::new(
+            TYPE::Char as usize,
+            0 as char,
+            u8::MAX as char,
+            0 as char,
+        )),
+        TYPE::Char => ValueType::Op4(character::CharType::new(
+            TYPE::Char as usize,
+            0 as char,
+            u8::MAX as char,
+            0 as char,
+        )),
+        TYPE::Int8 => ValueType::Op1(integer::IntType::new(
+            TYPE::Int8 as usize,
+            0 as u64,
+            i8::MAX as u64,
+            i8::MIN as u64,
+            -8,
+        )),
+        TYPE::Int16 => ValueType::Op1(integer::IntType::new(
+            TYPE::Int16 as usize,
+            0 as u64,
+            i16::MAX as u64,
+            i16::MIN as u64,
+            -16,
+        )),
+        TYPE::Int32 => ValueType::Op1(integer::IntType::new(
+            TYPE::Int32 as usize,
+            0 as u64,
+            i32::MAX as u64,
+            i32::MIN as u64,
+            -32,
+        )),
+        TYPE::Int64 => ValueType::Op1(integer::IntType::new(
+            TYPE::Int64 as usize,
+            0 as u64,
+            i64::MAX as u64,
+            i64::MIN as u64,
+            -64,
+        )),
+        TYPE::UInt8 => ValueType::Op1(integer::IntType::new(
+            TYPE::UInt8 as usize,
+            0 as u64,
+            u8::MAX as u64,
+            u8::MIN as u64,
+            8,
+        )),
+        TYPE::UInt16 => ValueType::Op1(integer::IntType::new(
+            TYPE::UInt16 as usize,
+            0 as u64,
+            u16::MAX as u64,
+            u16::MIN as u64,
+            16,
+        )),
+        TYPE::UInt32 => ValueType::Op1(integer::IntType::new(
+            TYPE::UInt32 as usize,
+            0 as u64,
+            u32::MAX as u64,
+            u32::MIN as u64,
+            32,
+        )),
+        TYPE::UInt64 => ValueType::Op1(integer::IntType::new(
+            TYPE::UInt64 as usize,
+            0 as u64,
+            u64::MAX as u64,
+            u64::MIN as u64,
+            64,
+        )),
+        TYPE::Bool => ValueType::Op2(integer::BoolType::new(TYPE::Bool as usize, 0 as u64, 0, 1)),
+        TYPE::COMPLEX | TYPE::ARRAY => return None,
+    };
+    Some(val)
+}
+
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct InterfaceVal {
     pub itf_name: String,
     pub itf_type: String,
     pub val: Vec<ValueType>,
+    // values of sibling IDL constants preferred when generating this field
+    #[serde(default)]
+    pub const_candidates: Vec<i128>,
 }
 impl InterfaceVal {
     pub fn new(itf_name: &String, itf_type: &String) -> InterfaceVal {
@@ -220,6 +467,7 @@ impl InterfaceVal {
             itf_name: itf_name.to_string(),
             itf_type: itf_type.to_string(),
             val: Vec::new(),
+            const_candidates: Vec::new(),
         };
         itf_val
     }
@@ -243,124 +491,31 @@ impl InterfaceVal {
         // let type_short = &itf_type[begin_idx..end_idx];
         let type_long = type_maps.get(&itf_type[begin_idx..end_idx]).unwrap();
         let args = itf_info.get(type_long).unwrap().to_owned();
+        let const_groups = ConstGroup::collect(&args);
         for arg in args {
+            // constants are not fields of the message, they only seed sibling fields
+            if arg.is_const {
+                continue;
+            }
             if arg.is_meta_type() {
                 let mut itf = InterfaceVal::new(&arg.arg_name, &arg.arg_type);
-                let val = match TYPE::from_str(arg.arg_type.as_str()) {
-                    TYPE::String => {
-                        let mut rng = rand::thread_rng();
-                        if itf.itf_name == "node" {
-                            ValueType::Op6(character::StringType::new(
-                                TYPE::String as usize,
-                                "".to_string(),
-                                0,
-                            ))
-                        } else {
-                            ValueType::Op6(character::StringType::new(
-                                TYPE::String as usize,
-                                "".to_string(),
-                                rng.gen_range(1..32),
-                            ))
-                        }
-                    }
-                    TYPE::Float32 => ValueType::Op3(double::DoubleType::new(
-                        TYPE::Float32 as usize,
-                        0.0,
-                        f32::MAX as f64,
-                        f32::MIN as f64,
-                        32,
-                    )),
-                    TYPE::Float64 => ValueType::Op3(double::DoubleType::new(
-                        TYPE::Float64 as usize,
-                        0.0,
-                        f64::MAX as f64,
-                        f64::MIN as f64,
-                        64,
-                    )),
-                    TYPE::Byte => ValueType::Op4(character::CharType::new(
-                        TYPE::Char as usize,
-                        0 as char,
-                        u8::MAX as char,
-                        0 as char,
-                    )),
-                    TYPE::Char => ValueType::Op4(character::CharType::new(
-                        TYPE::Char as usize,
-                        0 as char,
-                        u8::MAX as char,
-                        0 as char,
-                    )),
-                    TYPE::Int8 => ValueType::Op1(integer::IntType::new(
-                        TYPE::Int8 as usize,
-                        0 as u64,
-                        i8::MAX as u64,
-                        i8::MIN as u64,
-                        -8,
-                    )),
-                    TYPE::Int16 => ValueType::Op1(integer::IntType::new(
-                        TYPE::Int16 as usize,
-                        0 as u64,
-                        i16::MAX as u64,
-                        i16::MIN as u64,
-                        -16,
-                    )),
-                    TYPE::Int32 => ValueType::Op1(integer::IntType::new(
-                        TYPE::Int32 as usize,
-                        0 as u64,
-                        i32::MAX as u64,
-                        i32::MIN as u64,
-                        -32,
-                    )),
-                    TYPE::Int64 => ValueType::Op1(integer::IntType::new(
-                        TYPE::Int64 as usize,
-                        0 as u64,
-                        i64::MAX as u64,
-                        i64::MIN as u64,
-                        -64,
-                    )),
-                    TYPE::UInt8 => ValueType::Op1(integer::IntType::new(
-                        TYPE::UInt8 as usize,
-                        0 as u64,
-                        u8::MAX as u64,
-                        u8::MIN as u64,
-                        8,
-                    )),
-                    TYPE::UInt16 => ValueType::Op1(integer::IntType::new(
-                        TYPE::UInt16 as usize,
-                        0 as u64,
-                        u16::MAX as u64,
-                        u16::MIN as u64,
-                        16,
-                    )),
-                    TYPE::UInt32 => ValueType::Op1(integer::IntType::new(
-                        TYPE::UInt32 as usize,
-                        0 as u64,
-                        u32::MAX as u64,
-                        u32::MIN as u64,
-                        32,
-                    )),
-                    TYPE::UInt64 => ValueType::Op1(integer::IntType::new(
-                        TYPE::UInt64 as usize,
-                        0 as u64,
-                        u64::MAX as u64,
-                        u64::MIN as u64,
-                        64,
-                    )),
-                    TYPE::Bool => {
-                        ValueType::Op2(integer::BoolType::new(TYPE::Bool as usize, 0 as u64, 0, 1))
-                    }
-                    _ => {
-                        if arg.is_array {
-                            let array_len = arg.max_array_size;
-                            let array_type = arg.get_array_inner_type();
-                            ValueType::Op5(array::ArrayType::new(
-                                TYPE::ARRAY as usize,
-                                array_type,
-                                array_len as u64,
-                            ))
-                        } else {
-                            panic!("unsupport type: {}", arg.arg_type);
-                        }
+                itf.const_candidates = ConstGroup::get_candidates(&const_groups, &arg);
+                let str_len = match itf.itf_name == "node" {
+                    true => 0,
+                    false => rand::thread_rng().gen_range(1..32),
+                };
+                let val = match new_leaf(TYPE::from_str(arg.arg_type.as_str()), str_len) {
+                    Some(val) => val,
+                    None if arg.is_array => {
+                        let array_len = arg.max_array_size;
+                        let array_type = arg.get_array_inner_type();
+                        ValueType::Op5(array::ArrayType::new(
+                            TYPE::ARRAY as usize,
+                            array_type,
+                            array_len as u64,
+                        ))
                     }
+                    None => panic!("unsupport type: {}", arg.arg_type),
                 };
                 itf.val.push(val);
                 self.val.push(ValueType::Op(itf));
@@ -383,6 +538,18 @@ impl InterfaceVal {
     }
 
     pub fn gen_value(&mut self, rng: &mut RngType) -> Result<(), failure::Error> {
+        // values of a type with a semantic generator are mostly left to it
+        if let Some(gen) = get_generator(&self.itf_type) {
+            if rng.gen_ratio(SEMANTIC_RATIO.0, SEMANTIC_RATIO.1) {
+                let ctx = SEMANTIC_CTX.lock().unwrap().clone();
+                return gen.generate(self, &ctx, rng);
+            }
+        }
+        self.gen_fields(rng)
+    }
+
+    // random values for every field, nested messages still go through gen_value
+    pub fn gen_fields(&mut self, rng: &mut RngType) -> Result<(), failure::Error> {
         // generate value base on self
         for val in self.val.iter_mut() {
             // check if val is ValueType::Op(Interfaceval)
@@ -394,7 +561,12 @@ impl InterfaceVal {
                     }
                 },
                 ValueType::Op1(int) => {
-                    int.gen_integer();
+                    if !self.const_candidates.is_empty() && rng.gen_ratio(3, 4) {
+                        let idx = rng.gen_range(0..self.const_candidates.len());
+                        int.set_val(self.const_candidates[idx] as u64);
+                    } else {
+                        int.gen_integer();
+                    }
                 }
                 ValueType::Op2(bool) => {
                     bool.gen_bool();
@@ -421,77 +593,113 @@ impl InterfaceVal {
         }
         Ok(())
     }
+}
 
-    pub fn get_value(&self) -> String {
-        // recursively generate string with the following pattern {self.name: self.val}
-        // format a string
-        let mut res = String::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(arg_type: &str, arg_name: &str, const_val: &str) -> InterfaceParam {
+        InterfaceParam::new(
+            arg_type.to_string(),
+            arg_name.to_string(),
+            false,
+            0,
+            true,
+            const_val.to_string(),
+        )
+    }
 
-        for val in self.val.iter() {
-            match val {
-                ValueType::Op(itf) => {
-                    if TYPE::from_str(itf.itf_type.as_str()) != TYPE::COMPLEX {
-                        res.push_str(&itf.get_value());
-                    } else {
-                        res.push_str(&itf.itf_name);
-                        res.push_str(": ");
-                        res.push_str("{ ");
-                        let sub_res = itf.get_value().to_string();
-                        res.push_str(&sub_res);
-                        let end_idx = match res.rfind(',') {
-                            Some(idx) => idx,
-                            None => 0,
-                        };
-                        res = res[0..end_idx].to_string();
-                        res.push_str("}, ");
-                    }
-                }
-                ValueType::Op1(int) => {
-                    if self.itf_name == "sec" {
-                        res.push_str(&self.itf_name);
-                        res.push_str(": ");
-                        res.push_str(&integer::IntType::gen_i16_string());
-                        res.push_str(", ");
-                    } else {
-                        res.push_str(&self.itf_name);
-                        res.push_str(": ");
-                        res.push_str(&int.get_val());
-                        res.push_str(", ");
-                    }
-                }
-                ValueType::Op2(bool) => {
-                    res.push_str(&self.itf_name);
-                    res.push_str(": ");
-                    res.push_str(&bool.get_val());
-                    res.push_str(", ");
-                }
-                ValueType::Op3(double) => {
-                    res.push_str(&self.itf_name);
-                    res.push_str(": ");
-                    res.push_str(&double.get_val());
-                    res.push_str(", ");
-                }
-                ValueType::Op4(chara) => {
-                    res.push_str(&self.itf_name);
-                    res.push_str(": ");
-                    res.push_str(&chara.get_val());
-                    res.push_str(", ");
-                }
+    fn field(arg_type: &str, arg_name: &str) -> InterfaceParam {
+        InterfaceParam::new(
+            arg_type.to_string(),
+            arg_name.to_string(),
+            false,
+            0,
+            false,
+            String::new(),
+        )
+    }
 
-                ValueType::Op5(array) => {
-                    // res.push_str(&self.itf_name);
-                    // res.push_str(": ");
-                    res.push_str(&array.get_val().to_string());
-                    res.push_str(", ");
-                }
-                ValueType::Op6(string) => {
-                    res.push_str(&self.itf_name);
-                    res.push_str(": {'");
-                    res.push_str(&string.get_val());
-                    res.push_str("'}, ");
-                }
-            }
-        }
-        res
+    // constants of sensor_msgs/msg/NavSatFix and action_msgs/msg/GoalStatus
+    fn message() -> Vec<InterfaceParam> {
+        vec![
+            constant("uint8", "COVARIANCE_TYPE_UNKNOWN", "0"),
+            constant("uint8", "COVARIANCE_TYPE_APPROXIMATED", "1"),
+            constant("uint8", "COVARIANCE_TYPE_DIAGONAL_KNOWN", "2"),
+            constant("uint8", "COVARIANCE_TYPE_KNOWN", "3"),
+            constant("uint8", "MODE_FAST", "0x10"),
+            constant("int8", "STATUS_UNKNOWN", "0"),
+            constant("int8", "STATUS_ABORTED", "6"),
+            field("uint8", "position_covariance_type"),
+            field("int8", "status"),
+            field("float64", "altitude"),
+        ]
+    }
+
+    #[test]
+    fn collect_groups_constants() {
+        let groups = ConstGroup::collect(&message());
+        let groups: Vec<(&str, &str, Vec<i128>)> = groups
+            .iter()
+            .map(|g| (g.arg_type.as_str(), g.prefix.as_str(), g.values.clone()))
+            .collect();
+        assert_eq!(
+            groups,
+            vec![
+                ("uint8", "COVARIANCE_TYPE", vec![0, 1, 2, 3]),
+                ("uint8", "MODE", vec![16]),
+                ("int8", "STATUS", vec![0, 6]),
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_of_fields() {
+        let groups = ConstGroup::collect(&message());
+        // the named group only, one past the highest, -1 does not fit a uint8
+        let covariance = field("uint8", "position_covariance_type");
+        assert_eq!(
+            ConstGroup::get_candidates(&groups, &covariance),
+            vec![0, 1, 2, 3, 4]
+        );
+        // no group matches the name, every constant of the type
+        let flags = field("uint8", "flags");
+        assert_eq!(
+            ConstGroup::get_candidates(&groups, &flags),
+            vec![0, 1, 2, 3, 16, 17]
+        );
+        let status = field("int8", "status");
+        assert_eq!(
+            ConstGroup::get_candidates(&groups, &status),
+            vec![0, 6, -1, 7]
+        );
+        assert!(ConstGroup::get_candidates(&groups, &field("float64", "altitude")).is_empty());
+        // neighbours past the type bounds are dropped
+        let full = ConstGroup::collect(&[constant("uint8", "LEVEL_MAX", "255")]);
+        assert_eq!(
+            ConstGroup::get_candidates(&full, &field("uint8", "level")),
+            vec![255, 254]
+        );
+    }
+
+    #[test]
+    fn int_range_bounds() {
+        assert_eq!(int_range(TYPE::Int8), Some((-128, 127)));
+        assert_eq!(int_range(TYPE::UInt8), Some((0, 255)));
+        assert_eq!(int_range(TYPE::Int16), Some((-32768, 32767)));
+        assert_eq!(int_range(TYPE::UInt16), Some((0, 65535)));
+        assert_eq!(
+            int_range(TYPE::Int32),
+            Some((i32::MIN as i128, i32::MAX as i128))
+        );
+        assert_eq!(int_range(TYPE::UInt32), Some((0, u32::MAX as i128)));
+        assert_eq!(
+            int_range(TYPE::Int64),
+            Some((i64::MIN as i128, i64::MAX as i128))
+        );
+        assert_eq!(int_range(TYPE::UInt64), Some((0, u64::MAX as i128)));
+        assert_eq!(int_range(TYPE::Float64), None);
+        assert_eq!(int_range(TYPE::String), None);
     }
 }
@@ -0,0 +1,287 @@
+//! Lifecycle transitions of managed nodes. Transitions are sent to the change_state service of
+//! the node rather than through `ros2 lifecycle set`, which refuses the ones that are not
+//! available in the current state, so that illegal transitions reach the node as well.
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const CHANGE_STATE_TYPE: &str = "lifecycle_msgs/srv/ChangeState";
+pub const MAX_CHAIN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Transition {
+    Configure,
+    Cleanup,
+    Activate,
+    Deactivate,
+    Shutdown,
+}
+const TRANSITIONS: [Transition; 5] = [
+    Transition::Configure,
+    Transition::Cleanup,
+    Transition::Activate,
+    Transition::Deactivate,
+    Transition::Shutdown,
+];
+
+/// Primary states of the managed node state machine.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum LifecycleState {
+    Unconfigured,
+    Inactive,
+    Active,
+    Finalized,
+}
+const STATES: [LifecycleState; 3] = [
+    LifecycleState::Unconfigured,
+    LifecycleState::Inactive,
+    LifecycleState::Active,
+];
+
+impl Transition {
+    pub fn get_label(&self) -> &'static str {
+        match self {
+            Transition::Configure => "configure",
+            Transition::Cleanup => "cleanup",
+            Transition::Activate => "activate",
+            Transition::Deactivate => "deactivate",
+            Transition::Shutdown => "shutdown",
+        }
+    }
+
+    pub fn get_goal(&self) -> LifecycleState {
+        match self {
+            Transition::Configure | Transition::Deactivate => LifecycleState::Inactive,
+            Transition::Cleanup => LifecycleState::Unconfigured,
+            Transition::Activate => LifecycleState::Active,
+            Transition::Shutdown => LifecycleState::Finalized,
+        }
+    }
+
+    // state reached from `from`, None when the transition is not available there
+    pub fn apply(&self, from: LifecycleState) -> Option<LifecycleState> {
+        match (self, from) {
+            (Transition::Configure, LifecycleState::Unconfigured) => Some(LifecycleState::Inactive),
+            (Transition::Cleanup, LifecycleState::Inactive) => Some(LifecycleState::Unconfigured),
+            (Transition::Activate, LifecycleState::Inactive) => Some(LifecycleState::Active),
+            (Transition::Deactivate, LifecycleState::Active) => Some(LifecycleState::Inactive),
+            (Transition::Shutdown, LifecycleState::Finalized) => None,
+            (Transition::Shutdown, _) => Some(LifecycleState::Finalized),
+            _ => None,
+        }
+    }
+
+    // lifecycle_msgs/msg/Transition id, shutdown has one per primary state
+    fn gen_id(&self, rng: &mut RngType) -> u8 {
+        match self {
+            Transition::Configure => 1,
+            Transition::Cleanup => 2,
+            Transition::Activate => 3,
+            Transition::Deactivate => 4,
+            Transition::Shutdown => rng.gen_range(5..=7),
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Transition> {
+        match id {
+            1 => Some(Transition::Configure),
+            2 => Some(Transition::Cleanup),
+            3 => Some(Transition::Activate),
+            4 => Some(Transition::Deactivate),
+            5..=7 => Some(Transition::Shutdown),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LifecycleCall {
+    pub transition: Transition,
+    // id sent when not by_label, may be one that matches no transition at all
+    pub id: u8,
+    // the node resolves the label against its current state, the id is taken as is
+    pub by_label: bool,
+}
+
+impl LifecycleCall {
+    pub fn new(transition: Transition, rng: &mut RngType) -> LifecycleCall {
+        LifecycleCall {
+            transition,
+            id: transition.gen_id(rng),
+            by_label: rng.gen(),
+        }
+    }
+
+    pub fn generate(rng: &mut RngType) -> LifecycleCall {
+        LifecycleCall::new(TRANSITIONS[rng.gen_range(0..TRANSITIONS.len())], rng)
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..4) {
+            0 | 1 => *self = LifecycleCall::generate(rng),
+            2 => self.by_label = !self.by_label,
+            _ => {
+                // ids of the error and intermediate transitions, or none at all
+                self.by_label = false;
+                self.id = rng.gen();
+                if let Some(transition) = Transition::from_id(self.id) {
+                    self.transition = transition;
+                }
+            }
+        }
+    }
+
+    // the transition the node runs, None for an id that names none
+    pub fn get_transition(&self) -> Option<Transition> {
+        match self.by_label {
+            true => Some(self.transition),
+            false => Transition::from_id(self.id),
+        }
+    }
+
+    // by id, each shutdown id is only available in its own primary state
+    pub fn apply(&self, from: LifecycleState) -> Option<LifecycleState> {
+        let state = match (self.by_label, self.id) {
+            (false, 5) => LifecycleState::Unconfigured,
+            (false, 6) => LifecycleState::Inactive,
+            (false, 7) => LifecycleState::Active,
+            _ => return self.get_transition()?.apply(from),
+        };
+        match from == state {
+            true => Some(LifecycleState::Finalized),
+            false => None,
+        }
+    }
+
+    pub fn get_payload(&self) -> String {
+        match self.by_label {
+            true => format!(
+                "{{transition: {{label: \"{}\"}}}}",
+                self.transition.get_label()
+            ),
+            false => format!("{{transition: {{id: {}}}}}", self.id),
+        }
+    }
+
+    /// A walk through the state machine from a random state. Legal chains only take available
+    /// transitions, the others sometimes take one that is not, e.g. cleanup while active.
+    pub fn gen_chain(legal: bool, rng: &mut RngType) -> Vec<LifecycleCall> {
+        let mut state = STATES[rng.gen_range(0..STATES.len())];
+        let mut chain = Vec::new();
+        for _ in 0..rng.gen_range(2..=MAX_CHAIN) {
+            let (available, unavailable): (Vec<Transition>, Vec<Transition>) = TRANSITIONS
+                .iter()
+                .partition(|transition| transition.apply(state).is_some());
+            let take_illegal = !legal && rng.gen_ratio(1, 3);
+            let transition = match take_illegal || available.is_empty() {
+                true => unavailable[rng.gen_range(0..unavailable.len())],
+                false => available[rng.gen_range(0..available.len())],
+            };
+            let mut call = LifecycleCall::new(transition, rng);
+            if transition == Transition::Shutdown {
+                // the shutdown id of the current state, the others are never available
+                call.id = 5 + STATES.iter().position(|other| *other == state).unwrap_or(0) as u8;
+            }
+            // shutdown ends the walk, nothing is available once finalized
+            state = call.apply(state).unwrap_or(state);
+            chain.push(call);
+            if state == LifecycleState::Finalized {
+                break;
+            }
+        }
+        chain
+    }
+}
+
+/// What one transition request did, states are modeled from the sequence and None while the
+/// state of the node is not known yet.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LifecycleOutcome {
+    pub node_name: String,
+    pub transition: Option<Transition>,
+    pub success: bool,
+    pub from: Option<LifecycleState>,
+    pub to: Option<LifecycleState>,
+    // whether the transition is available in `from`, never for an unknown id
+    pub legal: Option<bool>,
+}
+
+impl LifecycleOutcome {
+    pub fn observe(
+        node_name: &str,
+        call: &LifecycleCall,
+        from: Option<LifecycleState>,
+        output: &str,
+    ) -> LifecycleOutcome {
+        // "lifecycle_msgs.srv.ChangeState_Response(success=True)"
+        let success = output.contains("success=True");
+        let transition = call.get_transition();
+        let to = match (success, transition) {
+            (true, Some(transition)) => Some(transition.get_goal()),
+            _ => from,
+        };
+        let legal = match transition {
+            Some(_) => from.map(|from| call.apply(from).is_some()),
+            None => Some(false),
+        };
+        LifecycleOutcome {
+            node_name: node_name.to_string(),
+            transition,
+            success,
+            from,
+            to,
+            legal,
+        }
+    }
+
+    // the state machine let an unavailable transition through
+    pub fn is_violation(&self) -> bool {
+        self.success && self.legal == Some(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn chains_and_outcomes() {
+        let mut rng = RngType::seed_from_u64(0);
+        for _ in 0..64 {
+            let chain = LifecycleCall::gen_chain(true, &mut rng);
+            assert!(!chain.is_empty() && chain.len() <= MAX_CHAIN);
+        }
+
+        // configure -> activate -> cleanup while active
+        let call = LifecycleCall {
+            transition: Transition::Cleanup,
+            id: 2,
+            by_label: false,
+        };
+        assert_eq!(call.get_payload(), "{transition: {id: 2}}");
+        let output = "response:\nlifecycle_msgs.srv.ChangeState_Response(success=False)\n";
+        let outcome =
+            LifecycleOutcome::observe("/planner", &call, Some(LifecycleState::Active), output);
+        assert_eq!(outcome.legal, Some(false));
+        assert_eq!(outcome.to, Some(LifecycleState::Active));
+        assert!(!outcome.is_violation());
+
+        let output = "response:\nlifecycle_msgs.srv.ChangeState_Response(success=True)\n";
+        let outcome =
+            LifecycleOutcome::observe("/planner", &call, Some(LifecycleState::Active), output);
+        assert!(outcome.is_violation());
+
+        // the shutdown id of the inactive state while active
+        let call = LifecycleCall {
+            transition: Transition::Shutdown,
+            id: 6,
+            by_label: false,
+        };
+        assert_eq!(call.apply(LifecycleState::Active), None);
+        assert_eq!(
+            call.apply(LifecycleState::Inactive),
+            Some(LifecycleState::Finalized)
+        );
+    }
+}
@@ -5,12 +5,33 @@ use onnxruntime::environment::Environment;
 pub mod gen;
 pub mod sys;
 // pub mod mutation;
+pub mod action;
+pub mod bag;
+pub mod boot;
+pub mod call;
+pub mod clock;
+pub mod cdr;
+pub mod executor;
+pub mod impersonate;
+pub mod impostor;
 pub mod interface;
+pub mod lifecycle;
 pub mod models;
+pub mod param;
 pub mod prog;
+pub mod qos;
+pub mod restore;
+pub mod semantic;
+pub mod sensor;
+pub mod sequence;
 pub mod target;
+pub mod text;
+pub mod tf;
+pub mod timing;
 pub mod ty;
 pub mod value;
+pub mod xml;
+pub mod yaml;
 use std::sync::Mutex;
 
 lazy_static::lazy_static! {
@@ -10,7 +10,8 @@ use std::{
 };
 #[derive(Debug)]
 pub struct OnnxModel {
-    pub session: Session<'static>,
+    // None when the target has no trained model, predictions are empty then
+    pub session: Option<Session<'static>>,
 }
 unsafe impl Send for OnnxModel {}
 unsafe impl Sync for OnnxModel {}
@@ -18,6 +19,9 @@ impl OnnxModel {
     pub fn new(model_path: &Path) -> Self {
         // Convert the reference to Path to an owned PathBuf
         let model_path = PathBuf::from(model_path);
+        if !model_path.exists() {
+            return OnnxModel { session: None };
+        }
         // Use the global static Environment to create a session
         let session = ENVIRONMENT
             .new_session_builder()
@@ -26,7 +30,9 @@ impl OnnxModel {
             .unwrap();
 
         // Return the OnnxModel instance
-        OnnxModel { session }
+        OnnxModel {
+            session: Some(session),
+        }
     }
 
     pub fn process_hash_trace(
@@ -48,8 +54,8 @@ impl OnnxModel {
         &mut self,
         input_data: &Vec<(u64, u64)>,
     ) -> Result<Vec<f32>, failure::Error> {
-        // Check if input_data is empty
-        if input_data.is_empty() {
+        // Check if input_data is empty or no model was trained for the target
+        if input_data.is_empty() || self.session.is_none() {
             // Return an empty vector and continue execution
             return Ok(Vec::new());
         }
@@ -63,7 +69,11 @@ impl OnnxModel {
 
         let input_array: Array2<f32> = Array::from_shape_vec(input_shape, input_vec)?;
 
-        let outputs = self.session.run(vec![input_array.into_dyn()])?;
+        let outputs = self
+            .session
+            .as_mut()
+            .unwrap()
+            .run(vec![input_array.into_dyn()])?;
 
         let output_tensor: &OrtOwnedTensor<f32, _> = outputs.get(0).unwrap();
         let output_slice = output_tensor.as_slice().unwrap();
@@ -99,8 +109,8 @@ impl OnnxModel {
         &mut self,
         input_data: &Vec<(u64, u64)>,
     ) -> Result<Vec<f32>, failure::Error> {
-        // Check if input_data is empty
-        if input_data.is_empty() {
+        // Check if input_data is empty or no model was trained for the target
+        if input_data.is_empty() || self.session.is_none() {
             // Return an empty vector and continue execution
             return Ok(Vec::new());
         }
@@ -114,7 +124,11 @@ impl OnnxModel {
 
         let input_array: Array2<f32> = Array::from_shape_vec(input_shape, input_vec)?;
 
-        let outputs = self.session.run(vec![input_array.into_dyn()])?;
+        let outputs = self
+            .session
+            .as_mut()
+            .unwrap()
+            .run(vec![input_array.into_dyn()])?;
 
         let output_tensor: &OrtOwnedTensor<f32, _> = outputs.get(0).unwrap();
         let output_slice = output_tensor.as_slice().unwrap();
@@ -150,8 +164,8 @@ impl OnnxModel {
         &mut self,
         input_data: &[Vec<f32>],
     ) -> Result<Vec<f32>, failure::Error> {
-        // Check if input_data is empty
-        if input_data.is_empty() {
+        // Check if input_data is empty or no model was trained for the target
+        if input_data.is_empty() || self.session.is_none() {
             // Return an empty vector and continue execution
             return Ok(Vec::new());
         }
@@ -167,7 +181,11 @@ impl OnnxModel {
 
         let input_array: Array2<f32> = Array::from_shape_vec(input_shape, input_vec)?;
 
-        let outputs = self.session.run(vec![input_array.into_dyn()])?;
+        let outputs = self
+            .session
+            .as_mut()
+            .unwrap()
+            .run(vec![input_array.into_dyn()])?;
 
         let output_tensor: &OrtOwnedTensor<f32, _> = outputs.get(0).unwrap();
         let output_slice = output_tensor.as_slice().unwrap();
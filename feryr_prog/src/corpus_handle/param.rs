@@ -0,0 +1,288 @@
+//! Multi-parameter updates. Many nodes only check the consistency of related parameters (limits,
+//! sizes, frame names) when several of them change together, which a single `param set` never
+//! does. An update is sent as one `set_parameters_atomically` request, or as a param file loaded
+//! with `ros2 param load`, which applies it in one non-atomic `set_parameters` request.
+//!
+//! In type-confusion mode values are sent with another type than the declared one, as an
+//! array instead of a scalar and the other way round, or as empty arrays. Nodes declaring
+//! parameters dynamically or accepting undeclared overrides often crash on them.
+use super::{
+    yaml::{float_scalar, YamlNode},
+    RngType,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+pub const MAX_PARAMS: usize = 8;
+pub const SET_ATOMICALLY_TYPE: &str = "rcl_interfaces/srv/SetParametersAtomically";
+const SCALAR_TYPES: [&str; 4] = ["bool", "int64", "float64", "string"];
+const MAX_CONFUSED_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ParamValue {
+    pub name: String,
+    // interface type of the generated value, e.g. int64 or float64[]
+    pub itf_type: String,
+    // yaml flow value
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ParamUpdate {
+    pub params: Vec<ParamValue>,
+    // set_parameters_atomically, otherwise a param file
+    pub atomic: bool,
+}
+
+impl ParamUpdate {
+    pub fn get_service_name(node_name: &str) -> String {
+        format!("{}/set_parameters_atomically", get_absolute_name(node_name))
+    }
+
+    // request of set_parameters_atomically, values are tagged with rcl_interfaces ParameterType
+    pub fn get_service_payload(&self) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|param| {
+                let (ty, field) = get_parameter_type(&param.itf_type);
+                YamlNode::Map(vec![
+                    ("name".to_string(), YamlNode::Str(param.name.clone())),
+                    (
+                        "value".to_string(),
+                        YamlNode::Map(vec![
+                            ("type".to_string(), YamlNode::Scalar(ty.to_string())),
+                            (field.to_string(), YamlNode::Scalar(param.value.clone())),
+                        ]),
+                    ),
+                ])
+            })
+            .collect();
+        YamlNode::Map(vec![("parameters".to_string(), YamlNode::Seq(params))]).emit()
+    }
+
+    // param file in flow syntax, `ros2 param load` only reads the section of the node
+    pub fn get_param_file(&self, node_name: &str) -> String {
+        get_params_file(&[(node_name.to_string(), self.params.clone())])
+    }
+
+    // the cli reads the update from a file in the instance directory, named after its content
+    // so concurrent calls never overwrite each other
+    pub fn get_param_path(&self, param_dir: &str, node_name: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        self.get_param_file(node_name).hash(&mut hasher);
+        Path::new(param_dir).join(format!("feryr_params_{:016x}.yaml", hasher.finish()))
+    }
+}
+
+/// A parameter sent with another type than the declared one.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Confusion {
+    pub name: String,
+    pub declared: String,
+    pub sent: String,
+}
+
+/// How the node took a confused parameter, kept apart from the other results.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum ConfusionResult {
+    // the set call reported success, the node now runs with a value of the wrong type
+    Accepted,
+    // the set call was refused, what a node is expected to do
+    Rejected,
+    Timeout,
+    // the node died during the call
+    Crashed,
+}
+
+impl ConfusionResult {
+    // from the output of `param set`, `param load` or the atomic service call, crashes are
+    // only known once the output has been checked
+    pub fn from_output(output: &str, timeout: bool) -> ConfusionResult {
+        let output = output.to_lowercase();
+        if timeout {
+            ConfusionResult::Timeout
+        } else if output.contains("failed") || output.contains("successful=false") {
+            ConfusionResult::Rejected
+        } else {
+            ConfusionResult::Accepted
+        }
+    }
+}
+
+/// Type and yaml value of a parameter declared as `declared` but sent with another type.
+pub fn gen_confused(declared: &str, rng: &mut RngType) -> (String, String) {
+    let (base, array) = match declared.strip_suffix("[]") {
+        Some(base) => (base, true),
+        None => (declared, false),
+    };
+    match rng.gen_range(0..3) {
+        // another type, same shape
+        0 => {
+            let others: Vec<&str> = SCALAR_TYPES
+                .iter()
+                .copied()
+                .filter(|ty| *ty != base)
+                .collect();
+            let other = others[rng.gen_range(0..others.len())];
+            match array {
+                true => (format!("{}[]", other), gen_array(other, rng)),
+                false => (other.to_string(), gen_scalar(other, rng)),
+            }
+        }
+        // same type, array instead of scalar and the other way round
+        1 => {
+            let base = match base {
+                "byte" => "int64",
+                _ => base,
+            };
+            match array {
+                true => (base.to_string(), gen_scalar(base, rng)),
+                false => (format!("{}[]", base), gen_array(base, rng)),
+            }
+        }
+        // the cli and rclpy both guess the type of an empty array, tag it with any of them
+        _ => {
+            let ty = SCALAR_TYPES[rng.gen_range(0..SCALAR_TYPES.len())];
+            (format!("{}[]", ty), "[]".to_string())
+        }
+    }
+}
+
+fn gen_array(ty: &str, rng: &mut RngType) -> String {
+    let items = (0..rng.gen_range(1..=MAX_CONFUSED_LEN))
+        .map(|_| YamlNode::Scalar(gen_scalar(ty, rng)))
+        .collect();
+    YamlNode::Seq(items).emit()
+}
+
+fn gen_scalar(ty: &str, rng: &mut RngType) -> String {
+    match ty {
+        "bool" => rng.gen::<bool>().to_string(),
+        "int64" => match rng.gen_range(0..3) {
+            0 => [0, -1, i64::MAX, i64::MIN][rng.gen_range(0..4)].to_string(),
+            _ => rng.gen_range(-1000i64..1000).to_string(),
+        },
+        "float64" => float_scalar(rng.gen_range(-1000.0..1000.0), 64),
+        // strings that read like the other types are the likeliest to be converted by a node
+        _ => {
+            let text = match rng.gen_range(0..4) {
+                0 => String::new(),
+                1 => "true".to_string(),
+                2 => rng.gen_range(-1000i64..1000).to_string(),
+                _ => (0..rng.gen_range(1..16))
+                    .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                    .collect(),
+            };
+            YamlNode::Str(text).emit()
+        }
+    }
+}
+
+/// Param file with one section per node, as read by `ros2 param load` and `--params-file`.
+pub fn get_params_file(nodes: &[(String, Vec<ParamValue>)]) -> String {
+    let sections = nodes
+        .iter()
+        .map(|(node_name, params)| {
+            let params = params
+                .iter()
+                .map(|param| {
+                    (
+                        YamlNode::Str(param.name.clone()).emit(),
+                        YamlNode::Scalar(param.value.clone()),
+                    )
+                })
+                .collect();
+            (
+                get_absolute_name(node_name),
+                YamlNode::Map(vec![("ros__parameters".to_string(), YamlNode::Map(params))]),
+            )
+        })
+        .collect();
+    YamlNode::Map(sections).emit()
+}
+
+fn get_absolute_name(node_name: &str) -> String {
+    match node_name.starts_with('/') {
+        true => node_name.to_string(),
+        false => format!("/{}", node_name),
+    }
+}
+
+// rcl_interfaces/msg/ParameterType and the ParameterValue field holding it
+fn get_parameter_type(itf_type: &str) -> (u8, &'static str) {
+    match itf_type {
+        "bool" => (1, "bool_value"),
+        "int64" => (2, "integer_value"),
+        "float64" => (3, "double_value"),
+        "byte[]" => (5, "byte_array_value"),
+        "bool[]" => (6, "bool_array_value"),
+        "int64[]" => (7, "integer_array_value"),
+        "float64[]" => (8, "double_array_value"),
+        "string[]" => (9, "string_array_value"),
+        _ => (4, "string_value"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn atomic_request_and_param_file() {
+        let update = ParamUpdate {
+            params: vec![
+                ParamValue {
+                    name: "max_speed".to_string(),
+                    itf_type: "float64".to_string(),
+                    value: "1.5e+00".to_string(),
+                },
+                ParamValue {
+                    name: "frames".to_string(),
+                    itf_type: "string[]".to_string(),
+                    value: "[\"map\"]".to_string(),
+                },
+            ],
+            atomic: true,
+        };
+        assert_eq!(
+            update.get_service_payload(),
+            "{parameters: [{name: \"max_speed\", value: {type: 3, double_value: 1.5e+00}}, \
+             {name: \"frames\", value: {type: 9, string_array_value: [\"map\"]}}]}"
+        );
+        assert_eq!(
+            update.get_param_file("ns/planner"),
+            "{/ns/planner: {ros__parameters: {\"max_speed\": 1.5e+00, \"frames\": [\"map\"]}}}"
+        );
+        assert_eq!(
+            ParamUpdate::get_service_name("/planner"),
+            "/planner/set_parameters_atomically"
+        );
+    }
+
+    #[test]
+    fn confused_values_change_type() {
+        let mut rng = <RngType as rand::SeedableRng>::seed_from_u64(0);
+        for declared in ["bool", "int64", "float64[]", "string", "byte[]"] {
+            for _ in 0..64 {
+                let (sent, value) = gen_confused(declared, &mut rng);
+                // only an empty array may keep the declared type
+                assert!(sent != declared || value == "[]", "{} {}", sent, value);
+                assert_eq!(sent.ends_with("[]"), value.starts_with('['));
+            }
+        }
+        assert_eq!(
+            ConfusionResult::from_output("Setting parameter failed: Wrong parameter type\n", false),
+            ConfusionResult::Rejected
+        );
+        assert_eq!(
+            ConfusionResult::from_output("Set parameter successful\n", false),
+            ConfusionResult::Accepted
+        );
+    }
+}
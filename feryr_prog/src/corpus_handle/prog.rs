@@ -1,24 +1,35 @@
 use super::{
     super::{ExecError, ERR_LOG_PATTERN, FALSE_LOG_PATTERN, HANG_LOG_PATTERN},
+    action::ActionCall,
+    call::CallArgs,
+    cdr::CdrEncoder,
+    clock::{ClockStream, CLOCK_TOPIC, CLOCK_TYPE},
+    executor::ExecOutput,
+    impersonate::Impersonation,
+    impostor::{Impostor, ImpostorKind, MAX_RESPONSES},
     interface::{InterfaceVal, Node, ITF},
+    lifecycle::{LifecycleCall, CHANGE_STATE_TYPE},
+    param::{gen_confused, Confusion, ParamUpdate, ParamValue, MAX_PARAMS},
+    qos::{Durability, Qos},
     target::Target,
+    tf::is_tf_topic,
+    timing::Timing,
+    xml::{get_seed, mutate_document, set_document, XML_RATIO},
     RngType,
 };
-use rand::{rngs::OsRng, Rng};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
 use util::fuzzer_info;
-use std::{
-    io::Read,
-    process::{Command, Stdio},
-    time::{Duration, SystemTime},
-};
-use wait_timeout::ChildExt;
+use std::time::SystemTime;
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Prog {
     pub itf: ITF,
     pub itf_name: String,
     pub itf_type: String,
+    #[serde(default)]
+    pub call_args: CallArgs,
+    // rendering of call_args for reports, never executed
     pub call_stream: String,
     pub itf_info: InterfaceVal,
     pub size: u64,
@@ -28,7 +39,8 @@ impl Prog {
         let mut prog = Prog::default();
         if period % 3 != 0 {
             // go to generation
-            prog.generate_call(target).unwrap();
+            prog.generate_call(target, &mut RngType::from_entropy())
+                .unwrap();
         } else {
             // go to mutation
             prog.muatate_call(target).unwrap();
@@ -37,112 +49,290 @@ impl Prog {
         Ok(prog)
     }
 
+    // a recorded message published as it is, e.g. one imported from a bag
+    pub fn from_message(node_name: &String, itf_info: InterfaceVal) -> Prog {
+        let mut prog = Prog {
+            itf: ITF::Topic,
+            itf_name: itf_info.itf_name.clone(),
+            itf_type: itf_info.itf_type.clone(),
+            itf_info,
+            ..Default::default()
+        };
+        prog.serialization(node_name);
+        prog
+    }
+
     pub fn muatate_call(&mut self, _target: &Target) -> Result<(), failure::Error> {
         Ok(())
     }
-    pub fn generate_call(&mut self, target: &Target) -> Result<(), failure::Error> {
-        let target_node = self.choice_node(target).unwrap();
-        let typ_vec = target_node.get_avalible_interface();
+    pub fn generate_call(
+        &mut self,
+        target: &Target,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let target_node = self.choice_node(target, rng)?;
+        self.generate_call_on(target, target_node, rng)
+    }
 
-        let idx = OsRng::default().gen_range(0..typ_vec.len());
+    pub fn generate_call_on(
+        &mut self,
+        target: &Target,
+        target_node: &Node,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let mut typ_vec = target_node.get_avalible_interface();
+        if !target.executor.support_server() {
+            typ_vec.retain(|itf| *itf != ITF::Impostor);
+        }
+        if self.get_params(target, target_node).is_empty() {
+            typ_vec.retain(|itf| *itf != ITF::Param);
+        }
+        if target.sim_clock && target.executor.support_clock() {
+            typ_vec.push(ITF::Clock);
+        }
+
+        let mut impostor = None;
+        let mut params = None;
+        let idx = rng.gen_range(0..typ_vec.len());
         match typ_vec[idx] {
             ITF::Topic => {
-                match self.gen_topic(target, &target_node, &mut target.rng.clone()) {
+                match self.gen_topic(target, target_node, rng) {
                     Ok(_) => {}
                     Err(e) => return Err(e),
                 }
                 self.itf = ITF::Topic;
             }
             ITF::Service => {
-                match self.gen_service(target, target_node, &mut target.rng.clone()) {
+                match self.gen_service(target, target_node, rng) {
                     Ok(_) => {}
                     Err(e) => return Err(e),
                 }
                 self.itf = ITF::Service;
             }
             ITF::Action => {
-                match self.gen_action(target, target_node, &mut target.rng.clone()) {
+                match self.gen_action(target, target_node, rng) {
                     Ok(_) => {}
                     Err(e) => return Err(e),
                 }
                 self.itf = ITF::Action;
             }
             ITF::Param => {
-                match self.gen_param(target, target_node, &mut target.rng.clone()) {
+                match self.gen_param(target, target_node, rng) {
                     Ok(_) => {}
                     Err(e) => return Err(e),
                 }
+                if rng.gen_ratio(1, 3) {
+                    params = self.gen_param_update(target, target_node, rng);
+                }
                 self.itf = ITF::Param;
             }
+            ITF::Impostor => {
+                impostor = Some(self.gen_impostor(target, target_node, rng)?);
+                self.itf = ITF::Impostor;
+            }
+            ITF::Lifecycle => {
+                self.gen_lifecycle(target, target_node)?;
+                self.itf = ITF::Lifecycle;
+            }
+            ITF::Clock => {
+                self.gen_clock();
+                self.itf = ITF::Clock;
+            }
         }
         self.serialization(&target_node.node_name);
+        if impostor.is_some() {
+            self.set_impostor(impostor);
+        }
+        if params.is_some() {
+            self.set_params(params);
+        }
+        if self.itf == ITF::Param && target.param_confusion && rng.gen_ratio(1, 2) {
+            self.gen_confusion(rng);
+        }
+        if self.itf == ITF::Action {
+            self.set_action(Some(ActionCall::generate(rng)));
+        }
+        if self.itf == ITF::Lifecycle {
+            self.set_lifecycle(Some(LifecycleCall::generate(rng)));
+        }
+        if self.itf == ITF::Clock {
+            self.set_clock(Some(ClockStream::generate(rng)));
+        }
+        if self.itf == ITF::Topic && target.impersonate && rng.gen_ratio(1, 4) {
+            if let Some(publisher) = self.get_publisher(target) {
+                let impersonate = Impersonation::generate(&publisher, rng);
+                self.set_impersonation(Some(impersonate));
+            }
+        }
+        if self.itf == ITF::Topic && rng.gen_ratio(1, 4) {
+            self.set_qos(Some(Qos::generate(rng)));
+        }
+        // robot descriptions are latched, a volatile publisher does not match their subscribers
+        if self.itf == ITF::Topic && self.get_xml_seed(target, target_node).is_some() {
+            let mut qos = self.call_args.qos.clone().unwrap_or_default();
+            qos.durability = Durability::TransientLocal;
+            self.set_qos(Some(qos));
+        }
+        if self.itf == ITF::Topic && rng.gen_ratio(1, 4) {
+            self.set_timing(Some(Timing::generate(rng)));
+        }
+        if self.itf == ITF::Topic
+            && target.raw_cdr
+            && target.executor.support_raw()
+            && rng.gen_ratio(1, 2)
+        {
+            self.gen_raw(target, rng)?;
+        }
         Ok(())
     }
 
-    pub fn choice_node<'a>(&self, target: &'a Target) -> Result<&'a Node, failure::Error> {
-        let mut target_node = &target.nodes[rand::thread_rng().gen_range(0..target.nodes.len())];
+    pub fn set_timing(&mut self, timing: Option<Timing>) {
+        self.call_args.set_timing(timing);
+        self.call_stream = self.call_args.to_call_stream();
+    }
 
-        while target_node.get_node_subscribers().len() == 0
-            && target_node.get_service_server().len() == 0
-            && target_node.get_action_server().len() == 0
-            && target_node.get_param().len() == 0
-        {
-            // if all equal to 0, regenerate node
-            target_node = &target.nodes[OsRng::default().gen_range(0..target.nodes.len())];
-        }
+    pub fn set_qos(&mut self, qos: Option<Qos>) {
+        self.call_args.qos = qos;
+        self.call_stream = self.call_args.to_call_stream();
+    }
 
-        Ok(target_node)
+    pub fn set_params(&mut self, params: Option<ParamUpdate>) {
+        self.call_args.params = params;
+        self.call_stream = self.call_args.to_call_stream();
     }
 
-    pub fn serialization(&mut self, node_name: &String) {
-        let mut call_stream = String::new();
-        match self.itf {
-            ITF::Topic => {
-                call_stream.push_str("ros2 topic pub --once ");
-            }
-            ITF::Service => {
-                call_stream.push_str("ros2 service call ");
-            }
-            ITF::Action => {
-                call_stream.push_str("ros2 action send_goal ");
+    // send the parameter, or some parameters of the update, with another type than declared
+    pub fn gen_confusion(&mut self, rng: &mut RngType) {
+        let mut confusions = Vec::new();
+        match self.call_args.params.as_mut() {
+            Some(update) => {
+                let first = rng.gen_range(0..update.params.len());
+                for (idx, param) in update.params.iter_mut().enumerate() {
+                    if idx != first && rng.gen() {
+                        continue;
+                    }
+                    let (sent, value) = gen_confused(&param.itf_type, rng);
+                    confusions.push(Confusion {
+                        name: param.name.clone(),
+                        declared: param.itf_type.clone(),
+                        sent: sent.clone(),
+                    });
+                    param.itf_type = sent;
+                    param.value = value;
+                }
             }
-            ITF::Param => {
-                call_stream.push_str("ros2 param set ");
+            None => {
+                let (sent, value) = gen_confused(&self.itf_type, rng);
+                confusions.push(Confusion {
+                    name: self.itf_name.clone(),
+                    declared: self.itf_type.clone(),
+                    sent,
+                });
+                self.call_args.payload = value;
             }
         }
-        if self.itf != ITF::Param {
-            call_stream.push_str(&self.itf_name);
-            call_stream.push_str(" ");
-            call_stream.push_str(&self.itf_type);
-            call_stream.push_str(" \"{");
-            let res = &self.itf_info.get_value();
-            // TODO
-            let start_idx = 0;
-            // match res.find(':') {
-            //     Some(idx) => idx + 1,
-            //     None => 0,
-            // };
-            call_stream.push_str(&res[start_idx..].to_string());
-            // pop ", "
-            call_stream.pop();
-            call_stream.pop();
-            call_stream.push_str("}\"");
-        } else {
-            call_stream.push_str(node_name);
-            call_stream.push_str(" ");
-            let res = &&self.itf_info.get_value();
-            call_stream.push_str(res);
-            call_stream.retain(|c| c != ':');
-            call_stream.pop();
-            call_stream.pop();
+        self.call_args.confusion = confusions;
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    pub fn set_impersonation(&mut self, impersonate: Option<Impersonation>) {
+        self.call_args.impersonate = impersonate;
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    // a node of the launch that already publishes the topic of this call
+    pub fn get_publisher(&self, target: &Target) -> Option<String> {
+        target
+            .nodes
+            .iter()
+            .find(|node| node.get_node_publisher().contains_key(&self.itf_name))
+            .map(|node| node.node_name.clone())
+    }
+
+    pub fn set_lifecycle(&mut self, lifecycle: Option<LifecycleCall>) {
+        self.call_args.set_lifecycle(lifecycle);
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    pub fn set_clock(&mut self, clock: Option<ClockStream>) {
+        self.call_args.set_clock(clock);
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    pub fn set_impostor(&mut self, impostor: Option<Impostor>) {
+        self.call_args.impostor = impostor;
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    // a fresh set of responses for the impostor of this call
+    pub fn gen_responses(&self, rng: &mut RngType) -> Vec<String> {
+        let mut responses = Vec::new();
+        for _ in 0..rng.gen_range(1..=MAX_RESPONSES) {
+            let mut response = self.itf_info.clone();
+            match response.gen_value(rng) {
+                Ok(_) => responses.push(response.to_yaml()),
+                Err(e) => fuzzer_info!("gen response error: {}", e),
+            }
         }
-        self.call_stream = call_stream;
-        dbg!(&self.call_stream);
+        responses
+    }
+
+    pub fn set_action(&mut self, action: Option<ActionCall>) {
+        self.call_args.set_action(action);
+        self.call_stream = self.call_args.to_call_stream();
+    }
+
+    // encode the generated message as cdr and break the wire format
+    pub fn gen_raw(&mut self, target: &Target, rng: &mut RngType) -> Result<(), failure::Error> {
+        let mut cdr =
+            CdrEncoder::new(&target.itfs_maps, &target.itfs_info).encode(&self.itf_info)?;
+        cdr.mutate(rng);
+        self.call_args.raw = cdr.data;
+        self.call_stream = self.call_args.to_call_stream();
+        Ok(())
+    }
+
+    // a node with something to fuzz, client-only nodes are reachable through impostor servers
+    pub fn choice_node<'a>(
+        &self,
+        target: &'a Target,
+        rng: &mut RngType,
+    ) -> Result<&'a Node, failure::Error> {
+        let clients = |node: &Node| {
+            target.executor.support_server()
+                && (!node.get_service_client().is_empty() || !node.get_action_client().is_empty())
+        };
+        let nodes: Vec<&Node> = target
+            .nodes
+            .iter()
+            .filter(|node| {
+                !node.get_node_subscribers().is_empty()
+                    || !node.get_service_server().is_empty()
+                    || !node.get_action_server().is_empty()
+                    || !self.get_params(target, node).is_empty()
+                    || clients(node)
+            })
+            .collect();
+        nodes
+            .choose(rng)
+            .copied()
+            .ok_or_else(|| failure::format_err!("no node of the target takes inputs"))
+    }
+
+    pub fn serialization(&mut self, node_name: &String) {
+        self.call_args = CallArgs::new(
+            self.itf.clone(),
+            node_name,
+            &self.itf_name,
+            &self.itf_type,
+            self.itf_info.to_yaml(),
+        );
+        self.call_stream = self.call_args.to_call_stream();
     }
 
     pub fn gen_topic(
         &mut self,
-        _target: &Target,
+        target: &Target,
         target_node: &Node,
         rng: &mut RngType,
     ) -> Result<(), failure::Error> {
@@ -151,8 +341,16 @@ impl Prog {
         let subscriber_len = topic_subscriber.len();
 
         // random choose a topic to execute
-        let topic_idx = rand::thread_rng().gen_range(0..subscriber_len);
+        let topic_idx = rng.gen_range(0..subscriber_len);
         self.itf_name = topic_subscriber.keys().nth(topic_idx).unwrap().to_string();
+        // in tf mode the frame tree of a tf consumer is fuzzed more often than its other inputs
+        let tf_topics: Vec<&String> = topic_subscriber
+            .keys()
+            .filter(|topic| is_tf_topic(topic))
+            .collect();
+        if target.tf_fuzz && !tf_topics.is_empty() && rng.gen() {
+            self.itf_name = tf_topics.choose(rng).unwrap().to_string();
+        }
         self.itf_type = topic_subscriber
             .get(&self.itf_name)
             .unwrap()
@@ -162,9 +360,10 @@ impl Prog {
         match self.itf_info.gen_value(rng) {
             Ok(_) => {}
             Err(e) => {
-                println!("gen topic error: {}", e);
+                fuzzer_info!("gen topic error: {}", e);
             }
         }
+        self.gen_xml(target, target_node, rng);
         Ok(())
     }
 
@@ -179,45 +378,127 @@ impl Prog {
         let service_server = target_node.get_service_server();
         let service_len = service_server.len();
 
-        let service_idx = rand::thread_rng().gen_range(0..service_len);
+        let service_idx = rng.gen_range(0..service_len);
         self.itf_name = service_server.keys().nth(service_idx).unwrap().to_string();
         self.itf_type = service_server.get(&self.itf_name).unwrap().itf_type.clone();
         self.itf_info = service_server.get(&self.itf_name).unwrap().clone();
         match self.itf_info.gen_value(rng) {
             Ok(_) => {}
             Err(e) => {
-                println!("gen service error: {}", e);
+                fuzzer_info!("gen service error: {}", e);
             }
         }
 
         Ok(())
     }
 
+    // parameters of the node that may be set, read-only ones only when asked for
+    pub fn get_params<'a>(&self, target: &Target, target_node: &'a Node) -> Vec<&'a String> {
+        target_node
+            .get_param()
+            .keys()
+            .filter(|name| target.readonly_param || !target.banned_param.contains(name))
+            .collect()
+    }
+
     pub fn gen_param(
         &mut self,
-        _target: &Target,
+        target: &Target,
         target_node: &Node,
         rng: &mut RngType,
     ) -> Result<(), failure::Error> {
-        // ros2 param load <node_name> <parameter_file>
-        let _param = "ros2 param set".to_string();
+        // ros2 param set <node_name> <param_name> <value>
         let param_list = target_node.get_param();
-        let param_len = param_list.len();
+        let names = self.get_params(target, target_node);
+        if names.is_empty() {
+            return Err(ExecError::ExecError(format!(
+                "no writable parameter on {}",
+                target_node.node_name
+            ))
+            .into());
+        }
 
-        let param_idx = rand::thread_rng().gen_range(0..param_len);
-        self.itf_name = param_list.keys().nth(param_idx).unwrap().to_string();
+        let param_idx = rng.gen_range(0..names.len());
+        self.itf_name = names[param_idx].to_string();
+        // a robot description is one parameter among many, but the one with the largest parser
+        let xml_names: Vec<&&String> = names
+            .iter()
+            .filter(|name| get_seed(&target.xml_seeds, &target_node.node_name, name).is_some())
+            .collect();
+        if !xml_names.is_empty() && rng.gen_ratio(XML_RATIO.0, XML_RATIO.1) {
+            self.itf_name = xml_names.choose(rng).unwrap().to_string();
+        }
         self.itf_type = param_list.get(&self.itf_name).unwrap().itf_type.clone();
         self.itf_info = param_list.get(&self.itf_name).unwrap().clone();
         match self.itf_info.gen_value(rng) {
             Ok(_) => {}
             Err(e) => {
-                println!("gen param error: {}", e);
+                fuzzer_info!("gen param error: {}", e);
             }
         }
+        self.gen_xml(target, target_node, rng);
 
         Ok(())
     }
 
+    // the seeded document of a string parameter or std_msgs/String topic, see xml.rs
+    fn get_xml_seed<'a>(&self, target: &'a Target, target_node: &Node) -> Option<&'a str> {
+        let itf_type = self.itf_type.replace("/msg/", "/");
+        if !matches!(itf_type.as_str(), "string" | "std_msgs/String") {
+            return None;
+        }
+        get_seed(&target.xml_seeds, &target_node.node_name, &self.itf_name)
+            .map(|seed| seed.value.as_str())
+    }
+
+    // replace the random string with a mutated robot description
+    fn gen_xml(&mut self, target: &Target, target_node: &Node, rng: &mut RngType) {
+        if let Some(seed) = self.get_xml_seed(target, target_node) {
+            if rng.gen_ratio(XML_RATIO.0, XML_RATIO.1) {
+                let doc = mutate_document(seed, rng);
+                set_document(&mut self.itf_info, doc);
+            }
+        }
+    }
+
+    // the generated parameter together with a few others of the same node, applied at once
+    pub fn gen_param_update(
+        &self,
+        target: &Target,
+        target_node: &Node,
+        rng: &mut RngType,
+    ) -> Option<ParamUpdate> {
+        let mut names = self.get_params(target, target_node);
+        names.retain(|name| **name != self.itf_name);
+        if names.is_empty() {
+            return None;
+        }
+        names.shuffle(rng);
+        names.truncate(rng.gen_range(1..MAX_PARAMS));
+
+        let mut params = vec![ParamValue {
+            name: self.itf_name.clone(),
+            itf_type: self.itf_type.clone(),
+            value: self.itf_info.to_yaml(),
+        }];
+        for name in names {
+            let mut itf_info = target_node.get_param().get(name).unwrap().clone();
+            if let Err(e) = itf_info.gen_value(rng) {
+                fuzzer_info!("gen param error: {}", e);
+                continue;
+            }
+            params.push(ParamValue {
+                name: name.to_string(),
+                itf_type: itf_info.itf_type.clone(),
+                value: itf_info.to_yaml(),
+            });
+        }
+        Some(ParamUpdate {
+            params,
+            atomic: rng.gen(),
+        })
+    }
+
     pub fn gen_action(
         &mut self,
         _target: &Target,
@@ -228,20 +509,92 @@ impl Prog {
         let action_server = target_node.get_action_server();
         let act_len = action_server.len();
 
-        let service_idx = rand::thread_rng().gen_range(0..act_len);
+        let service_idx = rng.gen_range(0..act_len);
         self.itf_name = action_server.keys().nth(service_idx).unwrap().to_string();
         self.itf_type = action_server.get(&self.itf_name).unwrap().itf_type.clone();
         self.itf_info = action_server.get(&self.itf_name).unwrap().clone();
         match self.itf_info.gen_value(rng) {
             Ok(_) => {}
             Err(e) => {
-                println!("gen action error: {}", e);
+                fuzzer_info!("gen action error: {}", e);
             }
         }
 
         Ok(())
     }
 
+    pub fn gen_impostor(
+        &mut self,
+        target: &Target,
+        target_node: &Node,
+        rng: &mut RngType,
+    ) -> Result<Impostor, failure::Error> {
+        // serve a name the node calls as a client
+        let service_client = target_node.get_service_client();
+        let action_client = target_node.get_action_client();
+        let client_len = service_client.len() + action_client.len();
+
+        let client_idx = rng.gen_range(0..client_len);
+        let (kind, client) = match client_idx < service_client.len() {
+            true => (ImpostorKind::Service, service_client.iter().nth(client_idx)),
+            false => (
+                ImpostorKind::Action,
+                action_client.iter().nth(client_idx - service_client.len()),
+            ),
+        };
+        let (name, val) = client.unwrap();
+        self.itf_name = name.to_string();
+        self.itf_type = val.itf_type.clone();
+        self.itf_info = val.clone();
+        match self.itf_info.gen_value(rng) {
+            Ok(_) => {}
+            Err(e) => {
+                fuzzer_info!("gen impostor error: {}", e);
+            }
+        }
+
+        let racing = target.nodes.iter().any(|node| match kind {
+            ImpostorKind::Service => node.get_service_server().contains_key(&self.itf_name),
+            ImpostorKind::Action => node.get_action_server().contains_key(&self.itf_name),
+        });
+        let mut impostor = Impostor::generate(kind, racing, rng);
+        impostor.responses = self.gen_responses(rng);
+        Ok(impostor)
+    }
+
+    // the time of every node on simulated time, whichever node the call was generated for
+    pub fn gen_clock(&mut self) {
+        self.itf_name = CLOCK_TOPIC.to_string();
+        self.itf_type = CLOCK_TYPE.to_string();
+        self.itf_info = InterfaceVal::default();
+    }
+
+    pub fn gen_lifecycle(
+        &mut self,
+        _target: &Target,
+        target_node: &Node,
+    ) -> Result<(), failure::Error> {
+        // ros2 service call <node>/change_state lifecycle_msgs/srv/ChangeState <transition>
+        let service = match target_node.get_lifecycle() {
+            Some(service) => service,
+            None => {
+                return Err(ExecError::ExecError(format!(
+                    "{} is not a managed node",
+                    target_node.node_name
+                ))
+                .into())
+            }
+        };
+        self.itf_name = service.to_string();
+        self.itf_type = CHANGE_STATE_TYPE.to_string();
+        self.itf_info = target_node
+            .get_service_server()
+            .get(service)
+            .unwrap()
+            .clone();
+        Ok(())
+    }
+
     pub fn exec_input_prog(
         &self,
         shm_dir: &mut String,
@@ -257,8 +610,11 @@ impl Prog {
         }
 
         match self.exec_one(target, shm_dir) {
-            Ok(_) => {
+            Ok(interesting) => {
                 // check if the result is valid
+                if interesting {
+                    target.corpus.push(self.clone());
+                }
                 Ok(())
             }
             Err(e) => {
@@ -267,22 +623,14 @@ impl Prog {
         }
     }
 
-    // check if a input has finish execution
-    pub fn exec_one(&self, target: &mut Target, work_dir: &String) -> Result<(), failure::Error> {
+    // check if a input has finish execution, returns whether the input is interesting
+    pub fn exec_one(&self, target: &mut Target, work_dir: &String) -> Result<bool, failure::Error> {
         // send input to ros app
         fuzzer_info!("exec_one");
         // clean shm
         target
             .shm_region
             .allow_time_write(&(work_dir.clone() + "/shm"));
-        let mut send_input_cmd = Command::new("bash")
-            .env("SHM_PATH", work_dir.to_owned() + "/shm")
-            .arg("-c")
-            .arg(&self.call_stream)
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .expect("failed to execute process");
         // get current timestamp
         let start_time = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -290,27 +638,24 @@ impl Prog {
             .as_nanos();
         dbg!(start_time);
 
-        match &send_input_cmd
-            .wait_timeout(Duration::from_secs(10))
-            .unwrap()
-        {
-            Some(_) => {
+        let shm_dir = work_dir.to_owned() + "/shm";
+        let exec_res = target.executor.exec(&self.call_args, &shm_dir)?;
+        self.check_output(target, work_dir, start_time, exec_res)
+    }
+
+    // classify what the executor reported for this call, returns whether it is interesting
+    pub fn check_output(
+        &self,
+        target: &mut Target,
+        work_dir: &String,
+        start_time: u128,
+        exec_res: ExecOutput,
+    ) -> Result<bool, failure::Error> {
+        let input_res = exec_res.output;
+        match exec_res.timeout {
+            false => {
                 // child has exited
                 fuzzer_info!("execution normal");
-                let mut input_res = String::new();
-                send_input_cmd
-                    .stdout
-                    .expect("filed to open stdout")
-                    .read_to_string(&mut input_res)
-                    .expect("Failed to read stdout");
-                let mut input_err = String::new();
-                send_input_cmd
-                    .stderr
-                    .expect("filed to open stdout")
-                    .read_to_string(&mut input_err)
-                    .expect("Failed to read stdout");
-                input_res.push_str(&input_err);
-
                 dbg!(&input_res);
                 // check if have bad input or if cli have error
                 if ERR_LOG_PATTERN.iter().any(|substring| {
@@ -336,17 +681,13 @@ impl Prog {
                     &(work_dir.to_owned() + &"/shm".to_string()),
                     &input_res,
                 ) {
-                    Ok(true) => {
-                        target.corpus.push(self.clone());
-                        return Ok(());
-                    }
-                    Ok(false) => return Ok(()),
+                    Ok(interesting) => return Ok(interesting),
                     Err(e) => {
                         return Err(e.into());
                     }
                 }
             }
-            None => {
+            true => {
                 // child hasn't exited yet, most likely to be a system crash or hang, just do a reboot
                 fuzzer_info!("execution timeout");
                 match target.check_crash() {
@@ -355,16 +696,9 @@ impl Prog {
                         return Err(e.into());
                     }
                 }
-                send_input_cmd.kill().unwrap();
-                let mut input_res = String::new();
-                send_input_cmd
-                    .stdout
-                    .expect("filed to open stdout")
-                    .read_to_string(&mut input_res)
-                    .expect("Failed to read stdout");
                 println!("{}", input_res);
                 if input_res.is_empty() {
-                    return Ok(())
+                    return Ok(false)
 
                     // Fix: this may not leading to crash, reduce unwanted false positive: system carshed, return crashed error
                     // return Err(ExecError::ExecError("ros2 is crashed ".into()).into());
@@ -381,7 +715,7 @@ impl Prog {
                     // system hang, return hang error
                     match target.update_shm(&(work_dir.to_owned() + &"/shm".to_string())) {
                         Ok(_) => {
-                            return Ok(())
+                            return Ok(false)
 
                             // Fix: this may not leading to crash, reduce unwanted false positive
                             // return Err(ExecError::InvalidResult {
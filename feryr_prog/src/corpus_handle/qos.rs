@@ -0,0 +1,263 @@
+//! QoS profile of the publisher or subscription an input creates. Mismatching the profile of
+//! the target (reliability, durability, deadline, liveliness) and tiny history depths reach
+//! rmw paths that the cli default profile never does.
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const MAX_DEPTH: u32 = 1000;
+pub const MAX_PERIOD_MS: u64 = 2000;
+const INTERESTING_DEPTH: [u32; 5] = [0, 1, 2, 10, MAX_DEPTH];
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Reliability {
+    SystemDefault,
+    Reliable,
+    BestEffort,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Durability {
+    SystemDefault,
+    Volatile,
+    TransientLocal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum History {
+    SystemDefault,
+    KeepLast,
+    KeepAll,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Liveliness {
+    SystemDefault,
+    Automatic,
+    ManualByTopic,
+}
+
+/// Durations are in milliseconds, 0 leaves the policy unset.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Qos {
+    pub reliability: Reliability,
+    pub durability: Durability,
+    pub history: History,
+    pub depth: u32,
+    pub deadline_ms: u64,
+    pub lifespan_ms: u64,
+    pub liveliness: Liveliness,
+    pub lease_ms: u64,
+}
+
+impl Default for Qos {
+    // what `ros2 topic pub` uses
+    fn default() -> Self {
+        Qos {
+            reliability: Reliability::Reliable,
+            durability: Durability::Volatile,
+            history: History::KeepLast,
+            depth: 10,
+            deadline_ms: 0,
+            lifespan_ms: 0,
+            liveliness: Liveliness::SystemDefault,
+            lease_ms: 0,
+        }
+    }
+}
+
+impl Qos {
+    pub fn generate(rng: &mut RngType) -> Qos {
+        let mut qos = Qos::default();
+        // start from the default and change a few policies, a fully random profile is
+        // incompatible with almost every endpoint
+        for _ in 0..rng.gen_range(1..4) {
+            qos.mutate(rng);
+        }
+        qos
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..7) {
+            0 => {
+                self.reliability = [
+                    Reliability::SystemDefault,
+                    Reliability::Reliable,
+                    Reliability::BestEffort,
+                ][rng.gen_range(0..3)]
+            }
+            1 => {
+                self.durability = [
+                    Durability::SystemDefault,
+                    Durability::Volatile,
+                    Durability::TransientLocal,
+                ][rng.gen_range(0..3)]
+            }
+            2 => {
+                self.history =
+                    [History::SystemDefault, History::KeepLast, History::KeepAll][rng.gen_range(0..3)]
+            }
+            3 => {
+                self.depth = match rng.gen() {
+                    true => INTERESTING_DEPTH[rng.gen_range(0..INTERESTING_DEPTH.len())],
+                    false => rng.gen_range(0..=MAX_DEPTH),
+                }
+            }
+            4 => self.deadline_ms = gen_period(rng),
+            5 => self.lifespan_ms = gen_period(rng),
+            _ => {
+                self.liveliness = [
+                    Liveliness::SystemDefault,
+                    Liveliness::Automatic,
+                    Liveliness::ManualByTopic,
+                ][rng.gen_range(0..3)];
+                self.lease_ms = gen_period(rng);
+            }
+        }
+    }
+
+    // `ros2 topic pub` options, the cli has no deadline, lifespan or liveliness option
+    pub fn get_cli_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        let reliability = match self.reliability {
+            Reliability::SystemDefault => "system_default",
+            Reliability::Reliable => "reliable",
+            Reliability::BestEffort => "best_effort",
+        };
+        let durability = match self.durability {
+            Durability::SystemDefault => "system_default",
+            Durability::Volatile => "volatile",
+            Durability::TransientLocal => "transient_local",
+        };
+        let history = match self.history {
+            History::SystemDefault => "system_default",
+            History::KeepLast => "keep_last",
+            History::KeepAll => "keep_all",
+        };
+        for (flag, val) in [
+            ("--qos-reliability", reliability.to_string()),
+            ("--qos-durability", durability.to_string()),
+            ("--qos-history", history.to_string()),
+            ("--qos-depth", self.depth.to_string()),
+        ] {
+            options.push(flag.to_string());
+            options.push(val);
+        }
+        options
+    }
+
+    // `offered_qos_profiles` entry of a recorded topic, rosbag2 stores the rmw enum values
+    pub fn get_bag_profile(&self) -> String {
+        let history = match self.history {
+            History::SystemDefault => 0,
+            History::KeepLast => 1,
+            History::KeepAll => 2,
+        };
+        let reliability = match self.reliability {
+            Reliability::SystemDefault => 0,
+            Reliability::Reliable => 1,
+            Reliability::BestEffort => 2,
+        };
+        let durability = match self.durability {
+            Durability::SystemDefault => 0,
+            Durability::TransientLocal => 1,
+            Durability::Volatile => 2,
+        };
+        let liveliness = match self.liveliness {
+            Liveliness::SystemDefault => 0,
+            Liveliness::Automatic => 1,
+            Liveliness::ManualByTopic => 3,
+        };
+        let duration = |ms: u64| {
+            format!(
+                "\n    sec: {}\n    nsec: {}",
+                ms / 1000,
+                ms % 1000 * 1_000_000
+            )
+        };
+        format!(
+            "- history: {}\n  depth: {}\n  reliability: {}\n  durability: {}\n  deadline:{}\n  \
+             lifespan:{}\n  liveliness: {}\n  liveliness_lease_duration:{}\n  \
+             avoid_ros_namespace_conventions: false",
+            history,
+            self.depth,
+            reliability,
+            durability,
+            duration(self.deadline_ms),
+            duration(self.lifespan_ms),
+            liveliness,
+            duration(self.lease_ms)
+        )
+    }
+}
+
+// unset, tighter than any publishing rate, or loose
+fn gen_period(rng: &mut RngType) -> u64 {
+    match rng.gen_range(0..3) {
+        0 => 0,
+        1 => rng.gen_range(1..10),
+        _ => rng.gen_range(1..=MAX_PERIOD_MS),
+    }
+}
+
+/// QoS events reported by the cli warnings or the helper event callbacks, e.g.
+/// `incompatible:DURABILITY` or `deadline_missed`.
+pub fn parse_qos_events(output: &str) -> Vec<String> {
+    let mut events = Vec::new();
+    for line in output.lines() {
+        let event = if line.contains("incompatible QoS") || line.contains("QoS incompatible") {
+            // "Last incompatible policy: DURABILITY_QOS_POLICY"
+            let policy = line
+                .rsplit(':')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .trim_end_matches('.')
+                .trim_end_matches("_QOS_POLICY");
+            format!("incompatible:{}", policy)
+        } else if line.contains("deadline missed") {
+            "deadline_missed".to_string()
+        } else if line.contains("liveliness lost") {
+            "liveliness_lost".to_string()
+        } else {
+            continue;
+        };
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_options_and_events() {
+        let qos = Qos {
+            durability: Durability::TransientLocal,
+            depth: 1,
+            ..Default::default()
+        };
+        let options = qos.get_cli_options();
+        assert_eq!(options[3], "transient_local");
+        assert_eq!(options[7], "1");
+        let profile = qos.get_bag_profile();
+        assert!(profile.starts_with("- history: 1\n  depth: 1\n  reliability: 1\n  durability: 1"));
+
+        let output = "[WARN] [1700000000.1] [_ros2cli_1]: New subscription discovered on topic \
+            '/chatter', requesting incompatible QoS. No messages will be sent to it. Last \
+            incompatible policy: DURABILITY_QOS_POLICY\n\
+            QoS incompatible: RELIABILITY\n\
+            deadline missed\ndeadline missed\n";
+        assert_eq!(
+            parse_qos_events(output),
+            vec![
+                "incompatible:DURABILITY",
+                "incompatible:RELIABILITY",
+                "deadline_missed"
+            ]
+        );
+    }
+}
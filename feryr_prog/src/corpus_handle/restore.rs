@@ -0,0 +1,354 @@
+//! Node state restoration between inputs. The parameters of the fuzzed nodes are dumped once
+//! after boot and loaded back on demand, so that the result of an input does not depend on the
+//! parameter and lifecycle inputs before it. Crashes still go through a full reboot.
+use super::call::ROS2_BIN;
+use std::{fs, path::Path, process::Command};
+use util::fuzzer_info;
+
+/// When to restore, counted in executed inputs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestorePolicy {
+    Never,
+    Always,
+    Every(u64),
+}
+
+impl RestorePolicy {
+    // "never", "always" or a number of inputs, never when unknown
+    pub fn parse(policy: &str) -> RestorePolicy {
+        match policy {
+            "always" => RestorePolicy::Always,
+            _ => match policy.parse::<u64>() {
+                Ok(0) | Err(_) => RestorePolicy::Never,
+                Ok(1) => RestorePolicy::Always,
+                Ok(inputs) => RestorePolicy::Every(inputs),
+            },
+        }
+    }
+
+    pub fn is_due(&self, inputs: u64) -> bool {
+        match self {
+            RestorePolicy::Never => false,
+            RestorePolicy::Always => true,
+            RestorePolicy::Every(every) => inputs % every == 0,
+        }
+    }
+}
+
+// seconds a cli query may take, a node that stopped spinning never answers it
+const ROS2_TIMEOUT: &str = "10";
+
+/// How a node that was active at boot comes back after its deactivate transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reactivation {
+    Activate,
+    // unconfigured, finalized or gone, activate cannot reach active from there
+    Reboot,
+}
+
+impl Reactivation {
+    // deactivate fails on a node an input already moved, its state then tells whether activate
+    // still works
+    pub fn get(deactivate: &str, state: Option<&str>) -> Reactivation {
+        if deactivate.contains("successful") {
+            return Reactivation::Activate;
+        }
+        match state {
+            Some("inactive") => Reactivation::Activate,
+            _ => Reactivation::Reboot,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct NodeState {
+    pub node_name: String,
+    // `ros2 param dump` output, empty when the node has no parameter service
+    pub params: String,
+    // lifecycle state after boot, e.g. "active", None for unmanaged nodes
+    pub lifecycle: Option<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Snapshot {
+    pub nodes: Vec<NodeState>,
+}
+
+// answers one ros2 cli query, stdout of `ros2 <args>` for a live instance
+type Query<'a> = dyn FnMut(&[&str]) -> Result<String, failure::Error> + 'a;
+
+impl Snapshot {
+    pub fn take(node_names: &[String], shm_dir: &str) -> Result<Snapshot, failure::Error> {
+        Snapshot::take_with(node_names, &mut |args| ros2(shm_dir, args))
+    }
+
+    fn take_with(node_names: &[String], query: &mut Query) -> Result<Snapshot, failure::Error> {
+        let managed = query(&["lifecycle", "nodes"])?;
+        let managed: Vec<&str> = managed.lines().map(|line| line.trim()).collect();
+        let mut snapshot = Snapshot::default();
+        for node_name in node_names {
+            // printed to stdout since humble, older distros write <node>.yaml instead
+            let mut params = query(&["param", "dump", node_name])?;
+            if !params.contains("ros__parameters") {
+                fuzzer_info!("no parameters to restore for {}", node_name);
+                params.clear();
+            }
+            let lifecycle = match managed.contains(&node_name.as_str()) {
+                true => get_lifecycle(query, node_name)?,
+                false => None,
+            };
+            snapshot.nodes.push(NodeState {
+                node_name: node_name.to_string(),
+                params,
+                lifecycle,
+            });
+        }
+        Ok(snapshot)
+    }
+
+    // load the dumped parameters back, active managed nodes are deactivated around the load
+    // when lifecycle is set so that they pick the parameters up again on activation
+    pub fn restore(&self, shm_dir: &str, lifecycle: bool) -> Result<(), failure::Error> {
+        self.restore_with(shm_dir, lifecycle, &mut |args| ros2(shm_dir, args))
+    }
+
+    // the param files go to the instance shm dir, other instances restore the same nodes
+    fn restore_with(
+        &self,
+        shm_dir: &str,
+        lifecycle: bool,
+        query: &mut Query,
+    ) -> Result<(), failure::Error> {
+        for node in self.nodes.iter() {
+            let cycle = lifecycle && node.lifecycle.as_deref() == Some("active");
+            if cycle {
+                let output = query(&["lifecycle", "set", &node.node_name, "deactivate"])?;
+                let state = match output.contains("successful") {
+                    true => None,
+                    false => get_lifecycle(query, &node.node_name)?,
+                };
+                if Reactivation::get(&output, state.as_deref()) == Reactivation::Reboot {
+                    return Err(failure::format_err!(
+                        "{} is {}, only a reboot brings it back",
+                        node.node_name,
+                        state.as_deref().unwrap_or("unreachable")
+                    ));
+                }
+            }
+            if !node.params.is_empty() {
+                let path = Path::new(shm_dir).join(format!(
+                    "feryr_restore{}.yaml",
+                    node.node_name.replace('/', "_")
+                ));
+                fs::write(&path, &node.params)?;
+                let output = query(&["param", "load", &node.node_name, &path.to_string_lossy()]);
+                let _ = fs::remove_file(&path);
+                let output = output?;
+                if !output.contains("Set parameter") {
+                    return Err(failure::format_err!(
+                        "failed to restore parameters of {}: {}",
+                        node.node_name,
+                        output
+                    ));
+                }
+            }
+            if cycle {
+                let output = query(&["lifecycle", "set", &node.node_name, "activate"])?;
+                if !output.contains("successful") {
+                    return Err(failure::format_err!(
+                        "failed to reactivate {}: {}",
+                        node.node_name,
+                        output
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// current lifecycle state of a managed node, e.g. "active" from "active [3]"
+fn get_lifecycle(query: &mut Query, node_name: &str) -> Result<Option<String>, failure::Error> {
+    Ok(query(&["lifecycle", "get", node_name])?
+        .split_whitespace()
+        .next()
+        .map(|state| state.to_string()))
+}
+
+pub(crate) fn ros2(shm_dir: &str, args: &[&str]) -> Result<String, failure::Error> {
+    let output = Command::new("timeout")
+        .env("SHM_PATH", shm_dir)
+        .args([ROS2_TIMEOUT, ROS2_BIN])
+        .args(args)
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(RestorePolicy::parse("never"), RestorePolicy::Never);
+        assert_eq!(RestorePolicy::parse("always"), RestorePolicy::Always);
+        assert_eq!(RestorePolicy::parse("0"), RestorePolicy::Never);
+        assert_eq!(RestorePolicy::parse("1"), RestorePolicy::Always);
+        assert_eq!(RestorePolicy::parse("50"), RestorePolicy::Every(50));
+        assert_eq!(RestorePolicy::parse("-3"), RestorePolicy::Never);
+        assert_eq!(RestorePolicy::parse(""), RestorePolicy::Never);
+    }
+
+    #[test]
+    fn policy_is_due() {
+        assert!(!RestorePolicy::Never.is_due(1));
+        assert!((1..10).all(|inputs| RestorePolicy::Always.is_due(inputs)));
+        let every = RestorePolicy::Every(3);
+        let due: Vec<u64> = (1..10).filter(|&inputs| every.is_due(inputs)).collect();
+        assert_eq!(due, vec![3, 6, 9]);
+    }
+
+    #[test]
+    fn reactivation_after_deactivate() {
+        let ok = "Transitioning successful\n";
+        let refused = "Unknown transition requested, available ones are:\n";
+        assert_eq!(Reactivation::get(ok, None), Reactivation::Activate);
+        assert_eq!(
+            Reactivation::get(refused, Some("inactive")),
+            Reactivation::Activate
+        );
+        assert_eq!(
+            Reactivation::get(refused, Some("unconfigured")),
+            Reactivation::Reboot
+        );
+        assert_eq!(
+            Reactivation::get(refused, Some("finalized")),
+            Reactivation::Reboot
+        );
+        // the node did not answer at all
+        assert_eq!(Reactivation::get("", None), Reactivation::Reboot);
+    }
+
+    const PLANNER_PARAMS: &str = "/planner:\n  ros__parameters:\n    max_speed: 1.5\n";
+
+    // answers queries from canned cli output picked by command prefix, the queries and the
+    // param files they load are logged in order
+    fn canned<'a>(
+        answers: &'a [(&str, &str)],
+        log: &'a mut Vec<String>,
+    ) -> impl FnMut(&[&str]) -> Result<String, failure::Error> + 'a {
+        move |args| {
+            let query = args.join(" ");
+            if args[..2] == ["param", "load"] {
+                log.push(fs::read_to_string(args[3])?);
+            }
+            log.push(query.clone());
+            Ok(answers
+                .iter()
+                .find(|(prefix, _)| query.starts_with(prefix))
+                .map(|(_, output)| output.to_string())
+                .unwrap_or_default())
+        }
+    }
+
+    fn planner_snapshot() -> Snapshot {
+        let answers = [
+            ("lifecycle nodes", "/camera\n/planner\n"),
+            ("param dump /planner", PLANNER_PARAMS),
+            ("param dump /talker", "Parameter services not available\n"),
+            ("lifecycle get /planner", "active [3]\n"),
+        ];
+        let mut log = Vec::new();
+        let nodes = ["/planner".to_string(), "/talker".to_string()];
+        let snapshot = Snapshot::take_with(&nodes, &mut canned(&answers, &mut log)).unwrap();
+        // only managed nodes are asked for their state
+        assert_eq!(
+            log,
+            vec![
+                "lifecycle nodes",
+                "param dump /planner",
+                "lifecycle get /planner",
+                "param dump /talker"
+            ]
+        );
+        snapshot
+    }
+
+    #[test]
+    fn take_parses_params_and_states() {
+        let snapshot = planner_snapshot();
+        assert_eq!(snapshot.nodes.len(), 2);
+        let (planner, talker) = (&snapshot.nodes[0], &snapshot.nodes[1]);
+        assert_eq!(planner.params, PLANNER_PARAMS);
+        assert_eq!(planner.lifecycle.as_deref(), Some("active"));
+        assert!(talker.params.is_empty());
+        assert_eq!(talker.lifecycle, None);
+    }
+
+    #[test]
+    fn restore_cycles_active_nodes_around_the_load() {
+        let snapshot = planner_snapshot();
+        let shm_dir = std::env::temp_dir().join(format!("feryr-restore-{}", std::process::id()));
+        fs::create_dir_all(&shm_dir).unwrap();
+        let shm_dir = shm_dir.to_string_lossy().to_string();
+        let path = Path::new(&shm_dir).join("feryr_restore_planner.yaml");
+
+        let answers = [
+            ("lifecycle set", "Transitioning successful\n"),
+            ("param load", "Set parameter max_speed successful\n"),
+        ];
+        let mut log = Vec::new();
+        snapshot
+            .restore_with(&shm_dir, true, &mut canned(&answers, &mut log))
+            .unwrap();
+        let load = format!("param load /planner {}", path.display());
+        assert_eq!(
+            log,
+            vec![
+                "lifecycle set /planner deactivate",
+                PLANNER_PARAMS,
+                &load,
+                "lifecycle set /planner activate"
+            ]
+        );
+        // the file lives in the instance and is gone after the load
+        assert!(!path.exists());
+
+        // without lifecycle only the parameters go back
+        let mut log = Vec::new();
+        snapshot
+            .restore_with(&shm_dir, false, &mut canned(&answers, &mut log))
+            .unwrap();
+        assert_eq!(log, vec![PLANNER_PARAMS, &load]);
+
+        // a node an input finalized cannot be restored in place
+        let answers = [
+            (
+                "lifecycle set",
+                "Unknown transition requested, available ones are:\n",
+            ),
+            ("lifecycle get", "finalized [4]\n"),
+        ];
+        let mut log = Vec::new();
+        let e = snapshot
+            .restore_with(&shm_dir, true, &mut canned(&answers, &mut log))
+            .unwrap_err();
+        assert!(e.to_string().contains("finalized"));
+        assert_eq!(
+            log,
+            vec![
+                "lifecycle set /planner deactivate",
+                "lifecycle get /planner"
+            ]
+        );
+
+        // the load failing is reported
+        let answers = [("param load", "Failed to load parameters\n")];
+        let e = snapshot
+            .restore_with(&shm_dir, false, &mut canned(&answers, &mut Vec::new()))
+            .unwrap_err();
+        assert!(e.to_string().contains("/planner"));
+        assert!(!path.exists());
+        fs::remove_dir_all(&shm_dir).unwrap();
+    }
+}
@@ -0,0 +1,373 @@
+//! Semantic generators keyed by ROS type. Message trees built by `construct_itf_layers` are
+//! filled by a generator of their type when there is one, so that stamps, orientations, frames
+//! and covariances are mostly plausible and sometimes broken in a way the receiver should catch,
+//! instead of being random doubles that are rejected before any interesting code runs.
+use super::{
+    interface::{InterfaceVal, ValueType},
+    sensor::{CompressedImageGen, ImageGen, LaserScanGen, OccupancyGridGen, PointCloud2Gen},
+    tf::TfGen,
+    ty::double::DoubleType,
+    RngType,
+};
+use rand::{seq::SliceRandom, Rng};
+use std::{
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// share of values of a known type that go through its generator, the rest stay random
+pub const SEMANTIC_RATIO: (u32, u32) = (3, 4);
+const NANOSEC: i64 = 1_000_000_000;
+
+/// What the generators know about the running target, fed by the fuzzer after boot.
+#[derive(Debug, Default, Clone)]
+pub struct SemanticCtx {
+    // frame ids seen on /tf and /tf_static
+    pub frames: Vec<String>,
+    // parent and child frame of every transform seen there
+    pub tf_edges: Vec<(String, String)>,
+    // simulation time minus wall time in nanoseconds, when the target runs on /clock
+    pub sim_offset: Option<i64>,
+}
+
+impl SemanticCtx {
+    // (sec, nanosec) of the clock the target uses
+    pub fn now(&self) -> (i64, i64) {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_nanos() as i64)
+            .unwrap_or(0);
+        let now = wall + self.sim_offset.unwrap_or(0);
+        (now.div_euclid(NANOSEC), now.rem_euclid(NANOSEC))
+    }
+}
+
+pub trait SemanticGen: Send + Sync {
+    // fill a value whose layers are already built, `itf.gen_fields` gives the random default
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error>;
+}
+
+lazy_static::lazy_static! {
+    pub static ref SEMANTIC_CTX: Mutex<SemanticCtx> = Mutex::new(SemanticCtx::default());
+    static ref GENERATORS: Mutex<Vec<(String, Arc<dyn SemanticGen>)>> = Mutex::new(vec![
+        gen_entry("builtin_interfaces/Time", TimeGen),
+        gen_entry("geometry_msgs/Quaternion", QuaternionGen),
+        gen_entry("std_msgs/Header", HeaderGen),
+        gen_entry("geometry_msgs/PoseWithCovariance", CovarianceGen),
+        gen_entry("geometry_msgs/TwistWithCovariance", CovarianceGen),
+        gen_entry("geometry_msgs/AccelWithCovariance", CovarianceGen),
+        gen_entry("sensor_msgs/Imu", CovarianceGen),
+        gen_entry("sensor_msgs/Image", ImageGen),
+        gen_entry("sensor_msgs/CompressedImage", CompressedImageGen),
+        gen_entry("sensor_msgs/PointCloud2", PointCloud2Gen),
+        gen_entry("sensor_msgs/LaserScan", LaserScanGen),
+        gen_entry("nav_msgs/OccupancyGrid", OccupancyGridGen),
+        gen_entry("tf2_msgs/TFMessage", TfGen),
+    ]);
+}
+
+fn gen_entry(ros_type: &str, gen: impl SemanticGen + 'static) -> (String, Arc<dyn SemanticGen>) {
+    (ros_type.to_string(), Arc::new(gen))
+}
+
+/// Add or replace the generator of `ros_type`, given as `pkg/Name`.
+pub fn register(ros_type: &str, gen: impl SemanticGen + 'static) {
+    let mut generators = GENERATORS.lock().unwrap();
+    generators.retain(|(other, _)| other != ros_type);
+    generators.push(gen_entry(ros_type, gen));
+}
+
+// field types come as `pkg/msg/Name`, `pkg/Name` or a bare `Name` inside their own package
+pub fn get_generator(itf_type: &str) -> Option<Arc<dyn SemanticGen>> {
+    let itf_type = itf_type.replace("/msg/", "/");
+    let generators = GENERATORS.lock().unwrap();
+    generators
+        .iter()
+        .find(|(ros_type, _)| match itf_type.contains('/') {
+            true => *ros_type == itf_type,
+            false => ros_type.rsplit('/').next() == Some(itf_type.as_str()),
+        })
+        .map(|(_, gen)| gen.clone())
+}
+
+pub fn observe_frames(frames: Vec<String>) {
+    let mut ctx = SEMANTIC_CTX.lock().unwrap();
+    for frame in frames {
+        if !frame.is_empty() && !ctx.frames.contains(&frame) {
+            ctx.frames.push(frame);
+        }
+    }
+}
+
+// `frame_id: map` and `child_frame_id: base_link` lines of `ros2 topic echo /tf`
+pub fn parse_frames(echo: &str) -> Vec<String> {
+    echo.lines()
+        .filter_map(|line| {
+            let (key, val) = line.trim().split_once(':')?;
+            match key.trim_start_matches("- ") {
+                "frame_id" | "child_frame_id" => Some(val.trim().trim_matches('\'').to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+// the frame tree in `ros2 topic echo /tf`, the header frame is the parent of the child frame
+pub fn parse_tf_edges(echo: &str) -> Vec<(String, String)> {
+    let mut edges = Vec::new();
+    let mut parent = None;
+    for line in echo.lines() {
+        let (key, val) = match line.trim().split_once(':') {
+            Some(field) => field,
+            None => continue,
+        };
+        let val = val.trim().trim_matches('\'').to_string();
+        match key.trim_start_matches("- ") {
+            "frame_id" => parent = Some(val),
+            "child_frame_id" => {
+                if let Some(parent) = parent.take() {
+                    edges.push((parent, val));
+                }
+            }
+            _ => (),
+        }
+    }
+    edges
+}
+
+pub fn observe_tf(echo: &str) {
+    observe_frames(parse_frames(echo));
+    let mut ctx = SEMANTIC_CTX.lock().unwrap();
+    for edge in parse_tf_edges(echo) {
+        if !ctx.tf_edges.contains(&edge) {
+            ctx.tf_edges.push(edge);
+        }
+    }
+}
+
+// `ros2 topic echo /clock`, the first stamp of the window. The offset follows the simulation as
+// long as it runs at real time, a stamp off by a bit is still a plausible one
+pub fn observe_clock(echo: &str) {
+    let field = |name: &str| {
+        echo.lines()
+            .find_map(|line| line.trim().strip_prefix(name)?.trim().parse::<i64>().ok())
+    };
+    if let (Some(sec), Some(nanosec)) = (field("sec:"), field("nanosec:")) {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_nanos() as i64)
+            .unwrap_or(0);
+        SEMANTIC_CTX.lock().unwrap().sim_offset = Some(sec * NANOSEC + nanosec - wall);
+    }
+}
+
+pub(crate) fn field_mut<'a>(itf: &'a mut InterfaceVal, name: &str) -> Option<&'a mut InterfaceVal> {
+    itf.val.iter_mut().find_map(|val| match val {
+        ValueType::Op(field) if field.itf_name == name => Some(field),
+        _ => None,
+    })
+}
+
+pub(crate) fn set_int(itf: &mut InterfaceVal, name: &str, val: i64) {
+    if let Some(ValueType::Op1(int)) = field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        int.set_val(val as u64);
+    }
+}
+
+pub(crate) fn set_double(itf: &mut InterfaceVal, name: &str, val: f64) {
+    if let Some(ValueType::Op3(double)) =
+        field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        double.set_val(val);
+    }
+}
+
+pub(crate) fn set_string(itf: &mut InterfaceVal, name: &str, val: String) {
+    if let Some(ValueType::Op6(string)) =
+        field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        string.set_val(val);
+    }
+}
+
+/// Stamps around the clock of the target, sometimes zero, far off, or with nanosec overflowing.
+pub struct TimeGen;
+
+impl SemanticGen for TimeGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let (now, _) = ctx.now();
+        let (sec, nanosec) = match rng.gen_range(0..8) {
+            0..=4 => (now + rng.gen_range(-2..=1), rng.gen_range(0..NANOSEC)),
+            5 => (0, 0),
+            6 => {
+                let off = rng.gen_range(3600..86400 * 365);
+                (
+                    now + if rng.gen() { off } else { -off },
+                    rng.gen_range(0..NANOSEC),
+                )
+            }
+            _ => (now, rng.gen_range(NANOSEC..=u32::MAX as i64)),
+        };
+        set_int(itf, "sec", sec);
+        set_int(itf, "nanosec", nanosec);
+        Ok(())
+    }
+}
+
+/// Unit quaternions, slightly off unit, or degenerate.
+pub struct QuaternionGen;
+
+impl SemanticGen for QuaternionGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let mut q: [f64; 4] = [0.0; 4];
+        q.iter_mut()
+            .for_each(|val| *val = rng.gen_range(-1.0..=1.0));
+        let norm = q
+            .iter()
+            .map(|val| val * val)
+            .sum::<f64>()
+            .sqrt()
+            .max(f64::EPSILON);
+        let scale = match rng.gen_range(0..8) {
+            0..=4 => 1.0,
+            5 | 6 => 1.0 + rng.gen_range(-1e-3..=1e-3),
+            _ => 0.0,
+        };
+        for (name, val) in ["x", "y", "z", "w"].iter().zip(q.iter()) {
+            set_double(itf, name, val / norm * scale);
+        }
+        Ok(())
+    }
+}
+
+/// A stamp from the time generator and a frame the target knows, sometimes an unknown one.
+pub struct HeaderGen;
+
+impl SemanticGen for HeaderGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        if let Some(stamp) = field_mut(itf, "stamp") {
+            TimeGen.generate(stamp, ctx, rng)?;
+        }
+        let frame = match (ctx.frames.choose(rng), rng.gen_ratio(1, 8)) {
+            (Some(frame), false) => frame.clone(),
+            _ => ["", "/", "unknown_frame", "map/"]
+                .choose(rng)
+                .unwrap()
+                .to_string(),
+        };
+        set_string(itf, "frame_id", frame);
+        Ok(())
+    }
+}
+
+/// Row-major covariance fields, symmetric positive semi-definite or broken: asymmetric, with
+/// negative or non finite variances.
+pub struct CovarianceGen;
+
+impl SemanticGen for CovarianceGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        for val in itf.val.iter_mut() {
+            let field = match val {
+                ValueType::Op(field) if field.itf_name.ends_with("covariance") => field,
+                _ => continue,
+            };
+            // float64[36] for 6 dof, float64[9] for 3
+            let len = field
+                .itf_type
+                .split_once('[')
+                .and_then(|(_, len)| len.trim_end_matches(']').parse::<f64>().ok())
+                .unwrap_or(0.0);
+            let dim = len.sqrt() as usize;
+            if let Some(ValueType::Op5(array)) = field.val.first_mut() {
+                let matrix = gen_covariance(dim.max(1), rng);
+                array.set_float_array(
+                    matrix
+                        .into_iter()
+                        .map(|val| DoubleType::new(0, val, f64::MAX, f64::MIN, 64))
+                        .collect(),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn gen_covariance(dim: usize, rng: &mut RngType) -> Vec<f64> {
+    // a * a^T is symmetric positive semi-definite
+    let a: Vec<f64> = (0..dim * dim).map(|_| rng.gen_range(-1.0..=1.0)).collect();
+    let mut cov = vec![0.0; dim * dim];
+    for i in 0..dim {
+        for j in 0..dim {
+            cov[i * dim + j] = (0..dim).map(|k| a[i * dim + k] * a[j * dim + k]).sum();
+        }
+    }
+    let (i, j) = (rng.gen_range(0..dim), rng.gen_range(0..dim));
+    match rng.gen_range(0..8) {
+        // all zero, i.e. unknown for most consumers
+        0 => cov.iter_mut().for_each(|val| *val = 0.0),
+        // -1 in the first element marks the estimate as missing
+        1 => cov[0] = -1.0,
+        2 => cov[i * dim + j] += 1.0 + rng.gen::<f64>(),
+        3 => cov[i * dim + i] = -cov[i * dim + i] - 1.0,
+        4 => cov[i * dim + j] = *[f64::NAN, f64::INFINITY].choose(rng).unwrap(),
+        _ => (),
+    }
+    cov
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn generators_by_type_and_frames() {
+        assert!(get_generator("builtin_interfaces/msg/Time").is_some());
+        assert!(get_generator("builtin_interfaces/Time").is_some());
+        assert!(get_generator("PoseWithCovariance").is_some());
+        assert!(get_generator("geometry_msgs/Pose").is_none());
+
+        let echo = "transforms:\n- header:\n    frame_id: odom\n  child_frame_id: base_link\n";
+        assert_eq!(parse_frames(echo), vec!["odom", "base_link"]);
+        let edge = ("odom".to_string(), "base_link".to_string());
+        assert_eq!(parse_tf_edges(echo), vec![edge]);
+
+        let mut rng = RngType::seed_from_u64(0);
+        let mut symmetric = 0;
+        for _ in 0..64 {
+            let cov = gen_covariance(6, &mut rng);
+            assert_eq!(cov.len(), 36);
+            if (0..6).all(|i| (0..6).all(|j| cov[i * 6 + j] == cov[j * 6 + i])) {
+                symmetric += 1;
+            }
+        }
+        assert!(symmetric > 32 && symmetric < 64);
+    }
+}
@@ -0,0 +1,529 @@
+//! Layout-consistent generators for sensor and map messages. Perception code indexes into
+//! `data` with the dimensions, steps and field offsets of the message, so these are generated
+//! consistent with each other, and now and then exactly one invariant is broken to reach the
+//! checks, or the missing checks, behind them.
+use super::{
+    interface::{InterfaceVal, ValueType},
+    semantic::{field_mut, set_double, set_int, set_string, SemanticCtx, SemanticGen},
+    ty::{character::StringType, double::DoubleType, integer::IntType, TYPE},
+    RngType,
+};
+use rand::{seq::SliceRandom, Rng};
+
+// share of generated messages with one broken invariant
+pub const BREAK_RATIO: (u32, u32) = (1, 4);
+// `ros2 topic pub` takes the message as one argument, which is limited to 128 KiB, and a byte
+// takes up to 6 characters of yaml
+pub const MAX_DATA: usize = 16 << 10;
+
+// (encoding, bytes per pixel)
+const ENCODINGS: [(&str, u32); 8] = [
+    ("rgb8", 3),
+    ("bgr8", 3),
+    ("rgba8", 4),
+    ("mono8", 1),
+    ("mono16", 2),
+    ("16UC1", 2),
+    ("32FC1", 4),
+    ("bayer_rggb8", 1),
+];
+
+// sensor_msgs/msg/PointField datatype constants and their sizes
+const FLOAT32: u8 = 7;
+const FLOAT64: u8 = 8;
+fn datatype_size(datatype: u8) -> u32 {
+    match datatype {
+        1 | 2 => 1,
+        3 | 4 => 2,
+        5..=7 => 4,
+        8 => 8,
+        _ => 0,
+    }
+}
+
+// one of `invariants` to break, or None for a consistent message
+fn pick_broken(invariants: &[&'static str], rng: &mut RngType) -> Option<&'static str> {
+    match rng.gen_ratio(BREAK_RATIO.0, BREAK_RATIO.1) {
+        true => invariants.choose(rng).copied(),
+        false => None,
+    }
+}
+
+fn gen_bytes(len: usize, rng: &mut RngType) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rng.fill(&mut bytes[..]);
+    bytes
+}
+
+fn set_bytes(itf: &mut InterfaceVal, name: &str, bytes: Vec<u8>) {
+    if let Some(ValueType::Op5(array)) =
+        field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        array.set_bytes(bytes);
+    }
+}
+
+fn set_floats(itf: &mut InterfaceVal, name: &str, vals: Vec<f64>) {
+    if let Some(ValueType::Op5(array)) =
+        field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        let pad = match array.get_inner_type() {
+            TYPE::Float32 => 32,
+            _ => 64,
+        };
+        array.set_float_array(
+            vals.into_iter()
+                .map(|val| DoubleType::new(0, val, f64::MAX, f64::MIN, pad))
+                .collect(),
+        );
+    }
+}
+
+fn set_bool(itf: &mut InterfaceVal, name: &str, val: bool) {
+    if let Some(ValueType::Op2(bool)) = field_mut(itf, name).and_then(|field| field.val.first_mut())
+    {
+        bool.set_val(val);
+    }
+}
+
+/// `sensor_msgs/Image`, `step` covers a row of `width` pixels of the encoding, `data` holds
+/// `height` rows.
+pub struct ImageGen;
+
+impl SemanticGen for ImageGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        let (encoding, bpp) = *ENCODINGS.choose(rng).unwrap();
+        let mut width = rng.gen_range(1..=64u32);
+        // rows may be padded
+        let mut step = width * bpp
+            + match rng.gen_ratio(1, 4) {
+                true => rng.gen_range(1..4),
+                false => 0,
+            };
+        let height = rng.gen_range(1..=(MAX_DATA as u32 / step).min(64));
+        let mut len = (step * height) as usize;
+        let mut encoding = encoding.to_string();
+        match pick_broken(
+            &["step", "data_short", "data_long", "encoding", "overflow"],
+            rng,
+        ) {
+            Some("step") => step = width * bpp - rng.gen_range(1..=width * bpp),
+            Some("data_short") => len -= rng.gen_range(1..=len),
+            Some("data_long") => len += rng.gen_range(1..=step as usize),
+            Some("encoding") => {
+                encoding = ["", "rgb", "yuv422_10"].choose(rng).unwrap().to_string()
+            }
+            // width * bpp or step * height wraps around u32 in code that does not widen first
+            Some(_) => {
+                width = u32::MAX / bpp.max(2) + 1;
+                step = width.wrapping_mul(bpp);
+            }
+            None => (),
+        }
+        set_int(itf, "width", width as i64);
+        set_int(itf, "height", height as i64);
+        set_int(itf, "step", step as i64);
+        set_int(itf, "is_bigendian", 0);
+        set_string(itf, "encoding", encoding);
+        set_bytes(itf, "data", gen_bytes(len, rng));
+        Ok(())
+    }
+}
+
+/// `sensor_msgs/CompressedImage`, `data` starts with the signature of `format` and ends with
+/// its trailer.
+pub struct CompressedImageGen;
+
+const JPEG: (&[u8], &[u8]) = (&[0xff, 0xd8, 0xff, 0xe0], &[0xff, 0xd9]);
+const PNG: (&[u8], &[u8]) = (
+    &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'],
+    &[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82],
+);
+
+impl SemanticGen for CompressedImageGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        let (mut format, (mut magic, trailer)) = match rng.gen() {
+            true => ("jpeg", JPEG),
+            false => ("png", PNG),
+        };
+        let mut body = gen_bytes(rng.gen_range(0..MAX_DATA / 4), rng);
+        match pick_broken(&["magic", "truncated", "format", "empty"], rng) {
+            Some("magic") => magic = if format == "jpeg" { PNG.0 } else { JPEG.0 },
+            Some("truncated") => body.clear(),
+            Some("format") => {
+                format = *["", "bmp", "16UC1; compressedDepth"].choose(rng).unwrap();
+            }
+            Some(_) => {
+                set_bytes(itf, "data", Vec::new());
+                set_string(itf, "format", format.to_string());
+                return Ok(());
+            }
+            None => (),
+        }
+        let mut data = magic.to_vec();
+        if !body.is_empty() {
+            data.append(&mut body);
+            data.extend_from_slice(trailer);
+        }
+        set_string(itf, "format", format.to_string());
+        set_bytes(itf, "data", data);
+        Ok(())
+    }
+}
+
+/// `sensor_msgs/PointCloud2`, `fields` fit in `point_step`, rows are `width` points long and
+/// `data` holds `height` rows.
+pub struct PointCloud2Gen;
+
+// (name, datatype) of common layouts, offsets are packed or padded to 16 bytes like pcl
+const LAYOUTS: [&[(&str, u8)]; 4] = [
+    &[("x", FLOAT32), ("y", FLOAT32), ("z", FLOAT32)],
+    &[
+        ("x", FLOAT32),
+        ("y", FLOAT32),
+        ("z", FLOAT32),
+        ("intensity", FLOAT32),
+    ],
+    &[
+        ("x", FLOAT32),
+        ("y", FLOAT32),
+        ("z", FLOAT32),
+        ("rgb", FLOAT32),
+    ],
+    &[("x", FLOAT64), ("y", FLOAT64), ("z", FLOAT64)],
+];
+
+struct PointField {
+    name: String,
+    offset: u32,
+    datatype: u8,
+    count: u32,
+}
+
+impl PointField {
+    fn to_itf(&self) -> InterfaceVal {
+        let leaf = |name: &str, ty: &str, val: ValueType| {
+            let mut leaf = InterfaceVal::new(&name.to_string(), &ty.to_string());
+            leaf.val.push(val);
+            ValueType::Op(leaf)
+        };
+        let uint = |ty: TYPE, val: u64, max: u64, pad: i32| {
+            ValueType::Op1(IntType::new(ty as usize, val, max, 0, pad))
+        };
+        let mut field = InterfaceVal::new(&"fields".to_string(), &"PointField[]".to_string());
+        field.val = vec![
+            leaf(
+                "name",
+                "string",
+                ValueType::Op6(StringType::new(
+                    TYPE::String as usize,
+                    self.name.clone(),
+                    self.name.len() as i32,
+                )),
+            ),
+            leaf(
+                "offset",
+                "uint32",
+                uint(TYPE::UInt32, self.offset as u64, u32::MAX as u64, 32),
+            ),
+            leaf(
+                "datatype",
+                "uint8",
+                uint(TYPE::UInt8, self.datatype as u64, u8::MAX as u64, 8),
+            ),
+            leaf(
+                "count",
+                "uint32",
+                uint(TYPE::UInt32, self.count as u64, u32::MAX as u64, 32),
+            ),
+        ];
+        field
+    }
+}
+
+impl SemanticGen for PointCloud2Gen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        let padded: bool = rng.gen();
+        let mut fields = Vec::new();
+        let mut point_step = 0;
+        for (name, datatype) in LAYOUTS.choose(rng).unwrap().iter() {
+            // pcl puts the fourth float of a point at 16
+            if padded && fields.len() == 3 && *datatype == FLOAT32 {
+                point_step = 16;
+            }
+            fields.push(PointField {
+                name: name.to_string(),
+                offset: point_step,
+                datatype: *datatype,
+                count: 1,
+            });
+            point_step += datatype_size(*datatype);
+        }
+        if padded {
+            point_step = point_step.div_ceil(16) * 16;
+        }
+        let height = match rng.gen() {
+            true => 1,
+            false => rng.gen_range(1..=4),
+        };
+        let width = rng.gen_range(1..=(MAX_DATA as u32 / (point_step * height)).min(128));
+        let mut row_step = width * point_step;
+        let mut data = gen_bytes((row_step * height) as usize, rng);
+        // plausible coordinates, NaN for invalid points of a cloud that is not dense
+        let is_dense: bool = rng.gen();
+        for point in data.chunks_exact_mut(point_step as usize) {
+            let invalid = !is_dense && rng.gen_ratio(1, 8);
+            for field in fields.iter() {
+                let val = match invalid {
+                    true => f64::NAN,
+                    false => rng.gen_range(-20.0..20.0),
+                };
+                let offset = field.offset as usize;
+                match field.datatype {
+                    FLOAT64 => point[offset..offset + 8].copy_from_slice(&val.to_le_bytes()),
+                    _ => point[offset..offset + 4].copy_from_slice(&(val as f32).to_le_bytes()),
+                }
+            }
+        }
+
+        let last = fields.len() - 1;
+        match pick_broken(
+            &[
+                "point_step",
+                "offset",
+                "row_step",
+                "data",
+                "datatype",
+                "count",
+                "duplicate",
+            ],
+            rng,
+        ) {
+            Some("point_step") => point_step = fields[last].offset,
+            Some("offset") => fields[last].offset = point_step + rng.gen_range(0..8),
+            Some("row_step") => row_step -= rng.gen_range(1..=row_step),
+            Some("data") => {
+                let len = data.len() - rng.gen_range(1..=data.len());
+                data.truncate(len);
+            }
+            Some("datatype") => fields[last].datatype = *[0, 9, u8::MAX].choose(rng).unwrap(),
+            Some("count") => fields[last].count = *[0, 2, u32::MAX].choose(rng).unwrap(),
+            Some(_) => fields[last].name = fields[0].name.clone(),
+            None => (),
+        }
+
+        // replace the generated fields where they were
+        let pos = itf
+            .val
+            .iter()
+            .position(|val| matches!(val, ValueType::Op(field) if field.itf_name == "fields"))
+            .unwrap_or(itf.val.len());
+        itf.val
+            .retain(|val| !matches!(val, ValueType::Op(field) if field.itf_name == "fields"));
+        for (idx, field) in fields.iter().enumerate() {
+            itf.val.insert(pos + idx, ValueType::Op(field.to_itf()));
+        }
+        set_int(itf, "width", width as i64);
+        set_int(itf, "height", height as i64);
+        set_int(itf, "point_step", point_step as i64);
+        set_int(itf, "row_step", row_step as i64);
+        set_bool(itf, "is_bigendian", false);
+        set_bool(itf, "is_dense", is_dense);
+        set_bytes(itf, "data", data);
+        Ok(())
+    }
+}
+
+/// `sensor_msgs/LaserScan`, one range per angle increment from angle_min to angle_max, within
+/// [range_min, range_max] or +inf for no return.
+pub struct LaserScanGen;
+
+impl SemanticGen for LaserScanGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        let count = rng.gen_range(2..=720usize);
+        let fov = rng.gen_range(0.1..=std::f64::consts::TAU);
+        let (mut angle_min, mut angle_max) = (-fov / 2.0, fov / 2.0);
+        let mut angle_increment = fov / (count - 1) as f64;
+        let scan_time = rng.gen_range(0.01..=0.2);
+        let (mut range_min, mut range_max) = (rng.gen_range(0.0..0.5), rng.gen_range(1.0..=30.0));
+        let mut ranges: Vec<f64> = (0..count)
+            .map(|_| match rng.gen_ratio(1, 16) {
+                true => f64::INFINITY,
+                false => rng.gen_range(range_min..=range_max),
+            })
+            .collect();
+        let mut intensities: Vec<f64> = match rng.gen() {
+            true => (0..count).map(|_| rng.gen_range(0.0..=255.0)).collect(),
+            false => Vec::new(),
+        };
+        match pick_broken(
+            &[
+                "ranges",
+                "increment",
+                "sign",
+                "range_limits",
+                "intensities",
+                "angles",
+            ],
+            rng,
+        ) {
+            Some("ranges") => match rng.gen() {
+                true => ranges.push(range_max),
+                false => ranges.truncate(count - 1),
+            },
+            Some("increment") => angle_increment = 0.0,
+            Some("sign") => angle_increment = -angle_increment,
+            Some("range_limits") => std::mem::swap(&mut range_min, &mut range_max),
+            Some("intensities") => intensities.truncate(rng.gen_range(1..count)),
+            Some(_) => std::mem::swap(&mut angle_min, &mut angle_max),
+            None => (),
+        }
+        set_double(itf, "angle_min", angle_min);
+        set_double(itf, "angle_max", angle_max);
+        set_double(itf, "angle_increment", angle_increment);
+        set_double(itf, "time_increment", scan_time / count as f64);
+        set_double(itf, "scan_time", scan_time);
+        set_double(itf, "range_min", range_min);
+        set_double(itf, "range_max", range_max);
+        set_floats(itf, "ranges", ranges);
+        set_floats(itf, "intensities", intensities);
+        Ok(())
+    }
+}
+
+/// `nav_msgs/OccupancyGrid`, `width * height` cells of -1 for unknown or 0 to 100.
+pub struct OccupancyGridGen;
+
+impl SemanticGen for OccupancyGridGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        _ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        itf.gen_fields(rng)?;
+        let mut width = rng.gen_range(1..=128u32);
+        let height = rng.gen_range(1..=(MAX_DATA as u32 / width).min(128));
+        let mut resolution = rng.gen_range(0.01..=1.0);
+        let mut data: Vec<u8> = (0..width * height)
+            .map(|_| match rng.gen_ratio(1, 4) {
+                true => -1i8 as u8,
+                false => rng.gen_range(0..=100),
+            })
+            .collect();
+        match pick_broken(&["data", "cell", "resolution", "overflow"], rng) {
+            Some("data") => match rng.gen() {
+                true => data.truncate(data.len() - rng.gen_range(1..=data.len())),
+                false => data.resize(data.len() + width as usize, 0),
+            },
+            Some("cell") => {
+                let idx = rng.gen_range(0..data.len());
+                data[idx] = rng.gen_range(101..=254);
+            }
+            Some("resolution") => resolution = *[0.0, -0.05, f64::NAN].choose(rng).unwrap(),
+            Some(_) => width = u32::MAX / height.max(2) + 1,
+            None => (),
+        }
+        if let Some(info) = field_mut(itf, "info") {
+            set_double(info, "resolution", resolution);
+            set_int(info, "width", width as i64);
+            set_int(info, "height", height as i64);
+        }
+        set_bytes(itf, "data", data);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::ty::array::ArrayType;
+    use rand::SeedableRng;
+
+    fn leaf(name: &str, ty: &str, val: ValueType) -> ValueType {
+        let mut leaf = InterfaceVal::new(&name.to_string(), &ty.to_string());
+        leaf.val.push(val);
+        ValueType::Op(leaf)
+    }
+
+    fn uint(pad: i32) -> ValueType {
+        ValueType::Op1(IntType::new(
+            TYPE::UInt32 as usize,
+            0,
+            u32::MAX as u64,
+            0,
+            pad,
+        ))
+    }
+
+    fn get_int(itf: &mut InterfaceVal, name: &str) -> u64 {
+        match field_mut(itf, name).unwrap().val.first() {
+            Some(ValueType::Op1(int)) => int.get_num(),
+            _ => panic!("{} is not an int", name),
+        }
+    }
+
+    #[test]
+    fn image_layout_is_consistent_unless_broken() {
+        let mut rng = RngType::seed_from_u64(0);
+        let (mut consistent, mut broken) = (0, 0);
+        for _ in 0..64 {
+            let mut image = InterfaceVal::new(&"msg".to_string(), &"sensor_msgs/Image".to_string());
+            for name in ["height", "width", "step"] {
+                image.val.push(leaf(name, "uint32", uint(32)));
+            }
+            image.val.push(leaf("is_bigendian", "uint8", uint(8)));
+            image.val.push(leaf(
+                "encoding",
+                "string",
+                ValueType::Op6(StringType::new(0, String::new(), 4)),
+            ));
+            image.val.push(leaf(
+                "data",
+                "uint8[]",
+                ValueType::Op5(ArrayType::new(TYPE::ARRAY as usize, TYPE::UInt8, 0)),
+            ));
+            ImageGen
+                .generate(&mut image, &SemanticCtx::default(), &mut rng)
+                .unwrap();
+
+            let (height, step) = (get_int(&mut image, "height"), get_int(&mut image, "step"));
+            let width = get_int(&mut image, "width");
+            let len = match field_mut(&mut image, "data").unwrap().val.first() {
+                Some(ValueType::Op5(array)) => array.get_bytes().len() as u64,
+                _ => panic!("data is not an array"),
+            };
+            let bpp = step / width.max(1);
+            if len == step * height && (1..=4).contains(&bpp) {
+                consistent += 1;
+            } else {
+                broken += 1;
+            }
+        }
+        assert!(consistent > broken && broken > 0);
+    }
+}
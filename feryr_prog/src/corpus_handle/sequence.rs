@@ -0,0 +1,1473 @@
+use super::{
+    super::cover_handle::callgraph::CbOverlap,
+    action::{ActionCall, ActionOp, ActionOutcome, GoalId},
+    bag::{get_bag_type, BagMessage, BagTopic},
+    call::CallArgs,
+    cdr::{CdrDecoder, CdrEncoder},
+    clock::{
+        get_clock_cdr, parse_ticks, ClockFiring, ClockStream, ClockTick, CLOCK_TOPIC, CLOCK_TYPE,
+    },
+    executor::{ExecOutput, ExecutorError},
+    impersonate::Impersonation,
+    interface::{Node, ITF},
+    lifecycle::{LifecycleCall, LifecycleOutcome, LifecycleState},
+    param::ConfusionResult,
+    prog::Prog,
+    qos::{parse_qos_events, Qos},
+    target::Target,
+    tf::{get_log_len, is_tf_topic, read_tf_errors, TfOutcome},
+    timing::Timing,
+    RngType,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use util::fuzzer_info;
+
+pub const MAX_SEQ_LEN: usize = 8;
+pub const MAX_DELAY_US: u64 = 1_000_000;
+// calls of a concurrent group start within this window
+pub const MAX_OFFSET_US: u64 = 10_000;
+
+// one call of a sequence, sent after waiting delay_us. A parallel call joins the group of the
+// call before it and delay_us is its start offset from the first call of the group.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SeqCall {
+    pub prog: Prog,
+    pub delay_us: u64,
+    #[serde(default)]
+    pub parallel: bool,
+}
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct CallOutcome {
+    pub idx: usize,
+    // wall clock in ns, comparable with the timestamps in shm
+    pub start_time: u128,
+    pub duration: u128,
+    pub interesting: bool,
+    pub error: String,
+    // callbacks seen running at the same time, only kept on the first call of a group
+    #[serde(default)]
+    pub overlaps: Vec<CbOverlap>,
+    // goal id, feedback and result seen by an action call
+    #[serde(default)]
+    pub action: Option<ActionOutcome>,
+    // client callbacks that got a response during the call, e.g. from an impostor
+    #[serde(default)]
+    pub client_callbacks: Vec<u64>,
+    // subscriber callbacks that reacted to an impersonated topic
+    #[serde(default)]
+    pub consumers: Vec<u64>,
+    // qos incompatibilities, missed deadlines and lost liveliness reported for the call
+    #[serde(default)]
+    pub qos_events: Vec<String>,
+    // how the node took a parameter of the wrong type
+    #[serde(default)]
+    pub confusion: Option<ConfusionResult>,
+    // transition of a managed node and the state it left the node in
+    #[serde(default)]
+    pub lifecycle: Option<LifecycleOutcome>,
+    // timer starts during a clock stream and the simulated time they saw
+    #[serde(default)]
+    pub clock: Vec<ClockFiring>,
+    // tf2 errors and latency of the consumers of an injected /tf or /tf_static message
+    #[serde(default)]
+    pub tf: Option<TfOutcome>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum SeqMutation {
+    Insert,
+    Remove,
+    Swap,
+    Splice,
+    Replace,
+    Delay,
+    Timing,
+    Parallel,
+    Duplicate,
+    Action,
+    Impostor,
+    Impersonate,
+    Qos,
+    Lifecycle,
+    Clock,
+}
+const SEQ_MUTATIONS: [SeqMutation; 15] = [
+    SeqMutation::Insert,
+    SeqMutation::Remove,
+    SeqMutation::Swap,
+    SeqMutation::Splice,
+    SeqMutation::Replace,
+    SeqMutation::Delay,
+    SeqMutation::Timing,
+    SeqMutation::Parallel,
+    SeqMutation::Duplicate,
+    SeqMutation::Action,
+    SeqMutation::Impostor,
+    SeqMutation::Impersonate,
+    SeqMutation::Qos,
+    SeqMutation::Lifecycle,
+    SeqMutation::Clock,
+];
+
+/// Counts of a bag import.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportStats {
+    pub messages: usize,
+    // on a topic a node subscribes to, with the type it subscribes with
+    pub subscribed: usize,
+    pub undecodable: usize,
+    pub sequences: usize,
+}
+
+/// A sequence as a rosbag2 recording, the topic inputs as messages at the time they were sent
+/// and every other call in a script that plays the bag next to them.
+#[derive(Debug, Default, Clone)]
+pub struct BagExport {
+    pub topics: Vec<BagTopic>,
+    pub messages: Vec<BagMessage>,
+    pub script: String,
+}
+
+impl BagExport {
+    fn add_topic(&mut self, name: &str, itf_type: &str, qos: &Qos) {
+        if !self.topics.iter().any(|topic| topic.name == name) {
+            self.topics.push(BagTopic {
+                name: name.to_string(),
+                itf_type: get_bag_type(itf_type),
+                qos: qos.get_bag_profile(),
+            });
+        }
+    }
+}
+
+/// Ordered list of calls executed as one input, e.g. set a param, publish, then call a
+/// service. Outcomes of the last execution are kept for crash reports.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Sequence {
+    pub calls: Vec<SeqCall>,
+    #[serde(default)]
+    pub outcomes: Vec<CallOutcome>,
+}
+
+impl Sequence {
+    // recorded messages on subscribed topics, cut where the recording pauses for longer than a
+    // call delay, so every sequence keeps the original inter-arrival times
+    pub fn from_bag(target: &Target, messages: &[BagMessage]) -> (Vec<Sequence>, ImportStats) {
+        let mut stats = ImportStats {
+            messages: messages.len(),
+            ..Default::default()
+        };
+        let mut seqs = Vec::new();
+        let mut seq = Sequence::default();
+        let mut last_ns = 0;
+        for message in messages {
+            let subscriber = target.nodes.iter().find_map(|node| {
+                let itf = node.get_node_subscribers().get(&message.topic)?;
+                Some((node, itf))
+            });
+            let (node, template) = match subscriber {
+                Some(subscriber) => subscriber,
+                None => continue,
+            };
+            if template.itf_type.replace("/msg/", "/") != message.itf_type.replace("/msg/", "/") {
+                continue;
+            }
+            stats.subscribed += 1;
+            let decoder = CdrDecoder::new(&target.itfs_maps, &target.itfs_info, &message.data);
+            let itf_info = match decoder.decode(&template.itf_name, &template.itf_type) {
+                Ok(itf_info) => itf_info,
+                Err(_) => {
+                    stats.undecodable += 1;
+                    continue;
+                }
+            };
+
+            let gap_us = message.time_ns.saturating_sub(last_ns) / 1000;
+            last_ns = message.time_ns;
+            if !seq.calls.is_empty() && (gap_us > MAX_DELAY_US || seq.calls.len() >= MAX_SEQ_LEN) {
+                seqs.push(std::mem::take(&mut seq));
+            }
+            seq.calls.push(SeqCall {
+                prog: Prog::from_message(&node.node_name, itf_info),
+                delay_us: match seq.calls.is_empty() {
+                    true => 0,
+                    false => gap_us,
+                },
+                parallel: false,
+            });
+        }
+        if !seq.calls.is_empty() {
+            seqs.push(seq);
+        }
+        stats.sequences = seqs.len();
+        (seqs, stats)
+    }
+
+    // start of every call in ns from the start of the sequence, as measured in the last
+    // execution when it reached every call, else as planned by the delays
+    pub fn get_offsets(&self) -> Vec<u64> {
+        let measured: Vec<Option<u128>> = (0..self.calls.len())
+            .map(|idx| {
+                let outcome = self.outcomes.iter().find(|outcome| outcome.idx == idx)?;
+                Some(outcome.start_time)
+            })
+            .collect();
+        if !measured.is_empty() && measured.iter().all(Option::is_some) {
+            let first = measured.iter().flatten().min().copied().unwrap();
+            return measured
+                .iter()
+                .map(|time| (time.unwrap() - first) as u64)
+                .collect();
+        }
+        let mut offsets = Vec::new();
+        let mut now_us = 0;
+        let mut begin = 0;
+        while begin < self.calls.len() {
+            let end = self.group_end(begin);
+            now_us += self.calls[begin].delay_us;
+            offsets.push(now_us * 1000);
+            for call in self.calls[begin + 1..end].iter() {
+                offsets.push((now_us + call.delay_us) * 1000);
+            }
+            begin = end;
+        }
+        offsets
+    }
+
+    // messages stamped from start_ns, the script is meant to sit in the bag directory. A topic
+    // call that does not encode is left to the script like the other calls.
+    pub fn to_bag(&self, target: &Target, start_ns: u64) -> BagExport {
+        let mut export = BagExport::default();
+        let mut calls = Vec::new();
+        for (call, offset_ns) in self.calls.iter().zip(self.get_offsets()) {
+            let prog = &call.prog;
+            let time_ns = start_ns + offset_ns;
+            let messages: Vec<BagMessage> = match (&prog.itf, &prog.call_args.clock) {
+                (ITF::Clock, Some(clock)) => {
+                    export.add_topic(CLOCK_TOPIC, CLOCK_TYPE, &Qos::default());
+                    clock
+                        .plan_ticks(time_ns)
+                        .iter()
+                        .map(|tick| BagMessage {
+                            topic: CLOCK_TOPIC.to_string(),
+                            itf_type: CLOCK_TYPE.to_string(),
+                            time_ns: time_ns + tick.wall_ns,
+                            data: get_clock_cdr(tick.sim_ns),
+                        })
+                        .collect()
+                }
+                (ITF::Topic, _) => match get_topic_messages(target, prog, time_ns) {
+                    Some(messages) => {
+                        let qos = prog.call_args.qos.clone().unwrap_or_default();
+                        export.add_topic(&prog.itf_name, &prog.itf_type, &qos);
+                        messages
+                    }
+                    None => {
+                        calls.push((offset_ns, &prog.call_args));
+                        continue;
+                    }
+                },
+                _ => {
+                    calls.push((offset_ns, &prog.call_args));
+                    continue;
+                }
+            };
+            export.messages.extend(messages);
+        }
+
+        // the bag starts playing with the script, the other calls wait for their offset
+        let mut script = String::from("#!/bin/sh\n");
+        if !export.messages.is_empty() {
+            script.push_str("ros2 bag play \"$(dirname \"$0\")\" &\n");
+        }
+        for (offset_ns, call_args) in calls {
+            script.push_str(&call_args.get_shell_notes());
+            // impostors and the like need the helper executor, only their note is left
+            if let Some(command) = call_args.to_shell_command() {
+                script.push_str(&format!(
+                    "({}; {}) &\n",
+                    sleep_cmd(offset_ns / 1000),
+                    command
+                ));
+            }
+        }
+        script.push_str("wait\n");
+        export.script = script;
+        export
+    }
+
+    pub fn get_seq(target: &Target, rng: &mut RngType) -> Result<Sequence, failure::Error> {
+        if !target.seq_corpus.is_empty() && rng.gen_ratio(1, 2) {
+            // go to mutation
+            let idx = rng.gen_range(0..target.seq_corpus.len());
+            let mut seq = target.seq_corpus[idx].clone();
+            seq.outcomes.clear();
+            seq.mutate(target, rng)?;
+            Ok(seq)
+        } else {
+            // go to generation
+            Sequence::generate(target, rng)
+        }
+    }
+
+    pub fn generate(target: &Target, rng: &mut RngType) -> Result<Sequence, failure::Error> {
+        let mut seq = Sequence::default();
+        for _ in 0..rng.gen_range(1..=MAX_SEQ_LEN) {
+            seq.calls.push(Sequence::gen_call(target, rng)?);
+        }
+        // the first call starts the first group
+        seq.calls[0].parallel = false;
+        Ok(seq)
+    }
+
+    fn gen_call(target: &Target, rng: &mut RngType) -> Result<SeqCall, failure::Error> {
+        let mut prog = Prog::default();
+        prog.generate_call(target, rng)?;
+        let parallel = target.concurrent && rng.gen_ratio(1, 4);
+        Ok(SeqCall {
+            prog,
+            delay_us: match parallel {
+                true => gen_offset(rng),
+                false => gen_delay(rng),
+            },
+            parallel,
+        })
+    }
+
+    pub fn mutate(&mut self, target: &Target, rng: &mut RngType) -> Result<(), failure::Error> {
+        for _ in 0..rng.gen_range(1..4) {
+            let op = SEQ_MUTATIONS[rng.gen_range(0..SEQ_MUTATIONS.len())];
+            match op {
+                SeqMutation::Insert => {
+                    if self.calls.len() < MAX_SEQ_LEN {
+                        let idx = rng.gen_range(0..=self.calls.len());
+                        self.calls.insert(idx, Sequence::gen_call(target, rng)?);
+                    }
+                }
+                SeqMutation::Remove => {
+                    if self.calls.len() > 1 {
+                        self.calls.remove(rng.gen_range(0..self.calls.len()));
+                    }
+                }
+                SeqMutation::Swap => {
+                    if self.calls.len() > 1 {
+                        let a = rng.gen_range(0..self.calls.len());
+                        let b = rng.gen_range(0..self.calls.len());
+                        self.calls.swap(a, b);
+                    }
+                }
+                SeqMutation::Splice => {
+                    // replace our tail by a run of calls from another corpus sequence
+                    if target.seq_corpus.is_empty() {
+                        continue;
+                    }
+                    let other = &target.seq_corpus[rng.gen_range(0..target.seq_corpus.len())];
+                    if other.calls.is_empty() {
+                        continue;
+                    }
+                    let cut = rng.gen_range(0..=self.calls.len());
+                    let begin = rng.gen_range(0..other.calls.len());
+                    self.calls.truncate(cut);
+                    for call in other.calls[begin..].iter() {
+                        if self.calls.len() >= MAX_SEQ_LEN {
+                            break;
+                        }
+                        self.calls.push(call.clone());
+                    }
+                }
+                SeqMutation::Replace => {
+                    if !self.calls.is_empty() {
+                        let idx = rng.gen_range(0..self.calls.len());
+                        self.calls[idx] = Sequence::gen_call(target, rng)?;
+                    }
+                }
+                SeqMutation::Delay => {
+                    if !self.calls.is_empty() {
+                        let idx = rng.gen_range(0..self.calls.len());
+                        self.calls[idx].delay_us = match self.calls[idx].parallel {
+                            true => gen_offset(rng),
+                            false => gen_delay(rng),
+                        };
+                    }
+                }
+                SeqMutation::Timing => {
+                    if self.calls.is_empty() {
+                        continue;
+                    }
+                    let idx = rng.gen_range(0..self.calls.len());
+                    let prog = &mut self.calls[idx].prog;
+                    if prog.itf != ITF::Topic {
+                        continue;
+                    }
+                    let timing = match prog.call_args.timing.clone() {
+                        Some(_) if rng.gen_ratio(1, 4) => None,
+                        Some(mut timing) => {
+                            timing.mutate(rng);
+                            Some(timing)
+                        }
+                        None => Some(Timing::generate(rng)),
+                    };
+                    prog.set_timing(timing);
+                }
+                SeqMutation::Parallel => {
+                    // join or leave the group of the previous call
+                    if !target.concurrent || self.calls.len() < 2 {
+                        continue;
+                    }
+                    let idx = rng.gen_range(1..self.calls.len());
+                    let call = &mut self.calls[idx];
+                    call.parallel = !call.parallel;
+                    call.delay_us = match call.parallel {
+                        true => gen_offset(rng),
+                        false => gen_delay(rng),
+                    };
+                }
+                SeqMutation::Duplicate => {
+                    // race a call against a copy of itself, e.g. two requests to one server
+                    if !target.concurrent
+                        || self.calls.is_empty()
+                        || self.calls.len() >= MAX_SEQ_LEN
+                    {
+                        continue;
+                    }
+                    let idx = rng.gen_range(0..self.calls.len());
+                    let mut call = self.calls[idx].clone();
+                    call.parallel = true;
+                    call.delay_us = gen_offset(rng);
+                    self.calls.insert(idx + 1, call);
+                }
+                SeqMutation::Action => {
+                    let actions: Vec<usize> = (0..self.calls.len())
+                        .filter(|&idx| self.calls[idx].prog.itf == ITF::Action)
+                        .collect();
+                    if actions.is_empty() {
+                        continue;
+                    }
+                    let idx = actions[rng.gen_range(0..actions.len())];
+                    if self.calls.len() < MAX_SEQ_LEN && rng.gen_ratio(1, 2) {
+                        // follow the goal up with a cancel, a preempting goal or a result query
+                        let mut call = self.calls[idx].clone();
+                        let mut action = ActionCall::generate(rng);
+                        action.op = [ActionOp::Cancel, ActionOp::Preempt, ActionOp::GetResult]
+                            [rng.gen_range(0..3)];
+                        action.follow_last = true;
+                        call.prog.set_action(Some(action));
+                        call.delay_us = gen_delay(rng);
+                        call.parallel = false;
+                        self.calls.insert(idx + 1, call);
+                    } else {
+                        let prog = &mut self.calls[idx].prog;
+                        let mut action = prog.call_args.action.clone().unwrap_or_default();
+                        action.mutate(rng);
+                        prog.set_action(Some(action));
+                    }
+                }
+                SeqMutation::Impostor => {
+                    let impostors: Vec<usize> = (0..self.calls.len())
+                        .filter(|&idx| self.calls[idx].prog.call_args.impostor.is_some())
+                        .collect();
+                    if impostors.is_empty() {
+                        continue;
+                    }
+                    let prog = &mut self.calls[impostors[rng.gen_range(0..impostors.len())]].prog;
+                    let mut impostor = prog.call_args.impostor.clone().unwrap();
+                    match rng.gen_ratio(1, 3) {
+                        true => impostor.responses = prog.gen_responses(rng),
+                        false => impostor.mutate(rng),
+                    }
+                    prog.set_impostor(Some(impostor));
+                }
+                SeqMutation::Impersonate => {
+                    if !target.impersonate || self.calls.is_empty() {
+                        continue;
+                    }
+                    let idx = rng.gen_range(0..self.calls.len());
+                    let prog = &mut self.calls[idx].prog;
+                    if prog.itf != ITF::Topic {
+                        continue;
+                    }
+                    let impersonate = match prog.call_args.impersonate.clone() {
+                        Some(_) if rng.gen_ratio(1, 4) => None,
+                        Some(mut impersonate) => {
+                            impersonate.mutate(rng);
+                            Some(impersonate)
+                        }
+                        None => match prog.get_publisher(target) {
+                            Some(publisher) => Some(Impersonation::generate(&publisher, rng)),
+                            None => continue,
+                        },
+                    };
+                    prog.set_impersonation(impersonate);
+                }
+                SeqMutation::Qos => {
+                    if self.calls.is_empty() {
+                        continue;
+                    }
+                    let idx = rng.gen_range(0..self.calls.len());
+                    let prog = &mut self.calls[idx].prog;
+                    if prog.itf != ITF::Topic {
+                        continue;
+                    }
+                    let qos = match prog.call_args.qos.clone() {
+                        Some(_) if rng.gen_ratio(1, 4) => None,
+                        Some(mut qos) => {
+                            qos.mutate(rng);
+                            Some(qos)
+                        }
+                        None => Some(Qos::generate(rng)),
+                    };
+                    prog.set_qos(qos);
+                }
+                SeqMutation::Clock => {
+                    if !target.sim_clock || !target.executor.support_clock() {
+                        continue;
+                    }
+                    let clocks: Vec<usize> = (0..self.calls.len())
+                        .filter(|&idx| self.calls[idx].prog.itf == ITF::Clock)
+                        .collect();
+                    if !clocks.is_empty() && rng.gen_ratio(3, 4) {
+                        let prog = &mut self.calls[clocks[rng.gen_range(0..clocks.len())]].prog;
+                        let mut clock = prog.call_args.clock.clone().unwrap_or_default();
+                        clock.mutate(rng);
+                        prog.set_clock(Some(clock));
+                    } else if self.calls.len() < MAX_SEQ_LEN {
+                        // time moves under whatever the calls around it provoke
+                        let mut prog = Prog::default();
+                        prog.gen_clock();
+                        prog.itf = ITF::Clock;
+                        prog.serialization(&String::new());
+                        prog.set_clock(Some(ClockStream::generate(rng)));
+                        let idx = rng.gen_range(0..=self.calls.len());
+                        self.calls.insert(
+                            idx,
+                            SeqCall {
+                                prog,
+                                delay_us: gen_delay(rng),
+                                parallel: false,
+                            },
+                        );
+                    }
+                }
+                SeqMutation::Lifecycle => {
+                    let lifecycles: Vec<usize> = (0..self.calls.len())
+                        .filter(|&idx| self.calls[idx].prog.itf == ITF::Lifecycle)
+                        .collect();
+                    if !lifecycles.is_empty() && rng.gen_ratio(1, 2) {
+                        let prog =
+                            &mut self.calls[lifecycles[rng.gen_range(0..lifecycles.len())]].prog;
+                        let mut lifecycle = prog.call_args.lifecycle.clone().unwrap();
+                        lifecycle.mutate(rng);
+                        prog.set_lifecycle(Some(lifecycle));
+                    } else {
+                        self.insert_lifecycle_chain(target, rng)?;
+                    }
+                }
+            }
+        }
+        if self.calls.is_empty() {
+            self.calls.push(Sequence::gen_call(target, rng)?);
+        }
+        // a removed, swapped or spliced call can leave a parallel one first, without a group
+        self.calls[0].parallel = false;
+        Ok(())
+    }
+
+    // a chain of transitions of one managed node spread between the other calls
+    fn insert_lifecycle_chain(
+        &mut self,
+        target: &Target,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let managed: Vec<&Node> = target
+            .nodes
+            .iter()
+            .filter(|node| node.get_lifecycle().is_some())
+            .collect();
+        if managed.is_empty() {
+            return Ok(());
+        }
+        let node = managed[rng.gen_range(0..managed.len())];
+        let mut template = Prog::default();
+        template.gen_lifecycle(target, node)?;
+        template.itf = ITF::Lifecycle;
+        template.serialization(&node.node_name);
+
+        let mut idx = rng.gen_range(0..=self.calls.len());
+        for lifecycle in LifecycleCall::gen_chain(rng.gen(), rng) {
+            if self.calls.len() >= MAX_SEQ_LEN {
+                break;
+            }
+            let mut prog = template.clone();
+            prog.set_lifecycle(Some(lifecycle));
+            self.calls.insert(
+                idx,
+                SeqCall {
+                    prog,
+                    delay_us: gen_delay(rng),
+                    parallel: false,
+                },
+            );
+            idx = rng.gen_range(idx + 1..=self.calls.len());
+        }
+        Ok(())
+    }
+
+    // modeled state of a managed node after the calls executed so far, None when unknown
+    fn lifecycle_state(&self, node_name: &str) -> Option<LifecycleState> {
+        self.outcomes
+            .iter()
+            .rev()
+            .filter_map(|outcome| outcome.lifecycle.as_ref())
+            .find(|lifecycle| lifecycle.node_name == node_name)
+            .and_then(|lifecycle| lifecycle.to)
+    }
+
+    fn observe_lifecycle(&self, prog: &Prog, output: &ExecOutput) -> Option<LifecycleOutcome> {
+        let lifecycle = prog.call_args.lifecycle.as_ref()?;
+        let node_name = &prog.call_args.node_name;
+        let outcome = LifecycleOutcome::observe(
+            node_name,
+            lifecycle,
+            self.lifecycle_state(node_name),
+            &output.output,
+        );
+        if outcome.is_violation() {
+            fuzzer_info!("{} took an unavailable transition", node_name);
+        }
+        Some(outcome)
+    }
+
+    /// Shell script rendering of the sequence, only used for reports.
+    pub fn serialization(&self) -> String {
+        let mut script = String::from("#!/bin/sh\n");
+        let mut begin = 0;
+        while begin < self.calls.len() {
+            let end = self.group_end(begin);
+            if self.calls[begin].delay_us > 0 {
+                script.push_str(&format!("{}\n", sleep_cmd(self.calls[begin].delay_us)));
+            }
+            for (idx, call) in self.calls[begin..end].iter().enumerate() {
+                let call_args = &call.prog.call_args;
+                script.push_str(&call_args.get_shell_notes());
+                let command = match call_args.to_shell_command() {
+                    Some(command) => command,
+                    None => continue,
+                };
+                match (end - begin, idx) {
+                    (1, _) => script.push_str(&format!("{}\n", command)),
+                    (_, 0) => script.push_str(&format!("{} &\n", command)),
+                    _ => {
+                        script.push_str(&format!("({}; {}) &\n", sleep_cmd(call.delay_us), command))
+                    }
+                }
+            }
+            if end - begin > 1 {
+                script.push_str("wait\n");
+            }
+            begin = end;
+        }
+        script
+    }
+
+    // end of the concurrent group starting at begin
+    pub fn group_end(&self, begin: usize) -> usize {
+        let mut end = begin + 1;
+        while end < self.calls.len() && self.calls[end].parallel {
+            end += 1;
+        }
+        end
+    }
+
+    // run all calls in order, concurrent groups at once, stop at the first failing one
+    pub fn exec(&mut self, target: &mut Target, work_dir: &String) -> Result<(), failure::Error> {
+        self.outcomes.clear();
+        target.check_crash()?;
+
+        let mut interesting = false;
+        let mut begin = 0;
+        while begin < self.calls.len() {
+            let end = self.group_end(begin);
+            let res = match end - begin {
+                1 => self.exec_call(target, work_dir, begin),
+                _ => self.exec_group(target, work_dir, begin, end),
+            };
+            match res {
+                Ok(hit) => interesting |= hit,
+                Err(e) => {
+                    fuzzer_info!("call {} of sequence failed", begin);
+                    target.current_seq = self.clone();
+                    return Err(e);
+                }
+            }
+            begin = end;
+        }
+
+        if interesting {
+            target.seq_corpus.push(self.clone());
+        }
+        target.current_seq = self.clone();
+        Ok(())
+    }
+
+    fn exec_call(
+        &mut self,
+        target: &mut Target,
+        work_dir: &String,
+        idx: usize,
+    ) -> Result<bool, failure::Error> {
+        if self.calls[idx].delay_us > 0 {
+            thread::sleep(Duration::from_micros(self.calls[idx].delay_us));
+        }
+        let prog = self.resolved_prog(idx);
+        target.add_prog(prog.clone());
+
+        // same as Prog::exec_one, the output is kept for the action outcome
+        let shm_dir = work_dir.to_owned() + "/shm";
+        target.shm_region.allow_time_write(&shm_dir);
+        let log_len = get_log_len(work_dir);
+        let start_time = now_ns();
+        let timer = Instant::now();
+        let res = target.executor.exec(&prog.call_args, &shm_dir);
+        let mut outcome = CallOutcome {
+            idx,
+            start_time,
+            duration: timer.elapsed().as_nanos(),
+            ..Default::default()
+        };
+        let mut ticks = Vec::new();
+        let res = match res {
+            Ok(output) => {
+                outcome.action = observe_action(&prog, &output);
+                outcome.qos_events = parse_qos_events(&output.output);
+                outcome.confusion = observe_confusion(&prog, &output);
+                outcome.lifecycle = self.observe_lifecycle(&prog, &output);
+                ticks = observe_ticks(&prog, &output);
+                prog.check_output(target, work_dir, start_time, output)
+            }
+            Err(e) => Err(e),
+        };
+        if let Some(clock) = prog.call_args.clock.as_ref() {
+            outcome.clock = target.call_graph.get_clock_firings(clock, &ticks);
+        }
+        outcome.tf = observe_tf(target, &prog, work_dir, log_len, start_time);
+        if let Some(action) = outcome.action.as_mut() {
+            action.callbacks = target.call_graph.get_action_callbacks(&prog.itf_name);
+        }
+        outcome.client_callbacks = target.call_graph.get_client_responses();
+        if prog.call_args.impersonate.is_some() {
+            outcome.consumers = target.call_graph.get_topic_consumers(&prog.itf_name);
+        }
+        match res {
+            Ok(hit) => {
+                // a transition the state machine should have refused is kept as well
+                let hit = hit || outcome.lifecycle.as_ref().is_some_and(|l| l.is_violation());
+                // and a timer that fired again on a clock that did not move forward
+                let hit = hit || outcome.clock.iter().any(|firing| firing.stale);
+                // and a tf2 error or latency spike not seen before
+                let hit = hit || outcome.tf.as_ref().is_some_and(|tf| tf.novel);
+                outcome.interesting = hit;
+                if hit {
+                    target.corpus.push(prog);
+                }
+                self.outcomes.push(outcome);
+                Ok(hit)
+            }
+            Err(e) => {
+                let e = confusion_error(&mut outcome, e);
+                outcome.error = e.to_string();
+                self.outcomes.push(outcome);
+                Err(e)
+            }
+        }
+    }
+
+    // fire calls begin..end concurrently and check whether their callbacks really overlapped
+    fn exec_group(
+        &mut self,
+        target: &mut Target,
+        work_dir: &String,
+        begin: usize,
+        end: usize,
+    ) -> Result<bool, failure::Error> {
+        if self.calls[begin].delay_us > 0 {
+            thread::sleep(Duration::from_micros(self.calls[begin].delay_us));
+        }
+        // goals are resolved against the calls before the group only
+        let progs: Vec<Prog> = (begin..end).map(|idx| self.resolved_prog(idx)).collect();
+        let mut batch: Vec<(CallArgs, u64)> = Vec::new();
+        for (offset, prog) in progs.iter().enumerate() {
+            target.add_prog(prog.clone());
+            let offset_us = match offset {
+                0 => 0,
+                _ => self.calls[begin + offset].delay_us,
+            };
+            batch.push((prog.call_args.clone(), offset_us));
+        }
+
+        let shm_dir = work_dir.to_owned() + "/shm";
+        target.shm_region.allow_time_write(&shm_dir);
+        let log_len = get_log_len(work_dir);
+        let start_time = now_ns();
+        let timer = Instant::now();
+        let res = target.executor.exec_batch(&batch, &shm_dir);
+        let duration = timer.elapsed().as_nanos();
+        let outputs = match res {
+            Ok(outputs) => outputs,
+            Err(e) => {
+                self.outcomes.push(CallOutcome {
+                    idx: begin,
+                    start_time,
+                    duration,
+                    error: e.to_string(),
+                    ..Default::default()
+                });
+                return Err(e);
+            }
+        };
+
+        let mut interesting = false;
+        let first = self.outcomes.len();
+        for (offset, output) in outputs.into_iter().enumerate() {
+            let idx = begin + offset;
+            let prog = &progs[offset];
+            let mut outcome = CallOutcome {
+                idx,
+                start_time: start_time + batch[offset].1 as u128 * 1000,
+                duration,
+                action: observe_action(prog, &output),
+                qos_events: parse_qos_events(&output.output),
+                confusion: observe_confusion(prog, &output),
+                lifecycle: self.observe_lifecycle(prog, &output),
+                ..Default::default()
+            };
+            let ticks = observe_ticks(prog, &output);
+            let res = prog.check_output(target, work_dir, start_time, output);
+            if let Some(clock) = prog.call_args.clock.as_ref() {
+                outcome.clock = target.call_graph.get_clock_firings(clock, &ticks);
+            }
+            // the logs of the group are shared, each tf call of it reports their errors
+            outcome.tf = observe_tf(target, prog, work_dir, log_len, start_time);
+            match res {
+                Ok(hit) => {
+                    let hit = hit || outcome.lifecycle.as_ref().is_some_and(|l| l.is_violation());
+                    let hit = hit || outcome.clock.iter().any(|firing| firing.stale);
+                    let hit = hit || outcome.tf.as_ref().is_some_and(|tf| tf.novel);
+                    outcome.interesting = hit;
+                    if hit {
+                        interesting = true;
+                        target.corpus.push(prog.clone());
+                    }
+                    self.outcomes.push(outcome);
+                }
+                Err(e) => {
+                    let e = confusion_error(&mut outcome, e);
+                    outcome.error = e.to_string();
+                    self.outcomes.push(outcome);
+                    return Err(e);
+                }
+            }
+        }
+
+        target.update_shm(&shm_dir)?;
+        let overlaps = target
+            .call_graph
+            .get_cb_overlaps(&target.shm_region, start_time);
+        if !overlaps.is_empty() {
+            fuzzer_info!("{} callback overlaps in group at {}", overlaps.len(), begin);
+            interesting = true;
+        }
+        if let Some(outcome) = self.outcomes.get_mut(first) {
+            outcome.overlaps = overlaps;
+        }
+        let client_callbacks = target.call_graph.get_client_responses();
+        for (offset, outcome) in self.outcomes[first..].iter_mut().enumerate() {
+            outcome.client_callbacks = client_callbacks.clone();
+            if progs[offset].call_args.impersonate.is_some() {
+                outcome.consumers = target
+                    .call_graph
+                    .get_topic_consumers(&progs[offset].itf_name);
+            }
+            if let Some(action) = outcome.action.as_mut() {
+                action.callbacks = target
+                    .call_graph
+                    .get_action_callbacks(&progs[offset].itf_name);
+            }
+        }
+        Ok(interesting)
+    }
+
+    // the call as it is sent, a goal that follows the last one gets its id from the outcomes
+    fn resolved_prog(&self, idx: usize) -> Prog {
+        let mut prog = self.calls[idx].prog.clone();
+        let last_goal = self.last_goal(&prog.itf_name);
+        if let (Some(mut action), Some(goal_id)) = (prog.call_args.action.clone(), last_goal) {
+            if action.follow_last {
+                action.target_id = goal_id;
+                prog.set_action(Some(action));
+            }
+        }
+        prog
+    }
+
+    // last goal accepted by the action server itf_name in this execution
+    fn last_goal(&self, itf_name: &str) -> Option<GoalId> {
+        self.outcomes
+            .iter()
+            .rev()
+            .filter(|outcome| self.calls[outcome.idx].prog.itf_name == itf_name)
+            .find_map(|outcome| outcome.action.as_ref().and_then(|action| action.goal_id))
+    }
+}
+
+fn observe_action(prog: &Prog, output: &ExecOutput) -> Option<ActionOutcome> {
+    match prog.itf {
+        ITF::Action => Some(ActionOutcome::parse(&output.output)),
+        _ => None,
+    }
+}
+
+// consumers of an injected transform and what they made of it, the call graph holds the trace
+// of the call already
+fn observe_tf(
+    target: &mut Target,
+    prog: &Prog,
+    work_dir: &str,
+    log_len: [u64; 2],
+    start_time: u128,
+) -> Option<TfOutcome> {
+    if prog.itf != ITF::Topic || !is_tf_topic(&prog.itf_name) {
+        return None;
+    }
+    let consumers = target.call_graph.get_topic_consumers(&prog.itf_name);
+    let mut outcome = TfOutcome {
+        latency: target
+            .call_graph
+            .get_callback_latency(&consumers, start_time as u64),
+        consumers,
+        errors: read_tf_errors(work_dir, log_len),
+        ..Default::default()
+    };
+    outcome.novel = target.tf_monitor.observe(&mut outcome);
+    Some(outcome)
+}
+
+fn observe_ticks(prog: &Prog, output: &ExecOutput) -> Vec<ClockTick> {
+    match prog.call_args.clock.is_some() {
+        true => parse_ticks(&output.output),
+        false => Vec::new(),
+    }
+}
+
+fn observe_confusion(prog: &Prog, output: &ExecOutput) -> Option<ConfusionResult> {
+    match prog.call_args.confusion.is_empty() {
+        true => None,
+        false => Some(ConfusionResult::from_output(&output.output, output.timeout)),
+    }
+}
+
+// crashes on a parameter of the wrong type are saved apart from the other crashes, cli and
+// executor errors are left as they are
+fn confusion_error(outcome: &mut CallOutcome, e: failure::Error) -> failure::Error {
+    let msg = e.to_string();
+    if outcome.confusion.is_none()
+        || e.downcast_ref::<ExecutorError>().is_some()
+        || msg.contains("ros2 log error")
+        || msg.contains("waiting for")
+    {
+        return e;
+    }
+    outcome.confusion = Some(ConfusionResult::Crashed);
+    failure::format_err!("param type confusion, {}", msg)
+}
+
+// most calls go back to back, some race a detached stream by a few microseconds, some wait
+// to let timers and queues drain
+fn gen_delay(rng: &mut RngType) -> u64 {
+    match rng.gen_range(0..6) {
+        0 => rng.gen_range(0..1000),
+        1 => rng.gen_range(0..MAX_DELAY_US),
+        _ => 0,
+    }
+}
+
+// start offset of a call inside a concurrent group, mostly a near simultaneous start
+fn gen_offset(rng: &mut RngType) -> u64 {
+    match rng.gen_range(0..4) {
+        0 => rng.gen_range(0..MAX_OFFSET_US),
+        _ => rng.gen_range(0..100),
+    }
+}
+
+// the messages of a topic call, every tick of its timing with a burst of copies
+fn get_topic_messages(target: &Target, prog: &Prog, time_ns: u64) -> Option<Vec<BagMessage>> {
+    let data = match prog.call_args.raw.is_empty() {
+        true => {
+            CdrEncoder::new(&target.itfs_maps, &target.itfs_info)
+                .encode(&prog.itf_info)
+                .ok()?
+                .data
+        }
+        false => prog.call_args.raw.clone(),
+    };
+    let timing = prog.call_args.timing.clone().unwrap_or(Timing {
+        duration_ms: 0,
+        ..Default::default()
+    });
+    let mut messages = Vec::new();
+    for tick in 0..timing.get_ticks() {
+        let tick_ns = time_ns + (tick as f64 * 1e9 / timing.rate) as u64;
+        for _ in 0..timing.burst {
+            messages.push(BagMessage {
+                topic: prog.itf_name.clone(),
+                itf_type: get_bag_type(&prog.itf_type),
+                time_ns: tick_ns,
+                data: data.clone(),
+            });
+        }
+    }
+    Some(messages)
+}
+
+fn sleep_cmd(delay_us: u64) -> String {
+    format!("sleep {}.{:06}", delay_us / 1_000_000, delay_us % 1_000_000)
+}
+
+fn now_ns() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::{
+        clock::ClockEvent,
+        executor::MockExecutor,
+        interface::InterfaceVal,
+        param::{ParamUpdate, ParamValue},
+    };
+    use rand::SeedableRng;
+    use std::{
+        fs::{self, File},
+        mem::size_of,
+        os::unix::fs::PermissionsExt,
+        path::Path,
+        process::Command,
+    };
+    use util::shmem::{shared_callback_infos, shared_cb_times, shared_msg_infos, shared_nodes};
+
+    // a target without a launch behind it, its calls go to the mock and its shm stays empty
+    fn mock_target(name: &str, executor: &MockExecutor) -> (Target, String) {
+        let work_dir =
+            std::env::temp_dir().join(format!("feryr-seq-{}-{}", name, std::process::id()));
+        let shm_dir = work_dir.join("shm");
+        fs::create_dir_all(&shm_dir).unwrap();
+        let files = [
+            ("times", size_of::<shared_cb_times>()),
+            ("nodes", size_of::<shared_nodes>()),
+            ("callbacks", size_of::<shared_callback_infos>()),
+            ("msg", size_of::<shared_msg_infos>()),
+        ];
+        for (file, len) in files {
+            File::create(shm_dir.join(file))
+                .unwrap()
+                .set_len(len as u64)
+                .unwrap();
+        }
+        let work_dir = work_dir.to_string_lossy().to_string();
+        let mut target = Target::new(String::new(), work_dir.clone() + "/");
+        target.executor = Box::new(executor.clone());
+        (target, work_dir)
+    }
+
+    // SharedMem of a target does not fit on the stack of a test thread in debug builds
+    fn with_stack(test: impl FnOnce() + Send + 'static) {
+        thread::Builder::new()
+            .stack_size(256 << 20)
+            .spawn(test)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    fn topic_call(topic: &str, parallel: bool) -> SeqCall {
+        let payload = "{data: \"a\"}".to_string();
+        let mut prog = Prog {
+            itf: ITF::Topic,
+            itf_name: topic.to_string(),
+            itf_type: "std_msgs/msg/String".to_string(),
+            call_args: CallArgs::new(ITF::Topic, "talker", topic, "std_msgs/msg/String", payload),
+            ..Default::default()
+        };
+        prog.call_stream = prog.call_args.to_call_stream();
+        SeqCall {
+            prog,
+            delay_us: 0,
+            parallel,
+        }
+    }
+
+    fn param_call(parallel: bool) -> SeqCall {
+        let mut call_args = CallArgs::new(ITF::Param, "/planner", "", "", String::new());
+        call_args.params = Some(ParamUpdate {
+            params: vec![ParamValue {
+                name: "max_speed".to_string(),
+                itf_type: "float64".to_string(),
+                value: "1.5".to_string(),
+            }],
+            atomic: false,
+        });
+        let mut prog = Prog {
+            itf: ITF::Param,
+            call_args,
+            ..Default::default()
+        };
+        prog.call_stream = prog.call_args.to_call_stream();
+        SeqCall {
+            prog,
+            delay_us: 1000,
+            parallel,
+        }
+    }
+
+    // runs a script from its own directory against a ros2 that logs its arguments and the
+    // param file it is given, the log comes back one line per entry
+    fn run_script(script: &str, dir: &Path) -> Vec<String> {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join("replay.sh");
+        fs::write(&path, script).unwrap();
+        let check = Command::new("sh").arg("-n").arg(&path).output().unwrap();
+        assert!(
+            check.status.success(),
+            "{}",
+            String::from_utf8_lossy(&check.stderr)
+        );
+
+        let bin = dir.join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let ros2 = bin.join("ros2");
+        fs::write(
+            &ros2,
+            "#!/bin/sh\necho \"$*\" >> \"$LOG\"\nfor last; do :; done\n\
+             case \"$last\" in *.yaml) cat \"$last\" >> \"$LOG\" || echo missing >> \"$LOG\";; esac\n",
+        )
+        .unwrap();
+        fs::set_permissions(&ros2, fs::Permissions::from_mode(0o755)).unwrap();
+        let log = dir.join("log");
+        let status = Command::new("sh")
+            .arg(&path)
+            .env(
+                "PATH",
+                format!("{}:{}", bin.display(), std::env::var("PATH").unwrap()),
+            )
+            .env("LOG", &log)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let log = fs::read_to_string(&log).unwrap_or_default();
+        fs::remove_dir_all(dir).unwrap();
+        log.lines().map(|line| line.to_string()).collect()
+    }
+
+    fn get_topics(executor: &MockExecutor) -> Vec<String> {
+        executor
+            .get_history()
+            .iter()
+            .map(|call| call.itf_name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn executor_failure_is_not_a_crash() {
+        with_stack(executor_failure);
+    }
+
+    fn executor_failure() {
+        let mut executor = MockExecutor::default();
+        let (mut target, work_dir) = mock_target("failure", &executor);
+        executor.push_output("", true);
+        executor.push_failure("broken pipe");
+        let mut seq = Sequence {
+            calls: vec![
+                topic_call("/a", false),
+                topic_call("/b", false),
+                topic_call("/c", false),
+            ],
+            ..Default::default()
+        };
+
+        let e = seq.exec(&mut target, &work_dir).unwrap_err();
+        assert!(e.downcast_ref::<ExecutorError>().is_some());
+        // the sequence stops at the failing call
+        assert_eq!(get_topics(&executor), vec!["/a", "/b"]);
+        assert_eq!(seq.outcomes.len(), 2);
+        assert!(seq.outcomes[0].error.is_empty());
+        assert_eq!(seq.outcomes[1].idx, 1);
+        assert!(seq.outcomes[1].error.contains("broken pipe"));
+        assert_eq!(target.current_seq.outcomes.len(), 2);
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    fn with_subscriber(target: &mut Target) {
+        let mut node = Node::new("talker".to_string());
+        for topic in ["/a", "/b"] {
+            let itf = InterfaceVal::new(&topic.to_string(), &"std_msgs/String".to_string());
+            node.add_subscriber(topic.to_string(), itf);
+        }
+        target.nodes.push(node);
+        target.concurrent = true;
+    }
+
+    fn check_groups(seq: &Sequence) {
+        assert!(!seq.calls.is_empty() && seq.calls.len() <= MAX_SEQ_LEN);
+        assert!(!seq.calls[0].parallel);
+        let mut begin = 0;
+        while begin < seq.calls.len() {
+            let end = seq.group_end(begin);
+            assert!(end > begin && end <= seq.calls.len());
+            for call in &seq.calls[begin + 1..end] {
+                assert!(call.parallel && call.delay_us <= MAX_OFFSET_US);
+            }
+            begin = end;
+        }
+        assert!(seq.calls.iter().all(|call| call.delay_us <= MAX_DELAY_US));
+    }
+
+    #[test]
+    fn generate_without_inputs_fails() {
+        with_stack(without_inputs);
+    }
+
+    fn without_inputs() {
+        let executor = MockExecutor::default();
+        let (mut target, work_dir) = mock_target("inputs", &executor);
+        // a node that only publishes gives the generator nothing to call
+        let mut node = Node::new("talker".to_string());
+        let itf = InterfaceVal::new(&"/a".to_string(), &"std_msgs/String".to_string());
+        node.add_publisher("/a".to_string(), itf);
+        target.nodes.push(node);
+
+        let mut rng = RngType::seed_from_u64(5);
+        assert!(Sequence::generate(&target, &mut rng).is_err());
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn mutations_keep_groups_valid() {
+        with_stack(mutations);
+    }
+
+    fn mutations() {
+        let executor = MockExecutor::default();
+        let (mut target, work_dir) = mock_target("mutate", &executor);
+        with_subscriber(&mut target);
+        // a corpus sequence made of one group, so a splice can start with a parallel call
+        let mut group = Sequence {
+            calls: vec![topic_call("/a", false)],
+            ..Default::default()
+        };
+        for _ in 1..MAX_SEQ_LEN {
+            let mut call = topic_call("/b", true);
+            call.delay_us = MAX_OFFSET_US;
+            group.calls.push(call);
+        }
+        target.seq_corpus.push(group);
+
+        let mut rng = RngType::seed_from_u64(7);
+        for _ in 0..50 {
+            let mut seq = Sequence::generate(&target, &mut rng).unwrap();
+            check_groups(&seq);
+            for _ in 0..20 {
+                seq.mutate(&target, &mut rng).unwrap();
+                check_groups(&seq);
+            }
+        }
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn groups_run_in_order_and_crashes_are_attributed() {
+        with_stack(groups_in_order);
+    }
+
+    fn groups_in_order() {
+        let mut executor = MockExecutor::default();
+        let (mut target, work_dir) = mock_target("groups", &executor);
+        for _ in 0..3 {
+            executor.push_output("", true);
+        }
+        executor.push_output("Failed to send: node died", false);
+        let mut seq = Sequence {
+            calls: vec![
+                topic_call("/a", false),
+                topic_call("/b", true),
+                topic_call("/c", true),
+                topic_call("/d", false),
+                topic_call("/e", false),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(seq.group_end(0), 3);
+        assert_eq!(seq.group_end(3), 4);
+
+        let e = seq.exec(&mut target, &work_dir).unwrap_err();
+        assert!(e.downcast_ref::<ExecutorError>().is_none());
+        // the first three go out as one batch, the crash stops the sequence before /e
+        assert_eq!(executor.state.lock().unwrap().batches, vec![3]);
+        assert_eq!(get_topics(&executor), vec!["/a", "/b", "/c", "/d"]);
+        let idxs: Vec<usize> = seq.outcomes.iter().map(|outcome| outcome.idx).collect();
+        assert_eq!(idxs, vec![0, 1, 2, 3]);
+        assert!(seq.outcomes[..3]
+            .iter()
+            .all(|outcome| outcome.error.is_empty()));
+        assert!(seq.outcomes[3].error.contains("node died"));
+        assert_eq!(target.current_corpus.len(), 4);
+        assert_eq!(target.current_seq.outcomes.len(), 4);
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+
+    #[test]
+    fn crash_script_keeps_groups_concurrent() {
+        let mut raw = topic_call("/a", false);
+        raw.prog.call_args.raw = vec![0, 1];
+        let seq = Sequence {
+            calls: vec![
+                raw,
+                param_call(true),
+                topic_call("/c", true),
+                topic_call("/d", false),
+            ],
+            ..Default::default()
+        };
+        let script = seq.serialization();
+        // annotations sit above their call and never reach the line that backgrounds it
+        let lines: Vec<&str> = script.lines().collect();
+        assert!(lines.contains(&"# raw cdr: 0001"));
+        assert!(lines
+            .iter()
+            .filter(|line| line.starts_with("ros2 topic pub") && line.ends_with('&'))
+            .any(|line| line.contains("/a")));
+        assert_eq!(lines.iter().filter(|line| line.ends_with(") &")).count(), 2);
+
+        let dir = std::env::temp_dir().join(format!("feryr-crash-{}", std::process::id()));
+        let log = run_script(&script, &dir);
+        let param_file = seq.calls[1].prog.call_args.params.as_ref().unwrap();
+        assert!(log.contains(&param_file.get_param_file("/planner")));
+        assert!(!log.contains(&"missing".to_string()));
+        assert_eq!(
+            log.iter()
+                .filter(|line| line.starts_with("topic pub"))
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn bag_export_carries_topics_clock_and_replayable_calls() {
+        with_stack(bag_export);
+    }
+
+    fn bag_export() {
+        let executor = MockExecutor::default();
+        let (target, work_dir) = mock_target("bag", &executor);
+        let mut raw = topic_call("/a", false);
+        raw.prog.call_args.raw = vec![0, 1];
+        let mut clock_args = CallArgs::new(ITF::Clock, "", CLOCK_TOPIC, CLOCK_TYPE, String::new());
+        clock_args.set_clock(Some(ClockStream {
+            period_ms: 10,
+            events: vec![
+                ClockEvent::Jump { delta_ms: 1000 },
+                ClockEvent::Freeze { duration_ms: 20 },
+            ],
+        }));
+        let clock = SeqCall {
+            prog: Prog {
+                itf: ITF::Clock,
+                itf_name: CLOCK_TOPIC.to_string(),
+                call_args: clock_args,
+                ..Default::default()
+            },
+            delay_us: 2000,
+            parallel: false,
+        };
+        let seq = Sequence {
+            calls: vec![raw, clock, param_call(false)],
+            ..Default::default()
+        };
+
+        let start_ns = 1_000_000_000_000;
+        let export = seq.to_bag(&target, start_ns);
+        let topics: Vec<&str> = export
+            .topics
+            .iter()
+            .map(|topic| topic.name.as_str())
+            .collect();
+        assert_eq!(topics, vec!["/a", CLOCK_TOPIC]);
+        let raw_msgs: Vec<&BagMessage> = export
+            .messages
+            .iter()
+            .filter(|msg| msg.topic == "/a")
+            .collect();
+        assert_eq!(raw_msgs.len(), 1);
+        assert_eq!(
+            (raw_msgs[0].time_ns, &raw_msgs[0].data),
+            (start_ns, &vec![0, 1])
+        );
+        // the jump, then the frozen time twice, 10ms apart from the clock call offset
+        let clock_ns = start_ns + 2_000_000;
+        let ticks: Vec<(u64, Vec<u8>)> = export
+            .messages
+            .iter()
+            .filter(|msg| msg.topic == CLOCK_TOPIC)
+            .map(|msg| (msg.time_ns, msg.data.clone()))
+            .collect();
+        let sim = get_clock_cdr(clock_ns + 1_000_000_000);
+        assert_eq!(
+            ticks,
+            vec![
+                (clock_ns, sim.clone()),
+                (clock_ns, sim.clone()),
+                (clock_ns + 10_000_000, sim)
+            ]
+        );
+
+        // only the param load is left to the script, its file is written next to it
+        let params = seq.calls[2].prog.call_args.params.as_ref().unwrap();
+        let file = params.get_param_path("", "/planner");
+        let file = file.to_string_lossy();
+        let lines: Vec<&str> = export.script.lines().collect();
+        assert_eq!(
+            lines[..2],
+            ["#!/bin/sh", "ros2 bag play \"$(dirname \"$0\")\" &"]
+        );
+        assert!(lines
+            .contains(&format!("cat > \"$(dirname \"$0\")\"/{} <<'FERYR_PARAMS'", file).as_str()));
+        assert!(lines.contains(
+            &format!(
+                "(sleep 0.003000; ros2 param load /planner \"$(dirname \"$0\")\"/{}) &",
+                file
+            )
+            .as_str()
+        ));
+        assert_eq!(lines.last(), Some(&"wait"));
+
+        let dir = Path::new(&work_dir).join("bag");
+        let log = run_script(&export.script, &dir);
+        assert!(log.contains(&format!("bag play {}", dir.display())));
+        assert!(log.contains(&format!(
+            "param load /planner {}",
+            dir.join(&*file).display()
+        )));
+        assert!(log.contains(&params.get_param_file("/planner")));
+        assert!(!log.contains(&"missing".to_string()));
+        fs::remove_dir_all(&work_dir).unwrap();
+    }
+}
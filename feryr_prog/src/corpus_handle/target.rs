@@ -1,10 +1,16 @@
 use super::super::{cover_handle::callgraph::*, ExecError};
 use super::ty::TYPE;
 use crate::corpus_handle::{
+    boot::{BootConfig, BootSchema},
+    executor::{CliExecutor, Executor},
     interface::*,
     models::OnnxModel,
     prog::Prog,
+    restore::{RestorePolicy, Snapshot},
+    sequence::Sequence,
+    tf::TfMonitor,
     ty::{array, character, double, integer, Type, TypeId},
+    xml::XmlSeed,
     SHM_PATH,
 };
 use multimap::MultiMap;
@@ -56,6 +62,36 @@ pub struct Target {
     pub call_graph: CallGraph,
     pub current_corpus: Vec<Prog>,
     pub corpus: Vec<Prog>,
+    pub current_seq: Sequence,
+    pub seq_corpus: Vec<Sequence>,
+    // injection backend, cli by default
+    pub executor: Box<dyn Executor>,
+    // also publish mutated raw cdr when the executor supports it
+    pub raw_cdr: bool,
+    // let sequences fire groups of calls concurrently
+    pub concurrent: bool,
+    // inject topics next to their legitimate publisher
+    pub impersonate: bool,
+    // also set parameters reported read-only, to check that the node rejects them
+    pub readonly_param: bool,
+    // set parameters with values of the wrong type
+    pub param_confusion: bool,
+    // when to load the parameters dumped after boot back, and whether managed nodes go
+    // through deactivate and activate around it
+    pub restore: RestorePolicy,
+    pub restore_lifecycle: bool,
+    pub snapshot: Option<Snapshot>,
+    // inputs per boot with a generated boot configuration, 0 boots with the default one
+    pub boot_fuzz: u64,
+    pub boot_schema: BootSchema,
+    pub boot_config: Option<BootConfig>,
+    // nodes run on use_sim_time and the fuzzer publishes /clock, see clock.rs
+    pub sim_clock: bool,
+    // pick /tf and /tf_static more often, see tf.rs
+    pub tf_fuzz: bool,
+    pub tf_monitor: TfMonitor,
+    // URDF and SRDF documents read after boot, see xml.rs
+    pub xml_seeds: Vec<XmlSeed>,
     pub executor_model: Arc<Mutex<OnnxModel>>,
     pub topic_model: Arc<Mutex<OnnxModel>>,
     pub trace_model: Arc<Mutex<OnnxModel>>,
@@ -81,6 +117,22 @@ impl Target {
             shm_region: SharedMem::new(),
             call_graph: CallGraph::new(),
             current_corpus: Vec::new(),
+            executor: Box::new(CliExecutor::default()),
+            raw_cdr: false,
+            concurrent: false,
+            impersonate: false,
+            readonly_param: false,
+            param_confusion: false,
+            restore: RestorePolicy::Never,
+            restore_lifecycle: false,
+            snapshot: None,
+            boot_fuzz: 0,
+            boot_schema: BootSchema::default(),
+            boot_config: None,
+            sim_clock: false,
+            tf_fuzz: false,
+            tf_monitor: TfMonitor::default(),
+            xml_seeds: Vec::new(),
             executor_model: Arc::new(Mutex::new(OnnxModel::new(&std::path::Path::new(
                 executor_model_path.as_str(),
             )))),
@@ -91,6 +143,8 @@ impl Target {
                 trace_model_path.as_str(),
             )))),
             corpus: Vec::new(),
+            current_seq: Sequence::default(),
+            seq_corpus: Vec::new(),
             itfs_types: vec![
                 "bool".to_string(),
                 "byte".to_string(),
@@ -158,6 +212,7 @@ impl Target {
 
     pub fn clean_prog(&mut self) {
         self.current_corpus.clear();
+        self.current_seq = Sequence::default();
     }
 
     pub fn add_prog(&mut self, prog: Prog) {
@@ -282,8 +337,8 @@ impl Target {
             .collect();
         param_list
     }
-    pub fn get_interfaces<'a>(&mut self, work_dir: &str, input_type: &String) {
-        // deserialize self.itfs_info from work_dir/../sys/itf_types.json
+    // profile of the target under sys/, with a trailing slash
+    pub fn get_profile_dir(work_dir: &str, input_type: &str) -> String {
         let mut path_prefix = work_dir.to_owned() + "/../../sys/";
         if input_type.contains("turtlebot3") {
             path_prefix = path_prefix + "turtlebot3/";
@@ -294,6 +349,12 @@ impl Target {
         } else if input_type.contains("autoware") {
             path_prefix = path_prefix + "autoware/";
         }
+        path_prefix
+    }
+
+    pub fn get_interfaces<'a>(&mut self, work_dir: &str, input_type: &String) {
+        // deserialize self.itfs_info from work_dir/../sys/itf_types.json
+        let path_prefix = Self::get_profile_dir(work_dir, input_type);
         let itf_types_path = path_prefix.clone() + "itf_types.json";
         let itf_types_file = File::open(itf_types_path).unwrap();
         let itf_types = serde_json::from_reader(itf_types_file).unwrap();
@@ -510,7 +571,8 @@ impl Target {
         // find the corresponding node
         let mut res_map = MultiMap::new();
         for param in param_vec {
-            // contiune if contain substr "qos"
+            // qos overrides are read only once the node runs, qos is fuzzed per input instead,
+            // simulated time is driven by ITF::Clock calls
             if param.contains("qos") || param.contains("use_sim_time") {
                 continue;
             }
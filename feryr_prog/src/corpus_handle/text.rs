@@ -0,0 +1,569 @@
+//! Text form of programs and sequences, to read and edit saved inputs by hand. One call per
+//! line, in the spirit of syzkaller programs:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! topic /listener /chatter std_msgs/msg/String {data: !string "hi"}
+//! param /talker rate float64 10.5 (delay: 2000us)
+//! service /adder /add_two_ints example_interfaces/srv/AddTwoInts {a: !int64 1, b: !int64 2} (parallel)
+//! ```
+//!
+//! A call is its kind, node, name and type, `-` when empty, then the payload as yaml flow with
+//! every field tagged with its type, so it parses back without the target. The properties in
+//! parentheses are the delay before the call, `parallel` when it joins the group of the call
+//! before, and the call arguments that differ from a plain call of the payload, as json.
+use super::{
+    call::CallArgs,
+    cdr::set_array_prims,
+    interface::{new_leaf, InterfaceVal, ValueType, ITF},
+    prog::Prog,
+    sequence::{SeqCall, Sequence},
+    ty::{array::ArrayType, TYPE},
+    yaml::{parse_node, skip_ws, YamlNode},
+};
+use serde_json::{Map, Value};
+use std::{fs, path::Path};
+
+pub const PROG_EXT: &str = "prog";
+
+impl Prog {
+    /// The call as one line, without the properties of a sequence call.
+    pub fn to_text(&self) -> String {
+        format_call(self, Vec::new())
+    }
+
+    // a delay or parallel property is accepted and ignored
+    pub fn from_text(line: &str) -> Result<Prog, failure::Error> {
+        Ok(parse_call(line)?.0)
+    }
+}
+
+impl Sequence {
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for call in self.calls.iter() {
+            let mut props = Vec::new();
+            if call.delay_us > 0 {
+                props.push(format!("delay: {}us", call.delay_us));
+            }
+            if call.parallel {
+                props.push("parallel".to_string());
+            }
+            text.push_str(&format_call(&call.prog, props));
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn from_text(text: &str) -> Result<Sequence, failure::Error> {
+        let mut seq = Sequence::default();
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (prog, delay_us, parallel) =
+                parse_call(line).map_err(|e| failure::format_err!("line {}: {}", idx + 1, e))?;
+            seq.calls.push(SeqCall {
+                prog,
+                delay_us,
+                parallel,
+            });
+        }
+        Ok(seq)
+    }
+}
+
+// a saved sequence, either serde json or the text form
+pub fn read_seq(path: &Path) -> Result<Sequence, failure::Error> {
+    let data = fs::read_to_string(path)?;
+    match data.trim_start().starts_with('{') {
+        true => Ok(serde_json::from_str(&data)?),
+        false => Sequence::from_text(&data),
+    }
+}
+
+fn get_kind(itf: &ITF) -> &'static str {
+    match itf {
+        ITF::Topic => "topic",
+        ITF::Service => "service",
+        ITF::Action => "action",
+        ITF::Param => "param",
+        ITF::Impostor => "impostor",
+        ITF::Lifecycle => "lifecycle",
+        ITF::Clock => "clock",
+    }
+}
+
+fn parse_kind(kind: &str) -> Result<ITF, failure::Error> {
+    Ok(match kind {
+        "topic" => ITF::Topic,
+        "service" => ITF::Service,
+        "action" => ITF::Action,
+        "param" => ITF::Param,
+        "impostor" => ITF::Impostor,
+        "lifecycle" => ITF::Lifecycle,
+        "clock" => ITF::Clock,
+        _ => return Err(failure::format_err!("unknown call kind {}", kind)),
+    })
+}
+
+fn format_call(prog: &Prog, mut props: Vec<String>) -> String {
+    let column = |val: &str| match val.is_empty() {
+        true => "-".to_string(),
+        false => val.to_string(),
+    };
+    let mut line = format!(
+        "{} {} {} {} {}",
+        get_kind(&prog.itf),
+        column(&prog.call_args.node_name),
+        column(&prog.itf_name),
+        column(&prog.itf_type),
+        to_typed(&prog.itf_info).emit()
+    );
+    props.extend(get_arg_props(prog));
+    if !props.is_empty() {
+        line.push_str(&format!(" ({})", props.join(", ")));
+    }
+    line
+}
+
+// what a call of the payload and nothing else is sent with
+fn get_plain_args(prog: &Prog, node_name: &str) -> Map<String, Value> {
+    let args = CallArgs::new(
+        prog.itf.clone(),
+        node_name,
+        &prog.itf_name,
+        &prog.itf_type,
+        prog.itf_info.to_yaml(),
+    );
+    match serde_json::to_value(args) {
+        Ok(Value::Object(args)) => args,
+        _ => unreachable!("call arguments are a struct"),
+    }
+}
+
+// call arguments that differ from the plain call, raw cdr as hex instead of a byte array
+fn get_arg_props(prog: &Prog) -> Vec<String> {
+    let plain = get_plain_args(prog, &prog.call_args.node_name);
+    let args = match serde_json::to_value(&prog.call_args) {
+        Ok(Value::Object(args)) => args,
+        _ => unreachable!("call arguments are a struct"),
+    };
+    let mut props = Vec::new();
+    for (key, val) in args {
+        if plain.get(&key) == Some(&val) {
+            continue;
+        }
+        let val = match key.as_str() {
+            "raw" => Value::String(
+                prog.call_args
+                    .raw
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect(),
+            ),
+            _ => val,
+        };
+        props.push(format!("{}: {}", key, val));
+    }
+    props
+}
+
+// the call, its delay and whether it is parallel
+fn parse_call(line: &str) -> Result<(Prog, u64, bool), failure::Error> {
+    let chars: Vec<char> = line.trim().chars().collect();
+    let mut pos = 0;
+    let mut columns = Vec::new();
+    for _ in 0..4 {
+        skip_ws(&chars, &mut pos);
+        let start = pos;
+        while pos < chars.len() && !chars[pos].is_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(failure::err_msg(
+                "expected kind, node, name, type and payload",
+            ));
+        }
+        let column: String = chars[start..pos].iter().collect();
+        columns.push(match column.as_str() {
+            "-" => String::new(),
+            _ => column,
+        });
+    }
+    let itf = parse_kind(&columns[0])?;
+    let (node_name, itf_name, itf_type) = (&columns[1], &columns[2], &columns[3]);
+
+    // a scalar payload ends at the first blank, inside a mapping it ends at a comma
+    skip_ws(&chars, &mut pos);
+    let payload = match chars.get(pos) {
+        None => return Err(failure::err_msg("missing payload")),
+        Some('{' | '[' | '"' | '!') => parse_node(&chars, &mut pos)?,
+        Some(_) => {
+            let start = pos;
+            while pos < chars.len() && !chars[pos].is_whitespace() {
+                pos += 1;
+            }
+            YamlNode::Scalar(chars[start..pos].iter().collect())
+        }
+    };
+    let mut itf_info = from_typed(itf_name, itf_type, &payload)?;
+    if itf_info.len() != 1 {
+        return Err(failure::err_msg("the payload is not one value"));
+    }
+    let mut prog = Prog {
+        itf,
+        itf_name: itf_name.clone(),
+        itf_type: itf_type.clone(),
+        itf_info: itf_info.remove(0),
+        ..Default::default()
+    };
+
+    let (mut delay_us, mut parallel) = (0, false);
+    let mut args = get_plain_args(&prog, node_name);
+    let rest: String = chars[pos..].iter().collect();
+    for (key, val) in parse_props(rest.trim())? {
+        match key.as_str() {
+            "delay" => delay_us = val.as_u64().unwrap_or(0),
+            "parallel" => parallel = val.as_bool().unwrap_or(false),
+            "raw" if val.is_string() => {
+                let raw = parse_hex(val.as_str().unwrap())?;
+                args.insert(key, Value::from(raw));
+            }
+            _ if args.contains_key(&key) => {
+                args.insert(key, val);
+            }
+            _ => return Err(failure::format_err!("unknown property {}", key)),
+        }
+    }
+    prog.call_args = serde_json::from_value(Value::Object(args))?;
+    prog.call_stream = prog.call_args.to_call_stream();
+    Ok((prog, delay_us, parallel))
+}
+
+// `(delay: 1500us, parallel, qos: {...})`, a property without value is a flag
+fn parse_props(text: &str) -> Result<Vec<(String, Value)>, failure::Error> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut rest = match text
+        .strip_prefix('(')
+        .and_then(|text| text.strip_suffix(')'))
+    {
+        Some(inner) => inner.trim_start(),
+        None => return Err(failure::format_err!("expected properties, found {}", text)),
+    };
+    let mut props = Vec::new();
+    while !rest.is_empty() {
+        let end = rest.find([':', ',']).unwrap_or(rest.len());
+        let key = rest[..end].trim().to_string();
+        rest = &rest[end..];
+        let val = match rest.strip_prefix(':') {
+            Some(val) if key == "delay" => {
+                let end = val.find(',').unwrap_or(val.len());
+                rest = &val[end..];
+                Value::from(parse_delay(val[..end].trim())?)
+            }
+            Some(val) => {
+                let mut stream = serde_json::Deserializer::from_str(val).into_iter::<Value>();
+                let json = match stream.next() {
+                    Some(json) => json?,
+                    None => return Err(failure::format_err!("{} has no value", key)),
+                };
+                rest = &val[stream.byte_offset()..];
+                json
+            }
+            None => Value::Bool(true),
+        };
+        props.push((key, val));
+        rest = rest.trim_start();
+        rest = match rest.strip_prefix(',') {
+            Some(next) => next.trim_start(),
+            None if rest.is_empty() => rest,
+            None => return Err(failure::format_err!("expected ',' before {}", rest)),
+        };
+    }
+    Ok(props)
+}
+
+// microseconds of `1500us`, `2ms`, `1s` or a plain number of microseconds
+fn parse_delay(delay: &str) -> Result<u64, failure::Error> {
+    let (num, scale) = match delay {
+        _ if delay.ends_with("us") => (&delay[..delay.len() - 2], 1),
+        _ if delay.ends_with("ms") => (&delay[..delay.len() - 2], 1000),
+        _ if delay.ends_with('s') => (&delay[..delay.len() - 1], 1_000_000),
+        _ => (delay, 1),
+    };
+    Ok(num.trim().parse::<u64>()? * scale)
+}
+
+fn parse_hex(hex: &str) -> Result<Vec<u8>, failure::Error> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(failure::format_err!("raw is not hex: {}", hex));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| Ok(u8::from_str_radix(&hex[idx..idx + 2], 16)?))
+        .collect()
+}
+
+// the value tree with every field tagged with its type, the type of the root is on the call
+fn to_typed(itf: &InterfaceVal) -> YamlNode {
+    if let Some(leaf) = itf.leaf_yaml_node() {
+        return leaf;
+    }
+    let mut fields: Vec<(String, YamlNode)> = Vec::new();
+    for val in itf.val.iter() {
+        let field = match val {
+            ValueType::Op(field) => field,
+            _ => continue,
+        };
+        let node = to_typed(field);
+        // message arrays are repeated siblings, folded back into one sequence
+        if field.is_msg_array() {
+            if let Some((name, YamlNode::Tagged(_, items))) = fields.last_mut() {
+                if let (true, YamlNode::Seq(items)) = (*name == field.itf_name, &mut **items) {
+                    items.push(node);
+                    continue;
+                }
+            }
+            fields.push((
+                field.itf_name.clone(),
+                YamlNode::Tagged(field.itf_type.clone(), Box::new(YamlNode::Seq(vec![node]))),
+            ));
+        } else {
+            fields.push((
+                field.itf_name.clone(),
+                YamlNode::Tagged(field.itf_type.clone(), Box::new(node)),
+            ));
+        }
+    }
+    YamlNode::Map(fields)
+}
+
+// the value tree of one field, one sibling per element of a message array
+fn from_typed(
+    itf_name: &str,
+    itf_type: &str,
+    node: &YamlNode,
+) -> Result<Vec<InterfaceVal>, failure::Error> {
+    let mut itf = InterfaceVal::new(&itf_name.to_string(), &itf_type.to_string());
+    match node {
+        YamlNode::Map(fields) => {
+            for (name, field) in fields {
+                let (field_type, field) = match field {
+                    YamlNode::Tagged(field_type, field) => (field_type, field),
+                    _ => return Err(failure::format_err!("{} has no type tag", name)),
+                };
+                for val in from_typed(name, field_type, field)? {
+                    itf.val.push(ValueType::Op(val));
+                }
+            }
+        }
+        YamlNode::Seq(items) if get_inner_type(itf_type) == Some(TYPE::COMPLEX) => {
+            let mut siblings = Vec::new();
+            for item in items {
+                if !matches!(item, YamlNode::Map(_)) {
+                    return Err(failure::format_err!("{} is not a message", item.emit()));
+                }
+                siblings.extend(from_typed(itf_name, itf_type, item)?);
+            }
+            return Ok(siblings);
+        }
+        _ => itf.val.push(parse_leaf(itf_type, node)?),
+    }
+    Ok(vec![itf])
+}
+
+// `float64` of `float64[3]` or `float64[<=3]`, None when not an array
+fn get_inner_type(itf_type: &str) -> Option<TYPE> {
+    let begin = itf_type.find('[')?;
+    Some(TYPE::from_str(&itf_type[..begin]))
+}
+
+fn parse_leaf(itf_type: &str, node: &YamlNode) -> Result<ValueType, failure::Error> {
+    let mismatch = || failure::format_err!("{} is not a {}", node.emit(), itf_type);
+    if let Some(inner_type) = get_inner_type(itf_type) {
+        let items = match node {
+            YamlNode::Seq(items) => items,
+            _ => return Err(mismatch()),
+        };
+        let inner = &itf_type[..itf_type.find('[').unwrap()];
+        let items = items
+            .iter()
+            .map(|item| parse_leaf(inner, item))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut array = ArrayType::new(TYPE::ARRAY as usize, inner_type, items.len() as u64);
+        match inner_type {
+            TYPE::UInt8 | TYPE::Byte | TYPE::Char => array.set_bytes(
+                items
+                    .iter()
+                    .map(|item| match item {
+                        ValueType::Op1(int) => int.get_num() as u8,
+                        ValueType::Op4(chara) => chara.get_char() as u8,
+                        _ => 0,
+                    })
+                    .collect(),
+            ),
+            _ => set_array_prims(&mut array, items),
+        }
+        return Ok(ValueType::Op5(array));
+    }
+
+    let mut val = new_leaf(TYPE::from_str(itf_type), 0)
+        .ok_or_else(|| failure::format_err!("{} is not a primitive type", itf_type))?;
+    match (&mut val, node) {
+        (ValueType::Op1(int), YamlNode::Scalar(num)) => {
+            let num = num.parse::<i128>()?;
+            let bits = int.get_pad().unsigned_abs();
+            let (min, max) = match int.get_pad() < 0 {
+                true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+                false => (0, (1i128 << bits) - 1),
+            };
+            if num < min || num > max {
+                return Err(mismatch());
+            }
+            int.set_val(num as u64)
+        }
+        (ValueType::Op2(bool), YamlNode::Scalar(val)) => bool.set_val(match val.as_str() {
+            "true" => true,
+            "false" => false,
+            _ => return Err(mismatch()),
+        }),
+        (ValueType::Op3(double), YamlNode::Scalar(num)) => double.set_val(match num.as_str() {
+            ".nan" => f64::NAN,
+            ".inf" => f64::INFINITY,
+            "-.inf" => f64::NEG_INFINITY,
+            _ => num.parse::<f64>()?,
+        }),
+        (ValueType::Op4(chara), YamlNode::Binary(bytes)) if bytes.len() == 1 => {
+            chara.set_val(bytes[0] as char)
+        }
+        (ValueType::Op4(chara), YamlNode::Str(val)) if val.chars().count() == 1 => {
+            chara.set_val(val.chars().next().unwrap())
+        }
+        (ValueType::Op6(string), YamlNode::Str(val)) => string.set_val(val.clone()),
+        _ => return Err(mismatch()),
+    }
+    Ok(val)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::{
+        clock::{ClockEvent, ClockStream},
+        qos::{Qos, Reliability},
+        timing::Timing,
+    };
+
+    const PROGRAM: &str = r#"# a hand written program
+topic /listener /chatter std_msgs/msg/String {data: !string "it\x27s \"quoted\"\n"}
+
+param /talker rate float64 10.5 (delay: 2ms)
+param /talker frames string[] ["map", "odom"] (delay: 20us, parallel)
+topic /planner /path nav_msgs/msg/Path {header: !std_msgs/msg/Header {stamp: !builtin_interfaces/msg/Time {sec: !int32 -1, nanosec: !uint32 7}, frame_id: !string "map"}, poses: !geometry_msgs/msg/PoseStamped[] [{pose: !geometry_msgs/msg/Pose {}}, {pose: !geometry_msgs/msg/Pose {}}]}
+topic /camera /image sensor_msgs/msg/Image {encoding: !string "rgb8", is_bigendian: !uint8 0, data: !uint8[] [1, 2, 255], tag: !byte !!binary "/w==", label: !char "x", scale: !float32[3] [0.1, .nan, -.inf], flags: !bool[] [true, false]}
+clock /talker /clock rosgraph_msgs/msg/Clock {}
+"#;
+
+    #[test]
+    fn programs_round_trip() {
+        let seq = Sequence::from_text(PROGRAM).unwrap();
+        assert_eq!(seq.calls.len(), 6);
+        let text = seq.to_text();
+        assert!(!text.contains('#'));
+        assert_eq!(Sequence::from_text(&text).unwrap().to_text(), text);
+
+        let lines: Vec<&str> = PROGRAM
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+        for (line, out) in lines.iter().zip(text.lines()) {
+            let out = out.replace("delay: 2000us", "delay: 2ms");
+            assert_eq!(*line, out);
+        }
+
+        let calls = &seq.calls;
+        assert_eq!(calls[0].prog.itf, ITF::Topic);
+        assert_eq!(
+            calls[0].prog.call_args.payload,
+            r#"{data: "it\x27s \"quoted\"\n"}"#
+        );
+        assert_eq!((calls[1].delay_us, calls[1].parallel), (2000, false));
+        assert_eq!((calls[2].delay_us, calls[2].parallel), (20, true));
+        assert_eq!(calls[1].prog.call_args.payload, "10.5");
+        assert_eq!(calls[3].prog.itf_info.val.len(), 3);
+        assert_eq!(
+            calls[3].prog.call_args.payload,
+            "{header: {stamp: {sec: -1, nanosec: 7}, frame_id: \"map\"}, poses: [{pose: {}}, {pose: {}}]}"
+        );
+        assert!(calls[4]
+            .prog
+            .call_args
+            .payload
+            .contains("data: [1, 2, 255], tag: !!binary \"/w==\""));
+        assert_eq!(calls[5].prog.call_args.node_name, "/talker");
+    }
+
+    #[test]
+    fn call_arguments_are_properties() {
+        let mut seq = Sequence::from_text(PROGRAM).unwrap();
+        let prog = &mut seq.calls[0].prog;
+        prog.call_args.raw = vec![0x00, 0x01, 0xff];
+        prog.call_args.qos = Some(Qos {
+            reliability: Reliability::BestEffort,
+            ..Default::default()
+        });
+        prog.call_args.set_timing(Some(Timing::default()));
+        prog.call_stream = prog.call_args.to_call_stream();
+        let clock = &mut seq.calls[5].prog;
+        clock.call_args.set_clock(Some(ClockStream {
+            period_ms: 10,
+            events: vec![ClockEvent::Jump { delta_ms: -5 }],
+        }));
+        clock.call_stream = clock.call_args.to_call_stream();
+
+        let text = seq.to_text();
+        let line = text.lines().next().unwrap();
+        assert!(line.contains("raw: \"0001ff\""), "{}", line);
+        assert!(line.contains("\"reliability\":\"BestEffort\""), "{}", line);
+        let parsed = Sequence::from_text(&text).unwrap();
+        assert_eq!(parsed.to_text(), text);
+        for (call, orig) in parsed.calls.iter().zip(seq.calls.iter()) {
+            assert_eq!(call.prog.call_stream, orig.prog.call_stream);
+            assert_eq!(call.prog.call_args.raw, orig.prog.call_args.raw);
+        }
+        assert_eq!(
+            parsed.calls[0].prog.call_args.qos,
+            seq.calls[0].prog.call_args.qos
+        );
+        assert_eq!(
+            parsed.calls[5].prog.call_args.clock,
+            seq.calls[5].prog.call_args.clock
+        );
+        let single = Prog::from_text(line).unwrap();
+        assert_eq!(single.to_text(), line);
+    }
+
+    #[test]
+    fn malformed_programs_are_rejected() {
+        for line in [
+            "topic /n /chatter",
+            "publish /n /chatter std_msgs/msg/String {data: !string \"\"}",
+            "topic /n /chatter std_msgs/msg/String {data: \"untagged\"}",
+            "topic /n /chatter std_msgs/msg/String {data: !int8 300}",
+            "topic /n /chatter std_msgs/msg/String {data: !string \"\"} (color: \"red\")",
+            "topic /n /chatter std_msgs/msg/String {data: !string \"\"} (delay: 5 parsecs)",
+            "topic /n /chatter std_msgs/msg/String {data: !string \"\"} (raw: \"0g\")",
+            "topic /n /chatter std_msgs/msg/String {data: !string \"\"} trailing",
+            "param /n rate float64 ten",
+        ] {
+            assert!(Prog::from_text(line).is_err(), "{}", line);
+        }
+        let err =
+            Sequence::from_text("# ok\n\nparam /n rate float64 1.0\nparam /n rate").unwrap_err();
+        assert!(err.to_string().starts_with("line 4:"), "{}", err);
+    }
+}
@@ -0,0 +1,427 @@
+//! TF tree fuzzing. `tf2_msgs/TFMessage` is generated against the frame tree learned from /tf
+//! and /tf_static: transforms along known edges most of the time, otherwise a tree broken in one
+//! of the ways tf2 consumers have to cope with. Consumers are watched for tf2 errors in the
+//! launch logs and for callbacks that run much longer than they usually do.
+use super::{
+    interface::{InterfaceVal, ValueType},
+    semantic::{SemanticCtx, SemanticGen},
+    ty::{character::StringType, double::DoubleType, integer::IntType, TYPE},
+    RngType,
+};
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+};
+
+pub const TF_TOPICS: [&str; 2] = ["/tf", "/tf_static"];
+pub const MAX_TRANSFORMS: usize = 8;
+// share of messages with a broken tree
+pub const BREAK_RATIO: (u32, u32) = (1, 2);
+// a consumer running this many times longer than its mean spikes
+pub const SPIKE_FACTOR: f64 = 10.0;
+const MIN_SAMPLES: u64 = 8;
+const MAX_ERRORS: usize = 16;
+// used until the target published a tree
+const DEFAULT_TREE: [(&str, &str); 3] = [
+    ("map", "odom"),
+    ("odom", "base_link"),
+    ("base_link", "base_scan"),
+];
+// error codes and exception texts of tf2 and tf2_ros, logged by the consumer
+const TF_ERRORS: [&str; 14] = [
+    "TF_REPEATED_DATA",
+    "TF_OLD_DATA",
+    "TF_SELF_TRANSFORM",
+    "TF_NO_FRAME_ID",
+    "TF_NO_CHILD_FRAME_ID",
+    "TF_NAN_INPUT",
+    "TF_DENORMALIZED_QUATERNION",
+    "TF_INVALID",
+    "extrapolation",
+    "Could not find a connection",
+    "does not exist",
+    "LookupException",
+    "ConnectivityException",
+    "jump back in time",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum TfBreak {
+    // a frame becomes the parent of one of its ancestors, or of itself
+    Cycle,
+    // a subtree no known frame connects to
+    Disconnected,
+    // a known child claimed by a second parent
+    DuplicateParent,
+    // stamps older than the buffer keeps
+    Stale,
+    // stamps ahead of the clock, lookups have to extrapolate
+    Future,
+    Denormalized,
+}
+
+const BREAKS: [TfBreak; 6] = [
+    TfBreak::Cycle,
+    TfBreak::Disconnected,
+    TfBreak::DuplicateParent,
+    TfBreak::Stale,
+    TfBreak::Future,
+    TfBreak::Denormalized,
+];
+
+struct Transform {
+    parent: String,
+    child: String,
+    stamp: (i64, i64),
+    translation: [f64; 3],
+    rotation: [f64; 4],
+}
+
+impl Transform {
+    fn new(parent: &str, child: &str, stamp: (i64, i64), rng: &mut RngType) -> Transform {
+        let mut rotation = [0.0; 4];
+        rotation
+            .iter_mut()
+            .for_each(|val| *val = rng.gen_range(-1.0..=1.0));
+        let norm = rotation
+            .iter()
+            .map(|val| val * val)
+            .sum::<f64>()
+            .sqrt()
+            .max(f64::EPSILON);
+        rotation.iter_mut().for_each(|val| *val /= norm);
+        let mut translation = [0.0; 3];
+        translation
+            .iter_mut()
+            .for_each(|val| *val = rng.gen_range(-10.0..=10.0));
+        Transform {
+            parent: parent.to_string(),
+            child: child.to_string(),
+            stamp,
+            translation,
+            rotation,
+        }
+    }
+
+    // geometry_msgs/TransformStamped, one element of `transforms`
+    fn to_itf(&self) -> InterfaceVal {
+        let node = |name: &str, ty: &str, val: Vec<ValueType>| {
+            let mut node = InterfaceVal::new(&name.to_string(), &ty.to_string());
+            node.val = val;
+            ValueType::Op(node)
+        };
+        let int = |name: &str, ty: &str, tyid: TYPE, val: i64, pad: i32| {
+            let int = IntType::new(tyid as usize, val as u64, u64::MAX, 0, pad);
+            node(name, ty, vec![ValueType::Op1(int)])
+        };
+        let double = |name: &str, val: f64| {
+            let double = DoubleType::new(TYPE::Float64 as usize, val, f64::MAX, f64::MIN, 64);
+            node(name, "float64", vec![ValueType::Op3(double)])
+        };
+        let string = |name: &str, val: &str| {
+            let string = StringType::new(TYPE::String as usize, val.to_string(), val.len() as i32);
+            node(name, "string", vec![ValueType::Op6(string)])
+        };
+
+        let stamp = node(
+            "stamp",
+            "builtin_interfaces/Time",
+            vec![
+                int("sec", "int32", TYPE::Int32, self.stamp.0, -32),
+                int("nanosec", "uint32", TYPE::UInt32, self.stamp.1, 32),
+            ],
+        );
+        let header = node(
+            "header",
+            "std_msgs/Header",
+            vec![stamp, string("frame_id", &self.parent)],
+        );
+        let translation = ["x", "y", "z"]
+            .iter()
+            .zip(self.translation.iter())
+            .map(|(name, val)| double(name, *val))
+            .collect();
+        let rotation = ["x", "y", "z", "w"]
+            .iter()
+            .zip(self.rotation.iter())
+            .map(|(name, val)| double(name, *val))
+            .collect();
+        let transform = node(
+            "transform",
+            "geometry_msgs/Transform",
+            vec![
+                node("translation", "geometry_msgs/Vector3", translation),
+                node("rotation", "geometry_msgs/Quaternion", rotation),
+            ],
+        );
+        let mut itf = InterfaceVal::new(
+            &"transforms".to_string(),
+            &"geometry_msgs/TransformStamped[]".to_string(),
+        );
+        itf.val = vec![header, string("child_frame_id", &self.child), transform];
+        itf
+    }
+}
+
+// follow the parents of frame up to the root, stops on a cycle the target published itself
+fn get_root(tree: &[(String, String)], frame: &str) -> String {
+    let mut root = frame.to_string();
+    for _ in 0..tree.len() {
+        match tree.iter().find(|(_, child)| *child == root) {
+            Some((parent, _)) => root = parent.clone(),
+            None => break,
+        }
+    }
+    root
+}
+
+fn gen_frame(rng: &mut RngType) -> String {
+    format!("fuzz_frame_{}", rng.gen::<u16>())
+}
+
+/// `tf2_msgs/TFMessage` along the learned frame tree, one break at a time.
+pub struct TfGen;
+
+impl SemanticGen for TfGen {
+    fn generate(
+        &self,
+        itf: &mut InterfaceVal,
+        ctx: &SemanticCtx,
+        rng: &mut RngType,
+    ) -> Result<(), failure::Error> {
+        let tree: Vec<(String, String)> = match ctx.tf_edges.is_empty() {
+            true => DEFAULT_TREE
+                .iter()
+                .map(|(parent, child)| (parent.to_string(), child.to_string()))
+                .collect(),
+            false => ctx.tf_edges.clone(),
+        };
+        let now = ctx.now();
+        let count = rng.gen_range(1..=tree.len().min(MAX_TRANSFORMS));
+        let mut transforms: Vec<Transform> = tree
+            .choose_multiple(rng, count)
+            .map(|(parent, child)| Transform::new(parent, child, now, rng))
+            .collect();
+
+        let broken = match rng.gen_ratio(BREAK_RATIO.0, BREAK_RATIO.1) {
+            true => BREAKS.choose(rng).copied(),
+            false => None,
+        };
+        let (parent, child) = tree.choose(rng).unwrap().clone();
+        match broken {
+            Some(TfBreak::Cycle) if rng.gen_ratio(1, 4) => {
+                transforms.push(Transform::new(&child, &child, now, rng))
+            }
+            Some(TfBreak::Cycle) => {
+                let root = get_root(&tree, &parent);
+                transforms.push(Transform::new(&child, &root, now, rng));
+            }
+            Some(TfBreak::Disconnected) => {
+                let island = gen_frame(rng);
+                transforms.push(Transform::new(&island, &gen_frame(rng), now, rng));
+                if rng.gen() {
+                    // or a known frame hung below the island
+                    transforms.push(Transform::new(&island, &child, now, rng));
+                }
+            }
+            Some(TfBreak::DuplicateParent) => {
+                let other = tree
+                    .iter()
+                    .flat_map(|(parent, child)| [parent, child])
+                    .filter(|frame| **frame != parent && **frame != child)
+                    .collect::<Vec<&String>>()
+                    .choose(rng)
+                    .map(|frame| frame.to_string())
+                    .unwrap_or_else(|| gen_frame(rng));
+                transforms.push(Transform::new(&other, &child, now, rng));
+            }
+            Some(TfBreak::Stale) => {
+                let age = *[11, 60, 3600, now.0].choose(rng).unwrap();
+                transforms
+                    .iter_mut()
+                    .for_each(|transform| transform.stamp.0 -= age);
+            }
+            Some(TfBreak::Future) => {
+                let ahead = *[1, 5, 60, 86400].choose(rng).unwrap();
+                transforms
+                    .iter_mut()
+                    .for_each(|transform| transform.stamp.0 += ahead);
+            }
+            Some(TfBreak::Denormalized) => {
+                let scale = *[0.0, 0.5, 2.0, f64::NAN].choose(rng).unwrap();
+                let transform = transforms.choose_mut(rng).unwrap();
+                transform.rotation.iter_mut().for_each(|val| *val *= scale);
+            }
+            None => (),
+        }
+
+        itf.val
+            .retain(|val| !matches!(val, ValueType::Op(field) if field.itf_name == "transforms"));
+        for transform in transforms.iter() {
+            itf.val.push(ValueType::Op(transform.to_itf()));
+        }
+        Ok(())
+    }
+}
+
+pub fn is_tf_topic(topic: &str) -> bool {
+    TF_TOPICS.contains(&topic.trim_end_matches('/'))
+}
+
+// sizes of the launch logs, errors are read from there on after the call
+pub fn get_log_len(work_dir: &str) -> [u64; 2] {
+    let len = |log: &str| {
+        fs::metadata(format!("{}/{}", work_dir, log))
+            .map(|meta| meta.len())
+            .unwrap_or(0)
+    };
+    [len("instance_out"), len("instance_err")]
+}
+
+// tf2 errors the consumers logged since `since`, each line once
+pub fn read_tf_errors(work_dir: &str, since: [u64; 2]) -> Vec<String> {
+    let mut errors: Vec<String> = Vec::new();
+    for (log, offset) in ["instance_out", "instance_err"].iter().zip(since) {
+        let mut text = String::new();
+        let read = fs::File::open(format!("{}/{}", work_dir, log)).and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_to_string(&mut text)
+        });
+        if read.is_err() {
+            continue;
+        }
+        for line in text.lines() {
+            if errors.len() >= MAX_ERRORS {
+                return errors;
+            }
+            if TF_ERRORS.iter().any(|error| line.contains(error))
+                && !errors.iter().any(|seen| seen == line)
+            {
+                errors.push(line.to_string());
+            }
+        }
+    }
+    errors
+}
+
+/// What the consumers of an injected TF message did with it.
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct TfOutcome {
+    pub consumers: Vec<u64>,
+    pub errors: Vec<String>,
+    // longest run of every consumer in the call, in ns
+    pub latency: Vec<(u64, u64)>,
+    // consumers that ran SPIKE_FACTOR times longer than their mean
+    pub spikes: Vec<u64>,
+    // a spike or an error kind the campaign had not seen yet
+    pub novel: bool,
+}
+
+/// Latency of every TF consumer over the campaign, and the tf2 errors seen so far.
+#[derive(Debug, Default, Clone)]
+pub struct TfMonitor {
+    // runs and mean duration in ns per consumer
+    latency: HashMap<u64, (u64, f64)>,
+    errors: Vec<String>,
+}
+
+impl TfMonitor {
+    // fill in the spikes of outcome and learn from it, true when it shows something new
+    pub fn observe(&mut self, outcome: &mut TfOutcome) -> bool {
+        for &(cb_id, duration) in outcome.latency.iter() {
+            let (runs, mean) = self.latency.entry(cb_id).or_insert((0, 0.0));
+            if *runs >= MIN_SAMPLES && duration as f64 > *mean * SPIKE_FACTOR {
+                outcome.spikes.push(cb_id);
+            }
+            *runs += 1;
+            *mean += (duration as f64 - *mean) / *runs as f64;
+        }
+        let mut novel = !outcome.spikes.is_empty();
+        for error in outcome.errors.iter() {
+            // the error kind, stamps and frame names differ on every line
+            let kind = TF_ERRORS.iter().find(|kind| error.contains(*kind)).unwrap();
+            if !self.errors.iter().any(|seen| seen == kind) {
+                self.errors.push(kind.to_string());
+                novel = true;
+            }
+        }
+        novel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::semantic::get_generator;
+    use rand::SeedableRng;
+
+    fn string(itf: &InterfaceVal, path: &[&str]) -> String {
+        let mut itf = itf;
+        for name in path {
+            itf = itf
+                .val
+                .iter()
+                .find_map(|val| match val {
+                    ValueType::Op(field) if field.itf_name == *name => Some(field),
+                    _ => None,
+                })
+                .unwrap();
+        }
+        match itf.val.first() {
+            Some(ValueType::Op6(string)) => string.get_val(),
+            _ => panic!("{} is not a string", path.join(".")),
+        }
+    }
+
+    #[test]
+    fn transforms_follow_the_tree_unless_broken() {
+        let ctx = SemanticCtx {
+            tf_edges: vec![
+                ("map".to_string(), "odom".to_string()),
+                ("odom".to_string(), "base_link".to_string()),
+            ],
+            ..Default::default()
+        };
+        let gen = get_generator("tf2_msgs/msg/TFMessage").unwrap();
+        let mut rng = RngType::seed_from_u64(0);
+        let mut off_tree = 0;
+        for _ in 0..64 {
+            let mut itf =
+                InterfaceVal::new(&"/tf".to_string(), &"tf2_msgs/msg/TFMessage".to_string());
+            gen.generate(&mut itf, &ctx, &mut rng).unwrap();
+            assert!(!itf.val.is_empty() && itf.val.len() <= ctx.tf_edges.len() + 2);
+            for val in itf.val.iter() {
+                let transform = match val {
+                    ValueType::Op(transform) => transform,
+                    _ => panic!("transforms are messages"),
+                };
+                let edge = (
+                    string(transform, &["header", "frame_id"]),
+                    string(transform, &["child_frame_id"]),
+                );
+                if !ctx.tf_edges.contains(&edge) {
+                    off_tree += 1;
+                }
+            }
+        }
+        assert!(off_tree > 0 && off_tree < 64);
+
+        let mut monitor = TfMonitor::default();
+        let mut outcome = TfOutcome {
+            errors: vec!["Warning: TF_OLD_DATA ignoring data from the past".to_string()],
+            ..Default::default()
+        };
+        assert!(monitor.observe(&mut outcome));
+        assert!(!monitor.observe(&mut outcome));
+        for _ in 0..MIN_SAMPLES {
+            outcome.latency = vec![(1, 1000)];
+            monitor.observe(&mut outcome);
+        }
+        outcome.latency = vec![(1, 100_000)];
+        assert!(monitor.observe(&mut outcome));
+        assert_eq!(outcome.spikes, vec![1]);
+    }
+}
@@ -0,0 +1,154 @@
+use super::RngType;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+pub const MIN_RATE: f64 = 0.5;
+pub const MAX_RATE: f64 = 10000.0;
+// keep a stream well inside the execution timeout
+pub const MAX_DURATION_MS: u64 = 5000;
+pub const MAX_BURST: u32 = 64;
+pub const MAX_MSGS: u64 = 20000;
+pub const MAX_JITTER_US: u64 = 10000;
+const INTERESTING_RATE: [f64; 6] = [1.0, 10.0, 30.0, 100.0, 1000.0, 10000.0];
+
+/// Publishing pattern of a topic call: `burst` messages back to back on every tick, `rate`
+/// ticks per second for `duration_ms`, each gap stretched by up to `jitter_us`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Timing {
+    pub rate: f64,
+    pub burst: u32,
+    pub duration_ms: u64,
+    pub jitter_us: u64,
+    // keep publishing in the background so the next call of a sequence interleaves
+    pub detach: bool,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            rate: 1.0,
+            burst: 1,
+            duration_ms: 1000,
+            jitter_us: 0,
+            detach: false,
+        }
+    }
+}
+
+impl Timing {
+    pub fn get_ticks(&self) -> u64 {
+        ((self.rate * self.duration_ms as f64 / 1000.0) as u64).max(1)
+    }
+
+    pub fn get_count(&self) -> u64 {
+        self.get_ticks() * self.burst as u64
+    }
+
+    pub fn generate(rng: &mut RngType) -> Timing {
+        let mut timing = Timing {
+            rate: INTERESTING_RATE[rng.gen_range(0..INTERESTING_RATE.len())],
+            burst: match rng.gen_range(0..3) {
+                0 => rng.gen_range(2..=MAX_BURST),
+                _ => 1,
+            },
+            duration_ms: rng.gen_range(1..=MAX_DURATION_MS),
+            jitter_us: match rng.gen_range(0..3) {
+                0 => rng.gen_range(0..=MAX_JITTER_US),
+                _ => 0,
+            },
+            detach: rng.gen_ratio(1, 4),
+        };
+        timing.clamp();
+        timing
+    }
+
+    // change one dimension of the pattern
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        match rng.gen_range(0..5) {
+            0 => {
+                self.rate = match rng.gen_range(0..3) {
+                    0 => self.rate * 2.0,
+                    1 => self.rate / 2.0,
+                    _ => INTERESTING_RATE[rng.gen_range(0..INTERESTING_RATE.len())],
+                }
+            }
+            1 => {
+                self.burst = match rng.gen() {
+                    true => self.burst.saturating_mul(2),
+                    false => rng.gen_range(1..=MAX_BURST),
+                }
+            }
+            2 => self.duration_ms = rng.gen_range(1..=MAX_DURATION_MS),
+            3 => {
+                self.jitter_us = match rng.gen() {
+                    true => 0,
+                    false => rng.gen_range(0..=MAX_JITTER_US),
+                }
+            }
+            _ => self.detach = !self.detach,
+        }
+        self.clamp();
+    }
+
+    fn clamp(&mut self) {
+        self.rate = self.rate.clamp(MIN_RATE, MAX_RATE);
+        self.burst = self.burst.clamp(1, MAX_BURST);
+        self.duration_ms = self.duration_ms.clamp(1, MAX_DURATION_MS);
+        self.jitter_us = self.jitter_us.min(MAX_JITTER_US);
+        while self.get_count() > MAX_MSGS {
+            self.duration_ms /= 2;
+            if self.duration_ms <= 1 {
+                self.duration_ms = 1;
+                self.burst = (MAX_MSGS / self.get_ticks()).max(1) as u32;
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn check_bounds(timing: &Timing) {
+        assert!(timing.rate >= MIN_RATE && timing.rate <= MAX_RATE);
+        assert!(timing.burst >= 1 && timing.burst <= MAX_BURST);
+        assert!(timing.duration_ms >= 1 && timing.duration_ms <= MAX_DURATION_MS);
+        assert!(timing.jitter_us <= MAX_JITTER_US);
+        assert!(timing.get_count() <= MAX_MSGS);
+    }
+
+    #[test]
+    fn generate_within_bounds() {
+        let mut rng = RngType::seed_from_u64(1);
+        for _ in 0..1000 {
+            check_bounds(&Timing::generate(&mut rng));
+        }
+    }
+
+    #[test]
+    fn mutate_within_bounds() {
+        let mut rng = RngType::seed_from_u64(2);
+        for _ in 0..100 {
+            let mut timing = Timing::generate(&mut rng);
+            // doubling and halving pile up over many rounds
+            for _ in 0..100 {
+                timing.mutate(&mut rng);
+                check_bounds(&timing);
+            }
+        }
+    }
+
+    #[test]
+    fn clamp_caps_the_message_count() {
+        let mut timing = Timing {
+            rate: MAX_RATE,
+            burst: MAX_BURST,
+            duration_ms: MAX_DURATION_MS,
+            ..Default::default()
+        };
+        timing.clamp();
+        check_bounds(&timing);
+    }
+}
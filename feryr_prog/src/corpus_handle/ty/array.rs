@@ -19,6 +19,9 @@ pub struct ArrayType {
     char_array: Vec<CharType>,
     bool_array: Vec<BoolType>,
     string_array: Vec<StringType>,
+    // 8-bit elements filled in one go, e.g. image data, used instead of the arrays above when set
+    #[serde(default)]
+    bytes: Vec<u8>,
 }
 impl ArrayType {
     pub fn new(tyid: usize, inner_type: TYPE, len: u64) -> Self {
@@ -31,16 +34,64 @@ impl ArrayType {
             char_array: Vec::new(),
             bool_array: Vec::new(),
             string_array: Vec::new(),
+            bytes: Vec::new(),
         }
     }
     pub fn get_tyid(&self) -> usize {
         self.tyid
     }
+    pub fn get_inner_type(&self) -> TYPE {
+        self.inner_type
+    }
+    pub fn get_int_array(&self) -> &Vec<IntType> {
+        &self.int_array
+    }
+    pub fn get_float_array(&self) -> &Vec<DoubleType> {
+        &self.float_array
+    }
+    pub fn get_char_array(&self) -> &Vec<CharType> {
+        &self.char_array
+    }
+    pub fn get_bool_array(&self) -> &Vec<BoolType> {
+        &self.bool_array
+    }
+    pub fn get_string_array(&self) -> &Vec<StringType> {
+        &self.string_array
+    }
+    pub fn get_bytes(&self) -> &Vec<u8> {
+        &self.bytes
+    }
+    pub fn set_bytes(&mut self, bytes: Vec<u8>) {
+        self.len = bytes.len() as u64;
+        self.int_array.clear();
+        self.char_array.clear();
+        self.bytes = bytes;
+    }
+    pub fn set_int_array(&mut self, vals: Vec<IntType>) {
+        self.len = vals.len() as u64;
+        self.int_array = vals;
+    }
+    pub fn set_float_array(&mut self, vals: Vec<DoubleType>) {
+        self.len = vals.len() as u64;
+        self.float_array = vals;
+    }
+    pub fn set_string_array(&mut self, vals: Vec<StringType>) {
+        self.len = vals.len() as u64;
+        self.string_array = vals;
+    }
+    pub fn set_bool_array(&mut self, vals: Vec<BoolType>) {
+        self.len = vals.len() as u64;
+        self.bool_array = vals;
+    }
 
     pub fn get_val(&self) -> String {
         let mut res = String::new();
+        if !self.bytes.is_empty() {
+            let vals: Vec<String> = self.bytes.iter().map(|b| b.to_string()).collect();
+            return vals.join(", ");
+        }
         match self.inner_type {
-            TYPE::String => {
+            TYPE::String | TYPE::WString => {
                 for i in 0..self.string_array.len() {
                     if self.string_array.len() == 0 {
                         break;
@@ -133,9 +184,10 @@ impl ArrayType {
     }
 
     pub fn gen_array(&mut self) -> Result<(), failure::Error> {
+        self.bytes.clear();
         // generate based on pad
         match self.inner_type {
-            TYPE::String => {
+            TYPE::String | TYPE::WString => {
                 for _ in 0..self.len {
                     let mut val = StringType::new(0, "".to_string(), 0);
                     match val.gen_string() {
@@ -228,7 +280,7 @@ impl ArrayType {
             }
             TYPE::Bool => {
                 for _ in 0..self.len {
-                    let val = BoolType::new(0, 0 as u64, 1 as u64, 0 as u64);
+                    let mut val = BoolType::new(0, 0 as u64, 1 as u64, 0 as u64);
                     val.gen_bool();
                     self.bool_array.push(val);
                 }
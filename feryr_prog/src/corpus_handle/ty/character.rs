@@ -28,12 +28,18 @@ impl CharType {
     pub fn get_val(&self) -> String {
         self.val.to_string()
     }
+    pub fn get_char(&self) -> char {
+        self.val
+    }
     pub fn get_max_val(&self) -> char {
         self.max_val
     }
     pub fn get_min_val(&self) -> char {
         self.min_val
     }
+    pub fn set_val(&mut self, val: char) {
+        self.val = val;
+    }
 
     pub fn gen_char(&mut self) -> char {
         let mut rng = rand::thread_rng();
@@ -61,6 +67,10 @@ impl StringType {
     pub fn get_len(&self) -> i32 {
         self.len
     }
+    pub fn set_val(&mut self, val: String) {
+        self.len = val.chars().count() as i32;
+        self.val = val;
+    }
     pub fn gen_string(&mut self) -> Result<(), failure::Error> {
         if self.len != 0 {
             let mut rng = rand::thread_rng();
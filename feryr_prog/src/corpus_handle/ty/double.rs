@@ -31,12 +31,21 @@ impl DoubleType {
             }
         }
     }
+    pub fn get_num(&self) -> f64 {
+        self.val
+    }
+    pub fn get_pad(&self) -> i32 {
+        self.pad
+    }
     pub fn get_max_val(&self) -> f64 {
         self.max_val
     }
     pub fn get_min_val(&self) -> f64 {
         self.min_val
     }
+    pub fn set_val(&mut self, val: f64) {
+        self.val = val;
+    }
 
     pub fn gen_double(&mut self) -> f64 {
         // generate based on pad
@@ -48,11 +48,11 @@ impl IntType {
     pub fn get_pad(&self) -> i32 {
         self.pad
     }
-
-    pub fn gen_i16_string() -> String {
-        let mut rng = rand::thread_rng();
-        let val = rng.gen::<i16>() as i16;
-        val.to_string()
+    pub fn get_num(&self) -> u64 {
+        self.val
+    }
+    pub fn set_val(&mut self, val: u64) {
+        self.val = val;
     }
 
     pub fn gen_integer(&mut self) -> u64 {
@@ -127,16 +127,22 @@ impl BoolType {
             _ => panic!("unsupport bool type"),
         }
     }
+    pub fn get_num(&self) -> u64 {
+        self.val
+    }
     pub fn get_max_val(&self) -> u64 {
         self.max_val
     }
     pub fn get_min_val(&self) -> u64 {
         self.min_val
     }
+    pub fn set_val(&mut self, val: bool) {
+        self.val = val as u64;
+    }
 
-    pub fn gen_bool(&self) -> u64 {
+    pub fn gen_bool(&mut self) -> u64 {
         let mut rng = rand::thread_rng();
-        let val = rng.gen_range(0..2);
-        val
+        self.val = rng.gen_range(0..2);
+        self.val
     }
 }
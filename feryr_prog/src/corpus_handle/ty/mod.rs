@@ -47,6 +47,7 @@ pub enum TYPE {
     Int64,
     UInt64,
     String,
+    WString,
     COMPLEX,
     ARRAY,
 }
@@ -71,6 +72,10 @@ impl TYPE {
             "int64" => TYPE::Int64,
             "uint64" => TYPE::UInt64,
             "string" => TYPE::String,
+            "wstring" => TYPE::WString,
+            // bounded strings like string<=10
+            s if s.starts_with("string<=") => TYPE::String,
+            s if s.starts_with("wstring<=") => TYPE::WString,
             _ => TYPE::COMPLEX,
         }
     }
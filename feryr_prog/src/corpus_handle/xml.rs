@@ -0,0 +1,653 @@
+//! Structure-aware mutation of XML string parameters such as `robot_description` (URDF) and
+//! `robot_description_semantic` (SRDF). The documents read after boot are parsed into a tree,
+//! mutated on elements, attributes, numbers, the joint graph and mesh paths, and written back, so
+//! that the parsers of the target see mostly well-formed documents instead of 32 random chars.
+use super::{
+    interface::{InterfaceVal, Node, ValueType},
+    restore::ros2,
+    semantic::set_string,
+    RngType,
+};
+use rand::{seq::SliceRandom, Rng};
+
+// string parameters whose value is read at boot, by a part of their name
+pub const XML_HINTS: [&str; 5] = ["description", "urdf", "srdf", "xml", "xacro"];
+// share of inputs on a seeded parameter or topic that get a mutated document
+pub const XML_RATIO: (u32, u32) = (1, 2);
+const MALFORMED_RATIO: (u32, u32) = (1, 16);
+const MAX_MUTATIONS: usize = 4;
+const MAX_DEPTH: usize = 256;
+const INTERESTING_NUMBERS: [&str; 12] = [
+    "0", "-0", "-1", "1e308", "-1e308", "nan", "inf", "-inf", "1e-320", "1e39", "0x10", "",
+];
+const INTERESTING_STRINGS: [&str; 6] = ["", " ", "&amp;", "$(find missing)", "../", "\u{ff}"];
+const JOINT_TYPES: [&str; 7] = [
+    "revolute",
+    "continuous",
+    "prismatic",
+    "fixed",
+    "floating",
+    "planar",
+    "unknown",
+];
+const MESH_PATHS: [&str; 7] = [
+    "",
+    "package://",
+    "package://missing_pkg/meshes/missing.stl",
+    "file:///dev/zero",
+    "file:///proc/self/mem",
+    "../../../../../etc/passwd",
+    "mesh.xyz",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    // character data, kept escaped as read
+    Text(String),
+    // comments, CDATA and processing instructions, kept verbatim
+    Raw(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    // values are kept escaped as read
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+}
+
+/// A document as the root element and the raw text around it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlDoc {
+    pub prolog: String,
+    pub root: XmlElement,
+    pub epilog: String,
+}
+
+struct Parser<'a> {
+    doc: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.doc[self.pos..]
+    }
+
+    fn error(&self, what: &str) -> failure::Error {
+        failure::err_msg(format!("xml: {} at {}", what, self.pos))
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // up to and including end
+    fn take_until(&mut self, end: &str) -> Result<&'a str, failure::Error> {
+        let len = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| self.error(&format!("missing {}", end)))?;
+        let taken = &self.rest()[..len + end.len()];
+        self.pos += taken.len();
+        Ok(taken)
+    }
+
+    fn take_name(&mut self) -> Result<&'a str, failure::Error> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "/>=".contains(c))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error("missing name"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    // comments, CDATA, processing instructions and declarations
+    fn take_raw(&mut self) -> Result<Option<&'a str>, failure::Error> {
+        for (start, end) in [
+            ("<!--", "-->"),
+            ("<![CDATA[", "]]>"),
+            ("<?", "?>"),
+            ("<!", ">"),
+        ] {
+            if self.rest().starts_with(start) {
+                return Ok(Some(self.take_until(end)?));
+            }
+        }
+        Ok(None)
+    }
+
+    fn parse_element(&mut self, depth: usize) -> Result<XmlElement, failure::Error> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deep"));
+        }
+        self.pos += 1;
+        let mut element = XmlElement {
+            name: self.take_name()?.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+        };
+        loop {
+            self.skip_ws();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break;
+            }
+            let name = self.take_name()?.to_string();
+            self.skip_ws();
+            if !self.rest().starts_with('=') {
+                return Err(self.error("missing ="));
+            }
+            self.pos += 1;
+            self.skip_ws();
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("unquoted attribute")),
+            };
+            self.pos += 1;
+            let value = self.take_until(&quote.to_string())?;
+            element
+                .attrs
+                .push((name, value[..value.len() - 1].to_string()));
+        }
+
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("unclosed {}", element.name)));
+            }
+            if rest.starts_with("</") {
+                self.pos += 2;
+                let name = self.take_name()?;
+                if name != element.name {
+                    return Err(self.error(&format!("{} closes {}", name, element.name)));
+                }
+                self.take_until(">")?;
+                return Ok(element);
+            }
+            if let Some(raw) = self.take_raw()? {
+                element.children.push(XmlNode::Raw(raw.to_string()));
+            } else if rest.starts_with('<') {
+                let child = self.parse_element(depth + 1)?;
+                element.children.push(XmlNode::Element(child));
+            } else {
+                let len = rest.find('<').unwrap_or(rest.len());
+                element
+                    .children
+                    .push(XmlNode::Text(rest[..len].to_string()));
+                self.pos += len;
+            }
+        }
+    }
+}
+
+impl XmlDoc {
+    pub fn parse(doc: &str) -> Result<XmlDoc, failure::Error> {
+        let mut parser = Parser { doc, pos: 0 };
+        loop {
+            parser.skip_ws();
+            if parser.take_raw()?.is_none() {
+                break;
+            }
+        }
+        if !parser.rest().starts_with('<') {
+            return Err(parser.error("missing root element"));
+        }
+        let prolog = doc[..parser.pos].to_string();
+        let root = parser.parse_element(0)?;
+        Ok(XmlDoc {
+            prolog,
+            root,
+            epilog: parser.rest().to_string(),
+        })
+    }
+
+    pub fn emit(&self) -> String {
+        let mut res = self.prolog.clone();
+        emit_element(&self.root, &mut res);
+        res.push_str(&self.epilog);
+        res
+    }
+
+    // index paths of every element below the root, parents before their children
+    fn get_paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = Vec::new();
+        collect_paths(&self.root, &mut Vec::new(), &mut paths);
+        paths
+    }
+
+    fn get_mut(&mut self, path: &[usize]) -> &mut XmlElement {
+        let mut element = &mut self.root;
+        for &idx in path {
+            element = match &mut element.children[idx] {
+                XmlNode::Element(child) => child,
+                _ => unreachable!("paths only lead to elements"),
+            };
+        }
+        element
+    }
+
+    fn find(&self, name: &str) -> Vec<Vec<usize>> {
+        self.get_paths()
+            .into_iter()
+            .filter(|path| self.get(path).name == name)
+            .collect()
+    }
+
+    fn get(&self, path: &[usize]) -> &XmlElement {
+        let mut element = &self.root;
+        for &idx in path {
+            element = match &element.children[idx] {
+                XmlNode::Element(child) => child,
+                _ => unreachable!("paths only lead to elements"),
+            };
+        }
+        element
+    }
+
+    pub fn mutate(&mut self, rng: &mut RngType) {
+        for _ in 0..rng.gen_range(1..=MAX_MUTATIONS) {
+            match rng.gen_range(0..5) {
+                0 => self.mutate_element(rng),
+                1 => self.mutate_attr(rng),
+                2 => self.mutate_number(rng),
+                3 => self.mutate_joint(rng),
+                _ => self.mutate_mesh(rng),
+            }
+        }
+    }
+
+    // duplicate, remove or move an element below the root
+    fn mutate_element(&mut self, rng: &mut RngType) {
+        let paths = self.get_paths();
+        let path = match paths.choose(rng) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let (idx, parent) = path.split_last().unwrap();
+        let element = self.get(&path).clone();
+        match rng.gen_range(0..3) {
+            0 => self
+                .get_mut(parent)
+                .children
+                .insert(idx + 1, XmlNode::Element(element)),
+            1 => {
+                self.get_mut(parent).children.remove(*idx);
+            }
+            _ => {
+                self.get_mut(parent).children.remove(*idx);
+                let paths = self.get_paths();
+                let target = match paths.choose(rng) {
+                    Some(target) => target.clone(),
+                    None => Vec::new(),
+                };
+                self.get_mut(&target)
+                    .children
+                    .push(XmlNode::Element(element));
+            }
+        }
+    }
+
+    // remove an attribute, copy one from another element or set a link name or odd string
+    fn mutate_attr(&mut self, rng: &mut RngType) {
+        let links = self.get_link_names();
+        let paths: Vec<Vec<usize>> = self
+            .get_paths()
+            .into_iter()
+            .filter(|path| !self.get(path).attrs.is_empty())
+            .collect();
+        let path = match paths.choose(rng) {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let donor = paths.choose(rng).map(|donor| self.get(donor).attrs.clone());
+        let element = self.get_mut(&path);
+        let idx = rng.gen_range(0..element.attrs.len());
+        match rng.gen_range(0..3) {
+            0 => {
+                element.attrs.remove(idx);
+            }
+            1 => {
+                let attr = donor.and_then(|attrs| attrs.choose(rng).cloned()).unwrap();
+                if !element.attrs.iter().any(|(name, _)| *name == attr.0) {
+                    element.attrs.push(attr);
+                }
+            }
+            _ => {
+                element.attrs[idx].1 = match (links.choose(rng), rng.gen()) {
+                    (Some(link), true) => link.clone(),
+                    _ => INTERESTING_STRINGS.choose(rng).unwrap().to_string(),
+                }
+            }
+        }
+    }
+
+    // one number of a numeric attribute, e.g. xyz, rpy, mass or limit, or their count
+    fn mutate_number(&mut self, rng: &mut RngType) {
+        let mut numeric = Vec::new();
+        for path in self.get_paths() {
+            for (idx, (_, value)) in self.get(&path).attrs.iter().enumerate() {
+                let mut tokens = value.split_whitespace().peekable();
+                if tokens.peek().is_some() && tokens.all(|token| token.parse::<f64>().is_ok()) {
+                    numeric.push((path.clone(), idx));
+                }
+            }
+        }
+        let (path, idx) = match numeric.choose(rng) {
+            Some(attr) => attr.clone(),
+            None => return,
+        };
+        let value = &mut self.get_mut(&path).attrs[idx].1;
+        let mut tokens: Vec<String> = value.split_whitespace().map(String::from).collect();
+        let pos = rng.gen_range(0..tokens.len());
+        match rng.gen_range(0..4) {
+            0 => {
+                tokens.remove(pos);
+            }
+            1 => tokens.insert(pos, tokens[pos].clone()),
+            2 => {
+                let scale = *[-1.0, 1e-9, 1e9].choose(rng).unwrap();
+                let scaled = tokens[pos].parse::<f64>().unwrap_or(1.0) * scale;
+                tokens[pos] = scaled.to_string();
+            }
+            _ => tokens[pos] = INTERESTING_NUMBERS.choose(rng).unwrap().to_string(),
+        }
+        *value = tokens.join(" ");
+    }
+
+    fn get_link_names(&self) -> Vec<String> {
+        self.find("link")
+            .iter()
+            .filter_map(|path| get_attr(self.get(path), "name"))
+            .collect()
+    }
+
+    // rewire the kinematic tree: cycles, self loops, unknown links, two parents of one link,
+    // other joint types and clashing joint names
+    fn mutate_joint(&mut self, rng: &mut RngType) {
+        let joints = self.find("joint");
+        let path = match joints.choose(rng) {
+            Some(path) => path.clone(),
+            None => return self.mutate_attr(rng),
+        };
+        let joint = self.get(&path);
+        let parent = joint_link(joint, "parent").unwrap_or_default();
+        let child = joint_link(joint, "child").unwrap_or_default();
+        // the root of the tree, reached from parent through the other joints
+        let mut root = parent.clone();
+        for _ in 0..joints.len() {
+            let up = joints
+                .iter()
+                .find(|other| joint_link(self.get(other), "child").as_ref() == Some(&root))
+                .and_then(|other| joint_link(self.get(other), "parent"));
+            match up {
+                Some(up) if up != parent => root = up,
+                _ => break,
+            }
+        }
+        let other_child = joints
+            .choose(rng)
+            .and_then(|other| joint_link(self.get(other), "child"));
+        let other_name = joints
+            .choose(rng)
+            .and_then(|other| get_attr(self.get(other), "name"));
+
+        let joint = self.get_mut(&path);
+        match rng.gen_range(0..6) {
+            0 => set_joint_link(joint, "child", &root),
+            1 => set_joint_link(joint, "child", &parent),
+            2 => set_joint_link(joint, "parent", "missing_link"),
+            3 => {
+                if let Some(other_child) = other_child {
+                    set_joint_link(joint, "child", &other_child)
+                }
+            }
+            4 => set_attr(joint, "type", JOINT_TYPES.choose(rng).unwrap()),
+            _ => match other_name {
+                Some(other_name) => set_attr(joint, "name", &other_name),
+                None => set_joint_link(joint, "parent", &child),
+            },
+        }
+    }
+
+    // mesh and texture paths of the visual and collision geometry
+    fn mutate_mesh(&mut self, rng: &mut RngType) {
+        let meshes: Vec<Vec<usize>> = self
+            .get_paths()
+            .into_iter()
+            .filter(|path| get_attr(self.get(path), "filename").is_some())
+            .collect();
+        let path = match meshes.choose(rng) {
+            Some(path) => path.clone(),
+            None => return self.mutate_attr(rng),
+        };
+        let element = self.get_mut(&path);
+        let filename = get_attr(element, "filename").unwrap();
+        let filename = match rng.gen_range(0..3) {
+            0 => MESH_PATHS.choose(rng).unwrap().to_string(),
+            1 => {
+                let ext = *[".stl", ".dae", ".obj", ".STL", ""].choose(rng).unwrap();
+                match filename.rsplit_once('.') {
+                    Some((stem, _)) => format!("{}{}", stem, ext),
+                    None => format!("{}{}", filename, ext),
+                }
+            }
+            _ => format!("{}{}", filename, "/x".repeat(rng.gen_range(1..2048))),
+        };
+        set_attr(element, "filename", &filename);
+    }
+}
+
+fn emit_element(element: &XmlElement, res: &mut String) {
+    res.push('<');
+    res.push_str(&element.name);
+    for (name, value) in element.attrs.iter() {
+        let quote = match value.contains('"') {
+            true => '\'',
+            false => '"',
+        };
+        res.push(' ');
+        res.push_str(name);
+        res.push('=');
+        res.push(quote);
+        res.push_str(value);
+        res.push(quote);
+    }
+    if element.children.is_empty() {
+        res.push_str("/>");
+        return;
+    }
+    res.push('>');
+    for child in element.children.iter() {
+        match child {
+            XmlNode::Element(child) => emit_element(child, res),
+            XmlNode::Text(text) | XmlNode::Raw(text) => res.push_str(text),
+        }
+    }
+    res.push_str("</");
+    res.push_str(&element.name);
+    res.push('>');
+}
+
+fn collect_paths(element: &XmlElement, prefix: &mut Vec<usize>, paths: &mut Vec<Vec<usize>>) {
+    for (idx, child) in element.children.iter().enumerate() {
+        if let XmlNode::Element(child) = child {
+            prefix.push(idx);
+            paths.push(prefix.clone());
+            collect_paths(child, prefix, paths);
+            prefix.pop();
+        }
+    }
+}
+
+fn get_attr(element: &XmlElement, name: &str) -> Option<String> {
+    element
+        .attrs
+        .iter()
+        .find(|(attr, _)| attr == name)
+        .map(|(_, value)| value.clone())
+}
+
+fn set_attr(element: &mut XmlElement, name: &str, value: &str) {
+    match element.attrs.iter_mut().find(|(attr, _)| attr == name) {
+        Some(attr) => attr.1 = value.to_string(),
+        None => element.attrs.push((name.to_string(), value.to_string())),
+    }
+}
+
+// `<parent link="base_link"/>` of a joint
+fn joint_link(joint: &XmlElement, side: &str) -> Option<String> {
+    joint.children.iter().find_map(|child| match child {
+        XmlNode::Element(child) if child.name == side => get_attr(child, "link"),
+        _ => None,
+    })
+}
+
+fn set_joint_link(joint: &mut XmlElement, side: &str, link: &str) {
+    for child in joint.children.iter_mut() {
+        if let XmlNode::Element(child) = child {
+            if child.name == side {
+                set_attr(child, "link", link);
+            }
+        }
+    }
+}
+
+// break the document itself now and then, parsers have to reject it cleanly
+fn malform(doc: &mut String, rng: &mut RngType) {
+    let mut pos = rng.gen_range(0..=doc.len());
+    while !doc.is_char_boundary(pos) {
+        pos -= 1;
+    }
+    match rng.gen_range(0..4) {
+        0 => doc.truncate(pos),
+        1 => doc.insert(pos, *['<', '>', '&', '"'].choose(rng).unwrap()),
+        2 => {
+            if let Some(end) = doc.rfind("</") {
+                doc.truncate(end);
+            }
+        }
+        _ => {
+            // well-formed, but deeper than a recursive parser may go
+            let depth = rng.gen_range(1000..10000);
+            if let Some(end) = doc.rfind("</") {
+                let nested = format!("{}{}", "<a>".repeat(depth), "</a>".repeat(depth));
+                doc.insert_str(end, &nested);
+            }
+        }
+    }
+}
+
+/// A mutated copy of doc, byte-level when doc does not parse.
+pub fn mutate_document(doc: &str, rng: &mut RngType) -> String {
+    let mut res = match XmlDoc::parse(doc) {
+        Ok(mut parsed) => {
+            parsed.mutate(rng);
+            parsed.emit()
+        }
+        Err(_) => doc.to_string(),
+    };
+    if rng.gen_ratio(MALFORMED_RATIO.0, MALFORMED_RATIO.1) || res == doc {
+        malform(&mut res, rng);
+    }
+    res
+}
+
+/// An XML parameter value read after boot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlSeed {
+    pub node_name: String,
+    pub name: String,
+    pub value: String,
+}
+
+// string parameters of the nodes that hold a document, one `ros2 param get` each
+pub fn read_seeds(nodes: &[Node], shm_dir: &str) -> Vec<XmlSeed> {
+    let mut seeds = Vec::new();
+    for node in nodes.iter() {
+        for (name, param) in node.get_param().iter() {
+            if param.itf_type != "string" || !XML_HINTS.iter().any(|hint| name.contains(hint)) {
+                continue;
+            }
+            let output = match ros2(shm_dir, &["param", "get", &node.node_name, name]) {
+                Ok(output) => output,
+                Err(_) => continue,
+            };
+            let value = match output.trim_end().strip_prefix("String value is: ") {
+                Some(value) => value,
+                None => continue,
+            };
+            if XmlDoc::parse(value).is_ok() {
+                seeds.push(XmlSeed {
+                    node_name: node.node_name.clone(),
+                    name: name.to_string(),
+                    value: value.to_string(),
+                });
+            }
+        }
+    }
+    seeds
+}
+
+// the seed of a parameter, or of a topic named like one, e.g. /robot_description
+pub fn get_seed<'a>(seeds: &'a [XmlSeed], node_name: &str, name: &str) -> Option<&'a XmlSeed> {
+    let short = name.rsplit('/').next().unwrap_or(name);
+    seeds
+        .iter()
+        .find(|seed| seed.node_name == node_name && seed.name == name)
+        .or_else(|| seeds.iter().find(|seed| seed.name == short))
+}
+
+// a string parameter holds the document itself, std_msgs/String in `data`
+pub fn set_document(itf: &mut InterfaceVal, doc: String) {
+    match itf.val.first_mut() {
+        Some(ValueType::Op6(string)) => string.set_val(doc),
+        _ => set_string(itf, "data", doc),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    const URDF: &str = r#"<?xml version="1.0"?>
+<!-- two links -->
+<robot name="bot">
+  <link name="base_link"><visual><geometry><mesh filename="package://bot/base.stl" scale="1 1 1"/></geometry></visual></link>
+  <link name="arm"/>
+  <joint name="arm_joint" type="revolute">
+    <parent link="base_link"/><child link="arm"/>
+    <origin xyz="0 0 0.1" rpy="0 0 0"/><limit lower="-1.57" upper="1.57" effort="10" velocity="1"/>
+  </joint>
+</robot>
+"#;
+
+    #[test]
+    fn documents_round_trip_and_mostly_stay_well_formed() {
+        let doc = XmlDoc::parse(URDF).unwrap();
+        assert_eq!(doc.emit(), URDF);
+        assert_eq!(doc.get_link_names(), vec!["base_link", "arm"]);
+        assert!(XmlDoc::parse("<robot><link></robot>").is_err());
+
+        let mut rng = RngType::seed_from_u64(0);
+        let mut well_formed = 0;
+        for _ in 0..64 {
+            let mutated = mutate_document(URDF, &mut rng);
+            assert_ne!(mutated, URDF);
+            if XmlDoc::parse(&mutated).is_ok() {
+                well_formed += 1;
+            }
+        }
+        assert!(well_formed > 48 && well_formed < 64);
+    }
+}
@@ -0,0 +1,578 @@
+//! YAML flow serialization of generated interface values
+//!
+//! The ros2 cli parses message payloads with `yaml.safe_load`, so every value is emitted in
+//! flow syntax with double-quoted, fully escaped strings. The output only contains printable
+//! ASCII and never a single quote, which keeps it safe to pass around as a single argument.
+
+use super::{
+    interface::{InterfaceVal, ValueType},
+    ty::TYPE,
+};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum YamlNode {
+    Map(Vec<(String, YamlNode)>),
+    Seq(Vec<YamlNode>),
+    // double-quoted string
+    Str(String),
+    // `!!binary` tagged bytes, used for ros `byte` fields
+    Binary(Vec<u8>),
+    // plain scalar: integers, floats and booleans
+    Scalar(String),
+    // local tag, e.g. `!float64 1.0`, only in the text form of programs
+    Tagged(String, Box<YamlNode>),
+}
+
+impl YamlNode {
+    pub fn emit(&self) -> String {
+        let mut res = String::new();
+        self.emit_into(&mut res);
+        res
+    }
+
+    fn emit_into(&self, res: &mut String) {
+        match self {
+            YamlNode::Map(fields) => {
+                res.push('{');
+                for (idx, (key, val)) in fields.iter().enumerate() {
+                    if idx != 0 {
+                        res.push_str(", ");
+                    }
+                    res.push_str(key);
+                    res.push_str(": ");
+                    val.emit_into(res);
+                }
+                res.push('}');
+            }
+            YamlNode::Seq(items) => {
+                res.push('[');
+                for (idx, item) in items.iter().enumerate() {
+                    if idx != 0 {
+                        res.push_str(", ");
+                    }
+                    item.emit_into(res);
+                }
+                res.push(']');
+            }
+            YamlNode::Str(val) => escape_str(val, res),
+            YamlNode::Binary(val) => {
+                res.push_str("!!binary \"");
+                res.push_str(&base64_encode(val));
+                res.push('"');
+            }
+            YamlNode::Scalar(val) => res.push_str(val),
+            YamlNode::Tagged(tag, val) => {
+                res.push('!');
+                res.push_str(tag);
+                res.push(' ');
+                val.emit_into(res);
+            }
+        }
+    }
+
+    // parse the flow subset produced by `emit`
+    pub fn parse(input: &str) -> Result<YamlNode, failure::Error> {
+        let chars: Vec<char> = input.chars().collect();
+        let mut pos = 0;
+        let node = parse_node(&chars, &mut pos)?;
+        skip_ws(&chars, &mut pos);
+        if pos != chars.len() {
+            return Err(failure::format_err!("trailing characters at {}", pos));
+        }
+        Ok(node)
+    }
+}
+
+impl InterfaceVal {
+    // the message fields as a yaml mapping, arrays of messages are stored as
+    // repeated siblings with the same name and are folded back into a sequence
+    pub fn to_yaml_node(&self) -> YamlNode {
+        if let Some(leaf) = self.leaf_yaml_node() {
+            return leaf;
+        }
+        let mut fields: Vec<(String, YamlNode)> = Vec::new();
+        for val in self.val.iter() {
+            let itf = match val {
+                ValueType::Op(itf) => itf,
+                _ => continue,
+            };
+            let node = itf.to_yaml_node();
+            if itf.is_msg_array() {
+                match fields.last_mut() {
+                    Some((name, YamlNode::Seq(items))) if *name == itf.itf_name => {
+                        items.push(node);
+                    }
+                    _ => fields.push((itf.itf_name.clone(), YamlNode::Seq(vec![node]))),
+                }
+            } else {
+                fields.push((itf.itf_name.clone(), node));
+            }
+        }
+        YamlNode::Map(fields)
+    }
+
+    pub fn to_yaml(&self) -> String {
+        self.to_yaml_node().emit()
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.val.len() == 1 && !matches!(self.val[0], ValueType::Op(_))
+    }
+
+    pub fn is_msg_array(&self) -> bool {
+        self.itf_type.contains('[') && !self.is_leaf()
+    }
+
+    // the value of a primitive field, or None for a message
+    pub fn leaf_yaml_node(&self) -> Option<YamlNode> {
+        if !self.is_leaf() {
+            return None;
+        }
+        let node = match &self.val[0] {
+            ValueType::Op(_) => unreachable!(),
+            ValueType::Op1(int) => YamlNode::Scalar(int.get_val()),
+            ValueType::Op2(bool) => YamlNode::Scalar(bool.get_val()),
+            ValueType::Op3(double) => {
+                YamlNode::Scalar(float_scalar(double.get_num(), double.get_pad()))
+            }
+            ValueType::Op4(chara) => {
+                if TYPE::from_str(self.itf_type.as_str()) == TYPE::Byte {
+                    YamlNode::Binary(vec![chara.get_char() as u8])
+                } else {
+                    YamlNode::Str(chara.get_val())
+                }
+            }
+            ValueType::Op5(array) if !array.get_bytes().is_empty() => {
+                let items = array
+                    .get_bytes()
+                    .iter()
+                    .map(|byte| match array.get_inner_type() {
+                        TYPE::Byte => YamlNode::Binary(vec![*byte]),
+                        TYPE::Char => YamlNode::Str((*byte as char).to_string()),
+                        TYPE::Int8 => YamlNode::Scalar((*byte as i8).to_string()),
+                        _ => YamlNode::Scalar(byte.to_string()),
+                    })
+                    .collect();
+                YamlNode::Seq(items)
+            }
+            ValueType::Op5(array) => {
+                let items = match array.get_inner_type() {
+                    TYPE::String | TYPE::WString => array
+                        .get_string_array()
+                        .iter()
+                        .map(|s| YamlNode::Str(s.get_val()))
+                        .collect(),
+                    TYPE::Byte => array
+                        .get_char_array()
+                        .iter()
+                        .map(|c| YamlNode::Binary(vec![c.get_char() as u8]))
+                        .collect(),
+                    TYPE::Char => array
+                        .get_char_array()
+                        .iter()
+                        .map(|c| YamlNode::Str(c.get_val()))
+                        .collect(),
+                    TYPE::Float32 | TYPE::Float64 => array
+                        .get_float_array()
+                        .iter()
+                        .map(|f| YamlNode::Scalar(float_scalar(f.get_num(), f.get_pad())))
+                        .collect(),
+                    TYPE::Bool => array
+                        .get_bool_array()
+                        .iter()
+                        .map(|b| YamlNode::Scalar(b.get_val()))
+                        .collect(),
+                    _ => array
+                        .get_int_array()
+                        .iter()
+                        .map(|i| YamlNode::Scalar(i.get_val()))
+                        .collect(),
+                };
+                YamlNode::Seq(items)
+            }
+            ValueType::Op6(string) => YamlNode::Str(string.get_val()),
+        };
+        Some(node)
+    }
+}
+
+// floats always carry a dot and a signed exponent, otherwise yaml 1.1 reads them as int or str
+pub fn float_scalar(val: f64, pad: i32) -> String {
+    if val.is_nan() {
+        return ".nan".to_string();
+    }
+    if val.is_infinite() {
+        return if val > 0.0 { ".inf" } else { "-.inf" }.to_string();
+    }
+    let repr = match pad {
+        32 => format!("{:?}", val as f32),
+        _ => format!("{:?}", val),
+    };
+    match repr.split_once('e') {
+        Some((mantissa, exp)) => {
+            let mantissa = match mantissa.contains('.') {
+                true => mantissa.to_string(),
+                false => mantissa.to_string() + ".0",
+            };
+            let exp = match exp.starts_with('-') {
+                true => exp.to_string(),
+                false => "+".to_string() + exp,
+            };
+            format!("{}e{}", mantissa, exp)
+        }
+        None if !repr.contains('.') => repr + ".0",
+        None => repr,
+    }
+}
+
+fn escape_str(val: &str, res: &mut String) {
+    res.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            // single quotes are escaped as well so the payload never needs shell quoting
+            '\'' => res.push_str("\\x27"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\r' => res.push_str("\\r"),
+            '\0' => res.push_str("\\0"),
+            ' '..='~' => res.push(c),
+            c if (c as u32) < 0x100 => write!(res, "\\x{:02x}", c as u32).unwrap(),
+            c if (c as u32) < 0x10000 => write!(res, "\\u{:04x}", c as u32).unwrap(),
+            c => write!(res, "\\U{:08x}", c as u32).unwrap(),
+        }
+    }
+    res.push('"');
+}
+
+const BASE64_TABLE: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut res = String::new();
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_TABLE[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+fn base64_decode(data: &str) -> Result<Vec<u8>, failure::Error> {
+    let mut res = Vec::new();
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for c in data.bytes().filter(|c| *c != b'=') {
+        let v = match BASE64_TABLE.iter().position(|t| *t == c) {
+            Some(v) => v as u32,
+            None => return Err(failure::format_err!("invalid base64 character {}", c)),
+        };
+        buf = buf << 6 | v;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            res.push((buf >> bits) as u8);
+            buf &= (1 << bits) - 1;
+        }
+    }
+    Ok(res)
+}
+
+pub(crate) fn skip_ws(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && chars[*pos].is_whitespace() {
+        *pos += 1;
+    }
+}
+
+fn expect(chars: &[char], pos: &mut usize, c: char) -> Result<(), failure::Error> {
+    skip_ws(chars, pos);
+    if *pos < chars.len() && chars[*pos] == c {
+        *pos += 1;
+        Ok(())
+    } else {
+        Err(failure::format_err!("expected '{}' at {}", c, pos))
+    }
+}
+
+pub(crate) fn parse_node(chars: &[char], pos: &mut usize) -> Result<YamlNode, failure::Error> {
+    skip_ws(chars, pos);
+    match chars.get(*pos) {
+        None => Err(failure::err_msg("unexpected end of input")),
+        Some('{') => {
+            *pos += 1;
+            let mut fields = Vec::new();
+            skip_ws(chars, pos);
+            if chars.get(*pos) == Some(&'}') {
+                *pos += 1;
+                return Ok(YamlNode::Map(fields));
+            }
+            loop {
+                skip_ws(chars, pos);
+                let start = *pos;
+                while *pos < chars.len() && chars[*pos] != ':' {
+                    *pos += 1;
+                }
+                let key: String = chars[start..*pos].iter().collect();
+                expect(chars, pos, ':')?;
+                let val = parse_node(chars, pos)?;
+                fields.push((key.trim().to_string(), val));
+                skip_ws(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some('}') => {
+                        *pos += 1;
+                        return Ok(YamlNode::Map(fields));
+                    }
+                    _ => return Err(failure::format_err!("unterminated mapping at {}", pos)),
+                }
+            }
+        }
+        Some('[') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            skip_ws(chars, pos);
+            if chars.get(*pos) == Some(&']') {
+                *pos += 1;
+                return Ok(YamlNode::Seq(items));
+            }
+            loop {
+                items.push(parse_node(chars, pos)?);
+                skip_ws(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some(']') => {
+                        *pos += 1;
+                        return Ok(YamlNode::Seq(items));
+                    }
+                    _ => return Err(failure::format_err!("unterminated sequence at {}", pos)),
+                }
+            }
+        }
+        Some('"') => Ok(YamlNode::Str(parse_quoted(chars, pos)?)),
+        Some('!') => {
+            let start = *pos + 1;
+            while *pos < chars.len() && !chars[*pos].is_whitespace() {
+                *pos += 1;
+            }
+            let tag: String = chars[start..*pos].iter().collect();
+            match tag.as_str() {
+                "!binary" => {
+                    skip_ws(chars, pos);
+                    Ok(YamlNode::Binary(base64_decode(&parse_quoted(chars, pos)?)?))
+                }
+                "" => Err(failure::format_err!("empty tag at {}", start)),
+                _ => Ok(YamlNode::Tagged(tag, Box::new(parse_node(chars, pos)?))),
+            }
+        }
+        Some(_) => {
+            let start = *pos;
+            while *pos < chars.len() && !matches!(chars[*pos], ',' | '}' | ']') {
+                *pos += 1;
+            }
+            let val: String = chars[start..*pos].iter().collect();
+            Ok(YamlNode::Scalar(val.trim().to_string()))
+        }
+    }
+}
+
+fn parse_quoted(chars: &[char], pos: &mut usize) -> Result<String, failure::Error> {
+    expect(chars, pos, '"')?;
+    let mut res = String::new();
+    loop {
+        let c = match chars.get(*pos) {
+            Some(c) => *c,
+            None => return Err(failure::err_msg("unterminated string")),
+        };
+        *pos += 1;
+        match c {
+            '"' => return Ok(res),
+            '\\' => {
+                let e = match chars.get(*pos) {
+                    Some(e) => *e,
+                    None => return Err(failure::err_msg("unterminated escape")),
+                };
+                *pos += 1;
+                let width = match e {
+                    'x' => 2,
+                    'u' => 4,
+                    'U' => 8,
+                    _ => 0,
+                };
+                if width != 0 {
+                    let hex: String = chars[*pos..].iter().take(width).collect();
+                    *pos += width;
+                    let code = u32::from_str_radix(&hex, 16)?;
+                    match char::from_u32(code) {
+                        Some(c) => res.push(c),
+                        None => return Err(failure::format_err!("invalid code point {}", code)),
+                    }
+                    continue;
+                }
+                res.push(match e {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    other => other,
+                });
+            }
+            c => res.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus_handle::ty::{array::ArrayType, character, double, integer};
+
+    fn field(name: &str, ty: &str, val: ValueType) -> ValueType {
+        let mut itf = InterfaceVal::new(&name.to_string(), &ty.to_string());
+        itf.val.push(val);
+        ValueType::Op(itf)
+    }
+
+    fn string(val: &str) -> ValueType {
+        ValueType::Op6(character::StringType::new(
+            TYPE::String as usize,
+            val.to_string(),
+            val.len() as i32,
+        ))
+    }
+
+    fn point(x: f64) -> InterfaceVal {
+        let mut itf = InterfaceVal::new(&"points".to_string(), &"Point[]".to_string());
+        for name in ["x", "y", "z"] {
+            itf.val.push(field(
+                name,
+                "float64",
+                ValueType::Op3(double::DoubleType::new(
+                    TYPE::Float64 as usize,
+                    x,
+                    f64::MAX,
+                    f64::MIN,
+                    64,
+                )),
+            ));
+        }
+        itf
+    }
+
+    #[test]
+    fn escaped_strings_round_trip() {
+        let samples = [
+            "plain",
+            "quote \" and 'single'",
+            "back\\slash $HOME `id`",
+            "new\nline\ttab\r\0nul",
+            "\u{7f}\u{85}\u{a0}é",
+            "\u{2028}中文\u{fffd}",
+            "emoji \u{1f916} \u{10ffff}",
+            "{a: [1, 2]}, # not a comment",
+        ];
+        for sample in samples.iter() {
+            let node = YamlNode::Str(sample.to_string());
+            let out = node.emit();
+            assert!(out.chars().all(|c| (' '..='~').contains(&c)), "{}", out);
+            assert!(!out.contains('\''));
+            assert_eq!(YamlNode::parse(&out).unwrap(), node);
+        }
+    }
+
+    #[test]
+    fn floats_are_yaml_floats() {
+        assert_eq!(float_scalar(1.0, 64), "1.0");
+        assert_eq!(float_scalar(0.5, 32), "0.5");
+        assert_eq!(float_scalar(1e300, 64), "1.0e+300");
+        assert_eq!(float_scalar(-2.5e-7, 64), "-2.5e-7");
+        assert_eq!(float_scalar(f64::NAN, 64), ".nan");
+        assert_eq!(float_scalar(f64::NEG_INFINITY, 64), "-.inf");
+    }
+
+    #[test]
+    fn message_tree_round_trip() {
+        let mut msg = InterfaceVal::new(&"/topic".to_string(), &"Polygon".to_string());
+        msg.val.push(field("label", "string", string("a\"b'c\u{1f916}")));
+        msg.val.push(field("title", "wstring", string("wide ünïcode")));
+        msg.val.push(field(
+            "count",
+            "int8",
+            ValueType::Op1(integer::IntType::new(TYPE::Int8 as usize, -3i64 as u64, 0, 0, -8)),
+        ));
+        msg.val.push(field(
+            "flag",
+            "bool",
+            ValueType::Op2(integer::BoolType::new(TYPE::Bool as usize, 1, 1, 0)),
+        ));
+        msg.val.push(field(
+            "raw",
+            "byte",
+            ValueType::Op4(character::CharType::new(
+                TYPE::Char as usize,
+                '\u{ff}',
+                '\u{ff}',
+                '\0',
+            )),
+        ));
+        let mut bytes = ArrayType::new(TYPE::ARRAY as usize, TYPE::Byte, 3);
+        bytes.gen_array().unwrap();
+        msg.val.push(field("data", "byte[]", ValueType::Op5(bytes)));
+        let empty = InterfaceVal::new(&"header".to_string(), &"std_msgs/Empty".to_string());
+        msg.val.push(ValueType::Op(empty));
+        msg.val.push(ValueType::Op(point(1.0)));
+        msg.val.push(ValueType::Op(point(2.5e-9)));
+
+        let node = msg.to_yaml_node();
+        let out = msg.to_yaml();
+        assert_eq!(YamlNode::parse(&out).unwrap(), node);
+
+        let fields = match &node {
+            YamlNode::Map(fields) => fields,
+            _ => panic!("message is not a mapping"),
+        };
+        let names: Vec<&str> = fields.iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["label", "title", "count", "flag", "raw", "data", "header", "points"]
+        );
+        assert_eq!(fields[2].1, YamlNode::Scalar("-3".to_string()));
+        assert_eq!(fields[3].1, YamlNode::Scalar("true".to_string()));
+        assert_eq!(fields[4].1, YamlNode::Binary(vec![0xff]));
+        match &fields[5].1 {
+            YamlNode::Seq(items) => {
+                assert_eq!(items.len(), 3);
+                assert!(items.iter().all(|i| matches!(i, YamlNode::Binary(b) if b.len() == 1)));
+            }
+            _ => panic!("byte array is not a sequence"),
+        }
+        assert_eq!(fields[6].1, YamlNode::Map(Vec::new()));
+        match &fields[7].1 {
+            YamlNode::Seq(items) => {
+                assert_eq!(items.len(), 2);
+                assert_eq!(items[1].emit(), "{x: 2.5e-9, y: 2.5e-9, z: 2.5e-9}");
+            }
+            _ => panic!("message array is not a sequence"),
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        for len in 0..8 {
+            let data: Vec<u8> = (0..len).map(|i| (i * 37 + 250) as u8).collect();
+            assert_eq!(base64_decode(&base64_encode(&data)).unwrap(), data);
+        }
+        assert_eq!(base64_encode(b"\x01"), "AQ==");
+    }
+}
@@ -114,6 +114,8 @@ impl CallbackInfo {
             3 => self.cb_type = CallbackType::Service,
             4 => self.cb_type = CallbackType::Client,
             5 => self.cb_type = CallbackType::Timer,
+            6 => self.cb_type = CallbackType::ActionServer,
+            7 => self.cb_type = CallbackType::ActionClient,
             _ => panic!("callback type unrecognized"),
         }
     }
@@ -140,6 +142,16 @@ impl CallbackInfo {
         self.cb_name = name;
     }
 
+    // action entities are named <action>/_action/<service or topic>
+    pub fn get_action_name(&self) -> Option<&str> {
+        for name in [&self.cb_name, &self.itf_name] {
+            if let Some(idx) = name.find("/_action/") {
+                return Some(&name[..idx]);
+            }
+        }
+        None
+    }
+
     pub fn get_node_handle(&self) -> u64 {
         self.node_handle
     }
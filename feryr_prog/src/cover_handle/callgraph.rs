@@ -1,13 +1,21 @@
-use crate::{corpus_handle::models::OnnxModel, get_name_full, RE};
+use crate::{
+    corpus_handle::{
+        clock::{ClockFiring, ClockStream, ClockTick},
+        models::OnnxModel,
+    },
+    get_name_full, RE,
+};
 use crate::{cover_handle::models::Cover, get_new_cover};
 use super::{
     super::{get_name_short, string_hasher, EventType, ExecError, CHECK_LEN},
     callback::*,
+    CallbackType,
     event_trace::CallTrace,
     node::*,
     timer_trace::TimerTrace,
     topic_trace::TopicTrace,
 };
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fs::OpenOptions,
@@ -17,6 +25,16 @@ use std::{
 };
 use util::shmem::*;
 
+// two callback executions that were running at the same time, callbacks are identified by
+// their id when known and by the raw handle otherwise
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CbOverlap {
+    pub first: u64,
+    pub second: u64,
+    pub start: u64,
+    pub duration: u64,
+}
+
 #[derive(Debug)]
 pub struct CallGraph {
     pub nodes: HashMap<u64, NodeInfo>,
@@ -435,6 +453,139 @@ impl CallGraph {
         Ok(())
     }
 
+    // callbacks of the entities behind action_name that ran in the current trace
+    pub fn get_action_callbacks(&self, action_name: &str) -> Vec<u64> {
+        let action_name = action_name.trim_end_matches('/');
+        let mut cb_ids = Vec::new();
+        for time in self.current_times.iter() {
+            let cb_id = match self.get_callback_id_via_time(time) {
+                Some(cb_id) => cb_id,
+                None => continue,
+            };
+            let is_action = match self.callbacks.get(&cb_id) {
+                Some(callback) => callback.get_action_name() == Some(action_name),
+                None => false,
+            };
+            if is_action && !cb_ids.contains(&cb_id) {
+                cb_ids.push(cb_id);
+            }
+        }
+        cb_ids
+    }
+
+    // subscriber callbacks of topic that ran in the current trace
+    pub fn get_topic_consumers(&self, topic: &str) -> Vec<u64> {
+        let mut cb_ids = Vec::new();
+        for time in self.current_times.iter() {
+            let cb_id = match self.get_callback_id_via_time(time) {
+                Some(cb_id) => cb_id,
+                None => continue,
+            };
+            let consumer = match self.callbacks.get(&cb_id) {
+                Some(callback) => {
+                    callback.cb_type == CallbackType::Subscriber
+                        && callback.cb_name.trim_end_matches('/') == topic.trim_end_matches('/')
+                }
+                None => false,
+            };
+            if consumer && !cb_ids.contains(&cb_id) {
+                cb_ids.push(cb_id);
+            }
+        }
+        cb_ids
+    }
+
+    // longest run of each of cb_ids that started after since in the current trace, in ns
+    pub fn get_callback_latency(&self, cb_ids: &[u64], since: u64) -> Vec<(u64, u64)> {
+        let mut latency = Vec::new();
+        for cb_id in cb_ids.iter() {
+            let callback = match self.current_event_trace.trace.get(cb_id) {
+                Some(callback) => callback,
+                None => continue,
+            };
+            let longest = callback
+                .start_time
+                .iter()
+                .filter(|&&start| start >= since)
+                .filter_map(|&start| {
+                    let end = callback.end_time.iter().find(|&&end| end >= start)?;
+                    Some(end - start)
+                })
+                .max();
+            if let Some(longest) = longest {
+                latency.push((*cb_id, longest));
+            }
+        }
+        latency
+    }
+
+    // timer starts of the current trace matched with the clock stream the call published
+    pub fn get_clock_firings(&self, stream: &ClockStream, ticks: &[ClockTick]) -> Vec<ClockFiring> {
+        let mut firings = Vec::new();
+        for (cb_id, trace) in self.current_timer_trace.iter() {
+            let is_timer = match self.callbacks.get(cb_id) {
+                Some(callback) => callback.cb_type == CallbackType::Timer,
+                None => false,
+            };
+            if is_timer {
+                firings.extend(trace.correlate_clock(*cb_id, stream, ticks));
+            }
+        }
+        firings.sort_by_key(|firing| firing.wall_ns);
+        firings
+    }
+
+    // client callbacks that handled a service or action response in the current trace
+    pub fn get_client_responses(&self) -> Vec<u64> {
+        let mut cb_ids = Vec::new();
+        for time in self.current_times.iter() {
+            if EventType::from(time.flag) != Some(EventType::CliRsp) {
+                continue;
+            }
+            if let Some(cb_id) = self.get_callback_id_via_time(time) {
+                if !cb_ids.contains(&cb_id) {
+                    cb_ids.push(cb_id);
+                }
+            }
+        }
+        cb_ids
+    }
+
+    // pair CbStart/CbEnd events after start_time into executions and report the overlapping
+    // ones, the shm region has to be loaded already
+    pub fn get_cb_overlaps(&self, shmem_region: &SharedMem, start_time: u128) -> Vec<CbOverlap> {
+        let mut times: Vec<cb_times> = shmem_region
+            .get_shm_cb_time()
+            .iter()
+            .filter(|time| time.cb != 0 && (time.time as u128) >= start_time)
+            .cloned()
+            .collect();
+        times.sort_by(|a, b| a.time.cmp(&b.time));
+
+        let mut running: HashMap<u64, Vec<u64>> = HashMap::new();
+        let mut intervals = Vec::new();
+        for time in times.iter() {
+            let cb = self.get_callback_id_via_time(time).unwrap_or(time.cb);
+            match EventType::from(time.flag) {
+                Some(EventType::CbStart) => running.entry(cb).or_default().push(time.time),
+                Some(EventType::CbEnd) => {
+                    if let Some(start) = running.get_mut(&cb).and_then(|starts| starts.pop()) {
+                        intervals.push((cb, start, time.time));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // callbacks that never returned are still running at the last event
+        let last = times.last().map(|time| time.time).unwrap_or(0);
+        for (cb, starts) in running {
+            for start in starts {
+                intervals.push((cb, start, last));
+            }
+        }
+        find_overlaps(intervals)
+    }
+
     pub fn event_monitor(
         &mut self,
         trace_model: &mut Arc<Mutex<OnnxModel>>,
@@ -903,3 +1054,28 @@ impl CallGraph {
         self.nodes.contains_key(id)
     }
 }
+
+// sweep (callback, start, end) executions ordered by start, each pair of callbacks is reported
+// once with its first overlap
+pub fn find_overlaps(mut intervals: Vec<(u64, u64, u64)>) -> Vec<CbOverlap> {
+    intervals.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut seen = HashSet::new();
+    let mut active: Vec<(u64, u64, u64)> = Vec::new();
+    let mut overlaps = Vec::new();
+    for (cb, start, end) in intervals {
+        active.retain(|running| running.2 > start);
+        for running in active.iter() {
+            let pair = (running.0.min(cb), running.0.max(cb));
+            if seen.insert(pair) {
+                overlaps.push(CbOverlap {
+                    first: running.0,
+                    second: cb,
+                    start,
+                    duration: running.2.min(end) - start,
+                });
+            }
+        }
+        active.push((cb, start, end));
+    }
+    overlaps
+}
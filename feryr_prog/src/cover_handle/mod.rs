@@ -17,6 +17,9 @@ pub enum CallbackType {
     Service,
     Client,
     Timer,
+    // goal, cancel and result services and feedback/status topics of an action
+    ActionServer,
+    ActionClient,
     Other,
 }
 impl Default for CallbackType {
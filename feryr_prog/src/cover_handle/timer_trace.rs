@@ -1,3 +1,4 @@
+use crate::corpus_handle::clock::{get_tick_at, ClockFiring, ClockStream, ClockTick};
 use serde::Serialize;
 use std::collections::{HashMap, VecDeque};
 
@@ -40,6 +41,38 @@ impl TimerTrace {
         }
     }
 
+    // the simulated time each start of the timer saw while the stream played, starts before the
+    // first tick or after the stream are left to the baseline clock
+    pub fn correlate_clock(
+        &self,
+        cb_id: u64,
+        stream: &ClockStream,
+        ticks: &[ClockTick],
+    ) -> Vec<ClockFiring> {
+        let end = match ticks.last() {
+            Some(last) => last.wall_ns + stream.period_ms * 1_000_000,
+            None => return Vec::new(),
+        };
+        let mut firings: Vec<ClockFiring> = Vec::new();
+        for &start in self.start_vec.iter().filter(|&&start| start <= end) {
+            let (tick, phase) = match get_tick_at(stream, ticks, start) {
+                Some(tick) => tick,
+                None => continue,
+            };
+            let stale = firings
+                .last()
+                .is_some_and(|last| last.sim_ns >= tick.sim_ns);
+            firings.push(ClockFiring {
+                cb_id,
+                wall_ns: start,
+                sim_ns: tick.sim_ns,
+                phase,
+                stale,
+            });
+        }
+        firings
+    }
+
     pub fn mean(&mut self, data: &Vec<u64>) -> Option<f64> {
         let sum = data.iter().sum::<u64>() as f64;
         let count = data.len();
@@ -0,0 +1,541 @@
+#!/usr/bin/env python3
+# Persistent injection helper for the fuzzer, see corpus_handle/executor.rs.
+#
+# Reads one json encoded CallArgs per line on stdin and answers one line
+# {"output": str, "timeout": bool} on stdout. Publishers, clients and action
+# clients are created on first use and reused for the whole boot, so an input
+# costs one publish/call instead of a ros2 cli start-up and discovery round.
+# A non-empty "raw" field is published as a serialized message (see cdr.rs),
+# a "timing" field turns the publish into a stream (see timing.rs).
+# A {"batch": [{"offset_us": int, "call": CallArgs}]} line runs the calls in
+# threads, each started offset_us after the first, and is answered with
+# {"outputs": [...]} in the same order (see Sequence::exec_group).
+# An "action" field selects the action protocol request (see action.rs), the
+# output is worded like `ros2 action send_goal --feedback` so the fuzzer parses
+# both the same way.
+# An "Impostor" call stands up a service or action server on its own node
+# (see impostor.rs) that answers with the generated responses in turn.
+# An "impersonate" field in follow mode answers every message of the real
+# publisher with a diverged copy (see impersonate.rs).
+# A "qos" field sets the publisher profile (see qos.rs), QoS events of the
+# publisher are appended to the output as "QoS incompatible: <policy>",
+# "deadline missed" and "liveliness lost" lines.
+# A "params" field sets several parameters in one set_parameters_atomically
+# request, or in one set_parameters request like `ros2 param load` (see param.rs).
+# With FERYR_SIM_CLOCK set /clock is published at the wall clock rate from the start, a
+# "Clock" call plays its "clock" stream instead (see clock.rs) and answers one
+# "clock <wall_ns> <sim_ns> <event>" line per published time.
+import copy
+import itertools
+import json
+import os
+import random
+import sys
+import threading
+import time
+import traceback
+
+import rclpy
+import yaml
+from rclpy.action import ActionClient, ActionServer, CancelResponse, GoalResponse
+from rclpy.duration import Duration
+from rclpy.executors import MultiThreadedExecutor
+from rclpy.parameter import Parameter
+from rclpy.qos import (DurabilityPolicy, HistoryPolicy, LivelinessPolicy, QoSProfile, ReliabilityPolicy,
+                       qos_policy_name_from_kind)
+try:
+    from rclpy.event_handler import PublisherEventCallbacks
+except ImportError:
+    from rclpy.qos_event import PublisherEventCallbacks
+from action_msgs.srv import CancelGoal
+from rcl_interfaces.srv import SetParameters, SetParametersAtomically
+from rosgraph_msgs.msg import Clock
+from unique_identifier_msgs.msg import UUID
+from rosidl_runtime_py.set_message import set_message_fields
+from rosidl_runtime_py.utilities import get_action, get_message, get_service
+
+WAIT_TIMEOUT = 5.0
+MATCH_TIMEOUT = 1.0
+POLICIES = {
+    'SystemDefault': 'SYSTEM_DEFAULT', 'Reliable': 'RELIABLE', 'BestEffort': 'BEST_EFFORT',
+    'Volatile': 'VOLATILE', 'TransientLocal': 'TRANSIENT_LOCAL', 'KeepLast': 'KEEP_LAST',
+    'KeepAll': 'KEEP_ALL', 'Automatic': 'AUTOMATIC', 'ManualByTopic': 'MANUAL_BY_TOPIC',
+}
+CLOCK_PERIOD = 0.01
+# builtin_interfaces/Time has an int32 sec
+MAX_SIM_NS = (2 ** 31 - 1) * 1000000000
+GOAL_STATUS = ['UNKNOWN', 'ACCEPTED', 'EXECUTING', 'CANCELING', 'SUCCEEDED', 'CANCELED', 'ABORTED']
+
+
+class Injector:
+    def __init__(self):
+        rclpy.init()
+        self.node = rclpy.create_node('feryr_injector')
+        self.publishers = {}
+        self.clients = {}
+        self.action_clients = {}
+        # set while a batch spins the node from a background executor
+        self.spinning = False
+        self.lock = threading.Lock()
+        self.impostor_ids = itertools.count()
+        # simulated time, published by run_clock unless a stream drives it
+        self.clock_lock = threading.Lock()
+        self.clock_pub = None
+        self.sim_ns = time.time_ns()
+        self.clock_driven = False
+
+    def cached(self, table, key, create):
+        with self.lock:
+            if key not in table:
+                table[key] = create()
+            return table[key]
+
+    def spin_until(self, future, timeout):
+        if not self.spinning:
+            rclpy.spin_until_future_complete(self.node, future, timeout_sec=timeout)
+            return future.done()
+        deadline = time.monotonic() + timeout
+        while not future.done() and time.monotonic() < deadline:
+            time.sleep(0.001)
+        return future.done()
+
+    def spin_once(self, timeout):
+        if self.spinning:
+            time.sleep(timeout)
+        else:
+            rclpy.spin_once(self.node, timeout_sec=timeout)
+
+    def fill(self, msg_type, payload):
+        msg = msg_type()
+        values = yaml.safe_load(payload) if payload else {}
+        if values:
+            set_message_fields(msg, values)
+        return msg
+
+    def fill_known(self, msg, payload):
+        # payloads of impostors cover every part of the interface, keep the fields of msg
+        values = yaml.safe_load(payload) if payload else {}
+        fields = msg.get_fields_and_field_types()
+        values = {k: v for k, v in (values or {}).items() if k in fields}
+        try:
+            set_message_fields(msg, values)
+        except Exception:
+            pass
+        return msg
+
+    def qos_profile(self, qos):
+        if not qos:
+            return 10
+        profile = QoSProfile(
+            history=HistoryPolicy[POLICIES[qos['history']]],
+            depth=qos['depth'],
+            reliability=ReliabilityPolicy[POLICIES[qos['reliability']]],
+            durability=DurabilityPolicy[POLICIES[qos['durability']]],
+            liveliness=LivelinessPolicy[POLICIES[qos['liveliness']]])
+        if qos['deadline_ms']:
+            profile.deadline = Duration(nanoseconds=qos['deadline_ms'] * 1000000)
+        if qos['lifespan_ms']:
+            profile.lifespan = Duration(nanoseconds=qos['lifespan_ms'] * 1000000)
+        if qos['lease_ms']:
+            profile.liveliness_lease_duration = Duration(nanoseconds=qos['lease_ms'] * 1000000)
+        return profile
+
+    def topic(self, req):
+        qos = req.get('qos')
+
+        def create():
+            msg_type = get_message(req['itf_type'])
+            events = []
+            callbacks = PublisherEventCallbacks(
+                incompatible_qos=lambda e: events.append(
+                    'QoS incompatible: %s' % qos_policy_name_from_kind(e.last_policy_kind)),
+                deadline=lambda _: events.append('deadline missed'),
+                liveliness=lambda _: events.append('liveliness lost'))
+            pub = self.node.create_publisher(
+                msg_type, req['itf_name'], self.qos_profile(qos), event_callbacks=callbacks)
+            return msg_type, pub, events
+        key = (req['itf_name'], req['itf_type'], json.dumps(qos, sort_keys=True))
+        msg_type, pub, events = self.cached(self.publishers, key, create)
+        output, timeout = self.publish(req, msg_type, pub)
+        output += ''.join('%s\n' % event for event in events)
+        del events[:]
+        return output, timeout
+
+    def publish(self, req, msg_type, pub):
+        if req.get('raw'):
+            # pre-serialized cdr, possibly malformed on purpose
+            msg = bytes(req['raw'])
+        else:
+            msg = self.fill(msg_type, req['payload'])
+        # same as --once: give discovery a chance to match the subscriber
+        waited = 0.0
+        while pub.get_subscription_count() == 0 and waited < MATCH_TIMEOUT:
+            self.spin_once(0.1)
+            waited += 0.1
+        shown = msg.hex() if isinstance(msg, bytes) else msg
+        impersonate = req.get('impersonate')
+        if impersonate and impersonate['follow']:
+            return self.impersonate(req, msg_type, pub, impersonate)
+        timing = req.get('timing')
+        if not timing:
+            pub.publish(msg)
+            return 'publishing #1: %s\n' % shown, False
+        if timing['detach']:
+            threading.Thread(target=self.stream, args=(pub, msg, timing), daemon=True).start()
+            return 'streaming in background: %s\n' % shown, False
+        count = self.stream(pub, msg, timing)
+        return 'publishing #%d: %s\n' % (count, shown), False
+
+    def stream(self, pub, msg, timing):
+        ticks = max(1, int(timing['rate'] * timing['duration_ms'] / 1000.0))
+        gap = 1.0 / timing['rate']
+        deadline = time.monotonic()
+        count = 0
+        for _ in range(ticks):
+            for _ in range(timing['burst']):
+                pub.publish(msg)
+                count += 1
+            # schedule against a deadline so slow publishes do not lower the rate
+            deadline += gap + random.uniform(0, timing['jitter_us']) / 1e6
+            delay = deadline - time.monotonic()
+            if delay > 0:
+                time.sleep(delay)
+        return count
+
+    def impersonate(self, req, msg_type, pub, imp):
+        generated = yaml.safe_load(req['payload']) if req['payload'] else {}
+        fakes = []
+        done = threading.Event()
+
+        def on_real(real):
+            # our own fakes come back through the subscription too
+            if done.is_set() or any(real == fake for fake in fakes):
+                return
+            fake = copy.deepcopy(real)
+            fields = fake.get_fields_and_field_types()
+            values = {k: v for k, v in (generated or {}).items()
+                      if k in fields and random.random() < imp['divergence']}
+            try:
+                set_message_fields(fake, values)
+            except Exception:
+                pass
+            time.sleep(imp['lag_us'] / 1e6)
+            pub.publish(fake)
+            fakes.append(fake)
+            if len(fakes) >= imp['count']:
+                done.set()
+
+        sub = self.node.create_subscription(msg_type, req['itf_name'], on_real,
+                                            self.qos_profile(req.get('qos')))
+        deadline = time.monotonic() + imp['window_ms'] / 1000.0
+        while not done.is_set() and time.monotonic() < deadline:
+            self.spin_once(0.01)
+        self.node.destroy_subscription(sub)
+        return 'impersonating %s: publishing #%d\n' % (imp['publisher'], len(fakes)), False
+
+    def service(self, req):
+        def create():
+            srv_type = get_service(req['itf_type'])
+            return srv_type, self.node.create_client(srv_type, req['itf_name'])
+        srv_type, cli = self.cached(self.clients, (req['itf_name'], req['itf_type']), create)
+        if not cli.wait_for_service(timeout_sec=WAIT_TIMEOUT):
+            return 'Waiting for service to become available...\n', True
+        request = self.fill(srv_type.Request, req['payload'])
+        future = cli.call_async(request)
+        if not self.spin_until(future, WAIT_TIMEOUT):
+            return 'requester: making request: %s\n' % request, True
+        return 'requester: making request: %s\n\nresponse:\n%s\n' % (request, future.result()), False
+
+    def action(self, req):
+        def create():
+            act_type = get_action(req['itf_type'])
+            return act_type, ActionClient(self.node, act_type, req['itf_name'])
+        act_type, cli = self.cached(self.action_clients, (req['itf_name'], req['itf_type']), create)
+        call = req.get('action') or {'op': 'SendGoal', 'goal_id': None, 'target_id': [0] * 16,
+                                     'wait_result': True}
+        if call['op'] in ('Cancel', 'CancelAll'):
+            return self.cancel(req, call)
+        if call['op'] == 'GetResult':
+            return self.get_result(req, act_type, call['target_id'])
+        if not cli.wait_for_server(timeout_sec=WAIT_TIMEOUT):
+            return 'Waiting for an action server to become available...\n', True
+
+        goal = self.fill(act_type.Goal, req['payload'])
+        output = ['Sending goal:\n     %s\n' % goal]
+        feedback = lambda msg: output.append('Feedback:\n    %s\n' % msg.feedback)
+        goal_uuid = UUID(uuid=list(call['goal_id'])) if call['goal_id'] else None
+        future = cli.send_goal_async(goal, feedback_callback=feedback, goal_uuid=goal_uuid)
+        if not self.spin_until(future, WAIT_TIMEOUT):
+            return '\n'.join(output), True
+        handle = future.result()
+        if not handle.accepted:
+            output.append('Goal was rejected.\n')
+            return '\n'.join(output), False
+        output.append('Goal accepted with ID: %s\n' % bytes(handle.goal_id.uuid).hex())
+        if call['op'] == 'SendGoal' and not call['wait_result']:
+            return '\n'.join(output), False
+
+        result = handle.get_result_async()
+        if not self.spin_until(result, WAIT_TIMEOUT):
+            return '\n'.join(output), True
+        output.append('Result:\n    %s\n' % result.result().result)
+        output.append('Goal finished with status: %s\n' % GOAL_STATUS[result.result().status])
+        if call['op'] == 'Preempt':
+            # how the goal we preempted ended
+            preempted, _ = self.get_result(req, act_type, call['target_id'])
+            output.append('Preempted goal: %s\n' % preempted.strip().splitlines()[-1])
+        return '\n'.join(output), False
+
+    def action_service(self, req, service, srv_type):
+        name = req['itf_name'].rstrip('/') + '/_action/' + service
+        _, cli = self.cached(self.clients, name, lambda: (srv_type, self.node.create_client(srv_type, name)))
+        if not cli.wait_for_service(timeout_sec=WAIT_TIMEOUT):
+            return None
+        return cli
+
+    def cancel(self, req, call):
+        cli = self.action_service(req, 'cancel_goal', CancelGoal)
+        if cli is None:
+            return 'Waiting for service to become available...\n', True
+        request = CancelGoal.Request()
+        if call['op'] == 'Cancel':
+            request.goal_info.goal_id.uuid = list(call['target_id'])
+        future = cli.call_async(request)
+        if not self.spin_until(future, WAIT_TIMEOUT):
+            return 'requester: making request: %s\n' % request, True
+        return 'requester: making request: %s\n\nresponse:\n%s\n' % (request, future.result()), False
+
+    def get_result(self, req, act_type, target_id):
+        srv_type = act_type.Impl.GetResultService
+        cli = self.action_service(req, 'get_result', srv_type)
+        if cli is None:
+            return 'Waiting for service to become available...\n', True
+        request = srv_type.Request()
+        request.goal_id.uuid = list(target_id)
+        future = cli.call_async(request)
+        # an unknown or running goal never answers, that is not a hang of the target
+        if not self.spin_until(future, WAIT_TIMEOUT):
+            return 'Result of goal %s not available\n' % bytes(target_id).hex(), False
+        response = future.result()
+        return 'Result:\n    %s\n\nGoal finished with status: %s\n' % (
+            response.result, GOAL_STATUS[response.status]), False
+
+    def param(self, req):
+        update = req.get('params')
+        atomic = bool(update and update['atomic'])
+        srv_type = SetParametersAtomically if atomic else SetParameters
+        name = req['node_name'].rstrip('/') + ('/set_parameters_atomically' if atomic else '/set_parameters')
+        _, cli = self.cached(
+            self.clients, name, lambda: (srv_type, self.node.create_client(srv_type, name)))
+        if not cli.wait_for_service(timeout_sec=WAIT_TIMEOUT):
+            return 'Node not found\n', False
+        if update:
+            values = [(p['name'], p['value']) for p in update['params']]
+        else:
+            values = [(req['itf_name'], req['payload'])]
+        request = srv_type.Request()
+        request.parameters = [
+            Parameter(param, value=yaml.safe_load(value)).to_parameter_msg() for param, value in values]
+        future = cli.call_async(request)
+        if not self.spin_until(future, WAIT_TIMEOUT):
+            return '', True
+        if atomic:
+            res = future.result().result
+            if res.successful:
+                return 'Set parameters atomically successful\n', False
+            return 'Set parameters atomically failed: %s\n' % res.reason, False
+        if not update:
+            res = future.result().results[0]
+            if res.successful:
+                return 'Set parameter successful\n', False
+            return 'Set parameter failed: %s\n' % res.reason, False
+        # worded like `ros2 param load`
+        output = ''
+        for (param, _), res in zip(values, future.result().results):
+            if res.successful:
+                output += 'Set parameter %s successful\n' % param
+            else:
+                output += 'Set parameter %s failed: %s\n' % (param, res.reason)
+        return output, False
+
+    def impostor(self, req):
+        imp = req['impostor']
+        node = rclpy.create_node('feryr_impostor_%d' % next(self.impostor_ids))
+        responses = imp['responses'] or [req['payload']]
+        answered = []
+        done = threading.Event()
+
+        def next_payload():
+            payload = responses[len(answered) % len(responses)]
+            answered.append(payload)
+            if len(answered) >= imp['answers']:
+                done.set()
+            time.sleep(imp['delay_us'] / 1e6)
+            return payload
+
+        if imp['kind'] == 'Service':
+            srv_type = get_service(req['itf_type'])
+            node.create_service(
+                srv_type, req['itf_name'], lambda _, response: self.fill_known(response, next_payload()))
+        else:
+            act_type = get_action(req['itf_type'])
+
+            def execute(goal_handle):
+                payload = next_payload()
+                for _ in range(imp['feedback']):
+                    goal_handle.publish_feedback(self.fill_known(act_type.Feedback(), payload))
+                if goal_handle.is_cancel_requested:
+                    goal_handle.canceled()
+                elif imp['abort']:
+                    goal_handle.abort()
+                else:
+                    goal_handle.succeed()
+                return self.fill_known(act_type.Result(), payload)
+
+            ActionServer(
+                node, act_type, req['itf_name'], execute_callback=execute,
+                goal_callback=lambda _: GoalResponse.REJECT if imp['reject'] else GoalResponse.ACCEPT,
+                cancel_callback=lambda _: CancelResponse.ACCEPT)
+
+        def serve():
+            executor = MultiThreadedExecutor()
+            executor.add_node(node)
+            deadline = time.monotonic() + imp['lifetime_ms'] / 1000.0
+            while not done.is_set() and time.monotonic() < deadline:
+                executor.spin_once(timeout_sec=0.01)
+            executor.shutdown()
+            node.destroy_node()
+
+        if imp['detach']:
+            threading.Thread(target=serve, daemon=True).start()
+            return 'impostor serving %s in background\n' % req['itf_name'], False
+        serve()
+        return 'impostor answered %d requests on %s\n' % (len(answered), req['itf_name']), False
+
+    def start_clock(self):
+        with self.lock:
+            if self.clock_pub is not None:
+                return
+            self.clock_pub = self.node.create_publisher(Clock, '/clock', 10)
+        threading.Thread(target=self.run_clock, daemon=True).start()
+
+    def publish_clock(self, sim_ns):
+        # callers hold clock_lock
+        self.sim_ns = min(max(sim_ns, 0), MAX_SIM_NS)
+        msg = Clock()
+        msg.clock.sec, msg.clock.nanosec = divmod(self.sim_ns, 1000000000)
+        self.clock_pub.publish(msg)
+        return self.sim_ns
+
+    def run_clock(self):
+        last = time.monotonic()
+        while True:
+            time.sleep(CLOCK_PERIOD)
+            now = time.monotonic()
+            with self.clock_lock:
+                if not self.clock_driven:
+                    self.publish_clock(self.sim_ns + int((now - last) * 1e9))
+            last = now
+
+    def clock(self, req):
+        self.start_clock()
+        stream = req['clock'] or {'period_ms': 10, 'events': []}
+        period = stream['period_ms'] / 1000.0
+        ticks = []
+
+        def tick(sim_ns, idx):
+            with self.clock_lock:
+                ticks.append((time.time_ns(), self.publish_clock(sim_ns), idx))
+
+        with self.clock_lock:
+            self.clock_driven = True
+        try:
+            for idx, event in enumerate(stream['events']):
+                kind, args = next(iter(event.items()))
+                if kind == 'Jump':
+                    tick(self.sim_ns + args['delta_ms'] * 1000000, idx)
+                    continue
+                rate = args['rate'] if kind == 'Run' else 0.0
+                last = time.monotonic()
+                end = last + args['duration_ms'] / 1000.0
+                while last < end:
+                    time.sleep(min(period, end - last))
+                    now = time.monotonic()
+                    tick(self.sim_ns + int((now - last) * rate * 1e9), idx)
+                    last = now
+        finally:
+            # the baseline goes on from the last published time
+            with self.clock_lock:
+                self.clock_driven = False
+        lines = ''.join('clock %d %d %d\n' % t for t in ticks)
+        return 'published %d clock ticks\n%s' % (len(ticks), lines), False
+
+    def handle(self, req):
+        handlers = {
+            'Topic': self.topic,
+            'Service': self.service,
+            'Action': self.action,
+            'Param': self.param,
+            'Impostor': self.impostor,
+            # a change_state request of a managed node (see lifecycle.rs)
+            'Lifecycle': self.service,
+            'Clock': self.clock,
+        }
+        try:
+            return handlers[req['itf']](req)
+        except Exception:
+            # reported the same way the cli reports a bad input
+            return 'Failed to inject: %s' % traceback.format_exc(), False
+
+    def batch(self, calls):
+        # one executor thread serves every response while the calls wait on their futures
+        executor = MultiThreadedExecutor()
+        executor.add_node(self.node)
+        self.spinning = True
+        spinner = threading.Thread(target=executor.spin, daemon=True)
+        spinner.start()
+
+        outputs = [('', True)] * len(calls)
+        start = time.monotonic()
+
+        def run(idx, item):
+            delay = start + item['offset_us'] / 1e6 - time.monotonic()
+            if delay > 0:
+                time.sleep(delay)
+            outputs[idx] = self.handle(item['call'])
+
+        workers = [threading.Thread(target=run, args=(idx, item)) for idx, item in enumerate(calls)]
+        for worker in workers:
+            worker.start()
+        for worker in workers:
+            worker.join()
+
+        executor.remove_node(self.node)
+        executor.shutdown()
+        spinner.join()
+        self.spinning = False
+        return outputs
+
+
+def main():
+    injector = Injector()
+    if 'FERYR_SIM_CLOCK' in os.environ:
+        injector.start_clock()
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        req = json.loads(line)
+        if 'batch' in req:
+            outputs = injector.batch(req['batch'])
+            reply = {'outputs': [{'output': output, 'timeout': timeout} for output, timeout in outputs]}
+        else:
+            output, timeout = injector.handle(req)
+            reply = {'output': output, 'timeout': timeout}
+        sys.stdout.write(json.dumps(reply) + '\n')
+        sys.stdout.flush()
+    injector.node.destroy_node()
+    rclpy.shutdown()
+
+
+if __name__ == '__main__':
+    main()